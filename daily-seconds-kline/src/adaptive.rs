@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use crate::market::Market;
+
+/// Largest `limit` the klines endpoint accepts.
+pub(crate) const MAX_WINDOW_ROWS: u32 = 1000;
+const MIN_WINDOW_ROWS: u32 = 100;
+const WINDOW_STEP_ROWS: u32 = 100;
+const MAX_CONCURRENCY: usize = 8;
+const SLOW_RESPONSE: Duration = Duration::from_secs(3);
+
+/// What one response told us about the exchange's current state.
+pub(crate) struct Observation {
+    /// `X-MBX-USED-WEIGHT-1M`, if the response carried it
+    pub(crate) used_weight: Option<u32>,
+    pub(crate) latency: Duration,
+}
+
+/// Sizes request windows and the number of requests in flight from observed
+/// weight usage and latency: grows additively while there is headroom and
+/// backs off multiplicatively when the limit gets close.
+pub(crate) struct Adaptive {
+    window_rows: u32,
+    concurrency: usize,
+    /// Weight the market allows per minute
+    weight_limit: u32,
+}
+
+impl Adaptive {
+    pub(crate) fn new(market: Market) -> Self {
+        Self {
+            window_rows: 600,
+            concurrency: 2,
+            weight_limit: market.weight_limit(),
+        }
+    }
+
+    pub(crate) fn window_rows(&self) -> u32 {
+        self.window_rows
+    }
+
+    pub(crate) fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    pub(crate) fn observe(&mut self, obs: &Observation) {
+        let (window_rows, concurrency) = (self.window_rows, self.concurrency);
+        let headroom = obs
+            .used_weight
+            .map(|used| 1.0 - f64::from(used) / f64::from(self.weight_limit))
+            .unwrap_or(1.0);
+        if headroom < 0.2 {
+            // weight is charged per request, so fewer and larger requests
+            self.concurrency = (self.concurrency / 2).max(1);
+            self.window_rows = MAX_WINDOW_ROWS;
+        } else if obs.latency > SLOW_RESPONSE {
+            self.concurrency = (self.concurrency - 1).max(1);
+            self.window_rows = (self.window_rows * 3 / 4).max(MIN_WINDOW_ROWS);
+        } else {
+            self.window_rows = (self.window_rows + WINDOW_STEP_ROWS).min(MAX_WINDOW_ROWS);
+            if headroom > 0.5 {
+                self.concurrency = (self.concurrency + 1).min(MAX_CONCURRENCY);
+            }
+        }
+        if (window_rows, concurrency) != (self.window_rows, self.concurrency) {
+            tracing::debug!(
+                "window_rows: {} -> {}, concurrency: {} -> {}, used_weight: {:?}, latency: {:?}",
+                window_rows,
+                self.window_rows,
+                concurrency,
+                self.concurrency,
+                obs.used_weight,
+                obs.latency
+            );
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+
+use crate::day;
+use crate::download::{interval_ms, INTERVAL, OUT_DIR};
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::manifest::{FileEntry, Manifest};
+use crate::queue::Job;
+use crate::reader::DatasetReader;
+use crate::resample::resample_in_days;
+use crate::writer::{self, DayBatch, Durability};
+
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(clap::Args)]
+pub(crate) struct AggregateArgs {
+    /// Dataset to aggregate, with its `manifest.json`; the new files are
+    /// added to it
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    /// Interval of the files to read
+    #[arg(long, default_value = INTERVAL)]
+    interval: String,
+    /// Intervals to write, comma-separated, e.g. `1m,5m,1h`
+    #[arg(long, value_delimiter = ',', required = true)]
+    into: Vec<String>,
+    /// Symbols to aggregate, comma-separated; all of them if left out
+    #[arg(long, value_delimiter = ',')]
+    symbols: Vec<String>,
+    /// First day to aggregate, e.g. 2024-06-01
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Last day to aggregate, inclusive
+    #[arg(long)]
+    to: Option<NaiveDate>,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+pub(crate) async fn run(args: AggregateArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || aggregate(&args)).await?
+}
+
+/// Writes a daily file of each `--into` interval next to every daily file
+/// of `--interval`, keeping the originals. Days whose coarser file is
+/// already complete are skipped; an incomplete day, such as today, is
+/// aggregated again on the next run.
+fn aggregate(args: &AggregateArgs) -> Result<()> {
+    let step_ms = interval_ms(&args.interval)
+        .ok_or_else(|| anyhow!("invalid interval {:?}", args.interval))
+        .context(Failure::Config)?;
+    let targets = args
+        .into
+        .iter()
+        .map(|into| {
+            interval_ms(into)
+                .filter(|ms| *ms > step_ms && ms % step_ms == 0 && *ms <= DAY_MS)
+                .map(|ms| (into.as_str(), ms))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "can't aggregate {} into {:?}; intervals must be multiples of it, up to 1d",
+                        args.interval,
+                        into
+                    )
+                })
+                .context(Failure::Config)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut manifest = Manifest::load(&args.dir)?;
+    let reader = DatasetReader::new(&manifest);
+    let format = manifest.format();
+    // merged and split files cover other spans than the day they're keyed by
+    let files: Vec<(String, FileEntry)> = manifest
+        .files()
+        .filter(|(key, e)| {
+            e.interval == args.interval
+                && e.last_day.is_none()
+                && e.hour.is_none()
+                && (args.symbols.is_empty() || args.symbols.contains(&e.symbol))
+                && args.from.is_none_or(|from| e.day >= from)
+                && args.to.is_none_or(|to| e.day <= to)
+                && Format::of(Path::new(key)).is_some()
+        })
+        .map(|(key, entry)| (key.to_string(), entry.clone()))
+        .collect();
+
+    let mut written = 0;
+    for (key, entry) in &files {
+        let pending: Vec<(Job, i64)> = targets
+            .iter()
+            .map(|(into, ms)| {
+                let job = Job {
+                    symbol: entry.symbol.clone(),
+                    interval: into.to_string(),
+                    day: entry.day,
+                };
+                (job, *ms)
+            })
+            .filter(|(job, _)| !manifest.is_complete(job))
+            .collect();
+        if pending.is_empty() {
+            continue;
+        }
+        let rows = reader.read(key, entry)?;
+        for (job, ms) in pending {
+            let batch = DayBatch {
+                rows: resample_in_days(&rows, ms, day::day_start())
+                    .with_context(|| format!("aggregating {} into {}", key, job.interval))?,
+                job,
+                complete: entry.complete,
+            };
+            let path = writer::write_file(
+                manifest.dir(),
+                manifest.layout(),
+                manifest.style(),
+                format,
+                &batch,
+                args.durability,
+            )?;
+            manifest.record(
+                &path,
+                FileEntry {
+                    interval: batch.job.interval.clone(),
+                    resampled_from: None,
+                    aggregated_from: Some(entry.interval.clone()),
+                    archive: None,
+                    rows: batch.rows.len(),
+                    bytes: std::fs::metadata(&path)?.len(),
+                    ..entry.clone()
+                },
+                args.durability,
+            )?;
+            written += 1;
+        }
+    }
+    tracing::info!(
+        "wrote {} file(s) from {} {} file(s)",
+        written,
+        files.len(),
+        args.interval
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::day::DayStart;
+    use crate::kline::Kline;
+    use crate::market::Market;
+
+    #[test]
+    fn hours_stay_in_their_day_and_missing_ones_are_left_out() {
+        let dir = std::env::temp_dir().join(format!("kline-aggregate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut manifest = Manifest::load_for(&dir, Market::Spot, DayStart::default()).unwrap();
+        let day: NaiveDate = "2024-06-01".parse().unwrap();
+        let start_ms = DayStart::default().start_ms(day);
+        // the first hour, nothing in the second, a little of the third and
+        // the last seconds before midnight
+        let seconds = (0..3600).chain(7200..7230).chain(86_390..86_400);
+        let rows = seconds
+            .map(|s| {
+                let open_time = start_ms + s * 1000;
+                Kline::new(
+                    open_time,
+                    open_time + 999,
+                    1,
+                    ["1", "1", "1", "1", "1", "1", "1", "1", "0"],
+                )
+            })
+            .collect();
+        let job = |interval: &str| Job {
+            symbol: "ETHUSDC".to_string(),
+            interval: interval.to_string(),
+            day,
+        };
+        let batch = DayBatch {
+            job: job("1s"),
+            rows,
+            complete: true,
+        };
+        writer::write_day(&mut manifest, &batch, Durability::None).unwrap();
+        let args = AggregateArgs {
+            dir: dir.clone(),
+            interval: "1s".to_string(),
+            into: vec!["1h".to_string()],
+            symbols: Vec::new(),
+            from: None,
+            to: None,
+            durability: Durability::None,
+        };
+        aggregate(&args).unwrap();
+
+        let mut manifest = Manifest::load_for(&dir, Market::Spot, DayStart::default()).unwrap();
+        let (key, entry) = manifest
+            .files()
+            .find(|(_, e)| e.interval == "1h")
+            .map(|(key, e)| (key.to_string(), e.clone()))
+            .unwrap();
+        assert_eq!(entry.aggregated_from.as_deref(), Some("1s"));
+        assert_eq!(entry.resampled_from, None);
+        let hours = DatasetReader::new(&manifest).read(&key, &entry).unwrap();
+        let spans: Vec<(i64, i64)> = hours.iter().map(|k| (k.open_time, k.close_time)).collect();
+        let hour_ms = 3_600_000;
+        assert_eq!(
+            spans,
+            [
+                (start_ms, start_ms + hour_ms - 1),
+                (start_ms + 2 * hour_ms, start_ms + 3 * hour_ms - 1),
+                (start_ms + 23 * hour_ms, start_ms + DAY_MS - 1),
+            ]
+        );
+        assert_eq!(hours[0].volume(), "3600");
+
+        // losing the source gets it downloaded again, aggregates or not
+        let source = manifest
+            .files()
+            .find(|(_, e)| e.interval == "1s")
+            .map(|(key, _)| key.to_string())
+            .unwrap();
+        manifest.remove(&source);
+        assert!(!manifest.is_complete(&job("1s")));
+        assert!(manifest.is_complete(&job("1h")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{NaiveDate, Utc};
+use futures::stream::{self, Stream, TryStreamExt};
+
+use crate::adaptive::MAX_WINDOW_ROWS;
+use crate::day::DayStart;
+use crate::download::{interval_ms, Fetcher};
+use crate::exit::Failure;
+use crate::kline::{self, Kline, Source};
+use crate::manifest::Manifest;
+use crate::market::Market;
+use crate::queue::Job;
+use crate::writer::{self, DayBatch, Durability};
+use crate::KlineRow;
+
+/// Pages through the klines endpoint of a [`Market`] for programs using
+/// this crate as a library. Requests go through `download`'s own fetcher:
+/// they wait out rate limits and the weight limit, and failed ones are
+/// retried with backoff from a budget shared by the client's clones. A
+/// request that still fails ends the stream with its error.
+#[derive(Clone)]
+pub struct KlineClient {
+    fetcher: Arc<Fetcher>,
+    market: Market,
+    /// Where [`KlineClient::day`] cuts days
+    day_start: DayStart,
+    /// Whether rows carry where and when they were fetched
+    provenance: bool,
+}
+
+/// Receives klines a day at a time, as `download` hands them to its files.
+pub trait DaySink {
+    /// Stores the klines of `symbol` and `interval` opening on `day`, in
+    /// order; `complete` is false for a day that isn't over yet.
+    fn write_day(
+        &mut self,
+        symbol: &str,
+        interval: &str,
+        day: NaiveDate,
+        rows: Vec<KlineRow>,
+        complete: bool,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// A dataset directory as `download` writes it: daily files in the
+/// dataset's layout and format, listed in its `manifest.json`.
+pub struct FileSink {
+    /// Lent to the blocking pool during a write
+    manifest: Option<Manifest>,
+    durability: Durability,
+}
+
+impl KlineClient {
+    pub fn new(market: Market, day_start: DayStart) -> Result<Self> {
+        Ok(Self {
+            fetcher: Arc::new(Fetcher::for_market(market)?),
+            market,
+            day_start,
+            provenance: false,
+        })
+    }
+
+    /// Fills in each row's `exchange`, `source` and `ingest_time`, which
+    /// are left out otherwise.
+    pub fn with_provenance(mut self) -> Self {
+        self.provenance = true;
+        self
+    }
+
+    /// Klines of `symbol` opening in `[start_ms, end_ms]`, in order, one
+    /// request per page of up to 1000.
+    pub fn klines(
+        &self,
+        symbol: &str,
+        interval: &str,
+        start_ms: i64,
+        end_ms: i64,
+    ) -> Result<impl Stream<Item = Result<KlineRow>> + '_> {
+        let step_ms = interval_ms(interval)
+            .ok_or_else(|| anyhow!("unknown interval {:?}", interval))
+            .context(Failure::Config)?;
+        // candles still open aren't final
+        let end_ms = end_ms.min(Utc::now().timestamp_millis() - step_ms);
+        let url = format!(
+            "{}/klines?limit={}&symbol={}&interval={}",
+            self.market.api(),
+            MAX_WINDOW_ROWS,
+            symbol,
+            interval
+        );
+        let pages = stream::try_unfold(start_ms, move |start_ms| {
+            let url = url.clone();
+            async move {
+                if start_ms > end_ms {
+                    return Ok(None);
+                }
+                let page_end_ms = (start_ms + i64::from(MAX_WINDOW_ROWS) * step_ms - 1).min(end_ms);
+                let url = format!("{}&startTime={}&endTime={}", url, start_ms, page_end_ms);
+                let body = self.fetcher.get(&url).await?;
+                let mut rows = kline::parse(&body).context(Failure::Validation)?;
+                rows.retain(|r| (start_ms..=page_end_ms).contains(&r.open_time));
+                if self.provenance {
+                    kline::stamp(&mut rows, Source::Rest);
+                }
+                let rows: Vec<Result<KlineRow>> = rows
+                    .iter()
+                    .map(|r| KlineRow::try_from(r).context(Failure::Validation))
+                    .collect();
+                anyhow::Ok(Some((stream::iter(rows), page_end_ms + 1)))
+            }
+        });
+        Ok(pages.try_flatten())
+    }
+
+    /// The klines opening on `day`, up to the last closed one for today.
+    pub async fn day(&self, symbol: &str, interval: &str, day: NaiveDate) -> Result<Vec<KlineRow>> {
+        let start_ms = self.day_start.start_ms(day);
+        self.klines(
+            symbol,
+            interval,
+            start_ms,
+            self.day_start.next_day_ms(start_ms) - 1,
+        )?
+        .try_collect()
+        .await
+    }
+
+    /// Fetches `from` to `to`, inclusive, into `sink` a day at a time.
+    pub async fn download(
+        &self,
+        symbol: &str,
+        interval: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+        sink: &mut impl DaySink,
+    ) -> Result<()> {
+        for day in from.iter_days().take_while(|day| *day <= to) {
+            let rows = self.day(symbol, interval, day).await?;
+            let end_ms = self.day_start.next_day_ms(self.day_start.start_ms(day));
+            let complete = end_ms <= Utc::now().timestamp_millis();
+            sink.write_day(symbol, interval, day, rows, complete)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl FileSink {
+    /// Writes into `dir`, starting a dataset of `market` with days cut at
+    /// `day_start` there if there is none; an existing one must match both.
+    pub fn open(dir: &Path, market: Market, day_start: DayStart) -> Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            manifest: Some(Manifest::load_for(dir, market, day_start)?),
+            durability: Durability::default(),
+        })
+    }
+}
+
+impl DaySink for FileSink {
+    async fn write_day(
+        &mut self,
+        symbol: &str,
+        interval: &str,
+        day: NaiveDate,
+        rows: Vec<KlineRow>,
+        complete: bool,
+    ) -> Result<()> {
+        let batch = DayBatch {
+            job: Job {
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+                day,
+            },
+            rows: rows.iter().map(Kline::from).collect(),
+            complete,
+        };
+        let mut manifest = self
+            .manifest
+            .take()
+            .ok_or_else(|| anyhow!("an earlier write to this sink failed"))?;
+        let durability = self.durability;
+        let manifest = tokio::task::spawn_blocking(move || {
+            writer::write_day(&mut manifest, &batch, durability)?;
+            anyhow::Ok(manifest)
+        })
+        .await??;
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Utc};
+
+use crate::download::OUT_DIR;
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::manifest::{ArchiveMember, FileEntry, Manifest};
+use crate::prune::Age;
+use crate::reader::DatasetReader;
+use crate::writer::{sync_dir, Durability};
+
+const ARCHIVE_DIR: &str = "archive";
+const ZSTD_LEVEL: i32 = 9;
+const TAR_BLOCK: usize = 512;
+
+#[derive(clap::Args)]
+pub(crate) struct ArchiveArgs {
+    /// Dataset to archive, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    /// Archive complete files whose last day is older than this, e.g. `90d`
+    /// or `6mo`
+    #[arg(long)]
+    older_than: Age,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+pub(crate) async fn run(args: ArchiveArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || archive(&args)).await?
+}
+
+/// Bundles old files into `archive/{symbol}-{interval}-{YYYY-MM}.tar.zst`,
+/// one per month. Every file gets its own zstd frame, so the manifest can
+/// point at it for `DatasetReader` while the bundle still extracts with
+/// `tar --zstd -xf`. A month archived twice gets a second bundle.
+fn archive(args: &ArchiveArgs) -> Result<()> {
+    let cutoff = args.older_than.cutoff(Utc::now().date_naive());
+    let mut manifest = Manifest::load(&args.dir)?;
+    let mut groups: BTreeMap<(String, String, String), Vec<(String, FileEntry)>> = BTreeMap::new();
+    for (key, entry) in manifest.files() {
+        let last_day = entry.last_day.unwrap_or(entry.day);
+        if entry.archive.is_some()
+            || !entry.complete
+            || last_day >= cutoff
+            || (last_day.year(), last_day.month()) != (entry.day.year(), entry.day.month())
+            || Format::of(Path::new(key)).is_none()
+        {
+            continue;
+        }
+        groups
+            .entry((
+                entry.symbol.clone(),
+                entry.interval.clone(),
+                entry.day.format("%Y-%m").to_string(),
+            ))
+            .or_default()
+            .push((key.to_string(), entry.clone()));
+    }
+
+    std::fs::create_dir_all(args.dir.join(ARCHIVE_DIR))?;
+    let reader = DatasetReader::new(&manifest);
+    let mut archived = 0;
+    for ((symbol, interval, month), files) in groups {
+        let name = archive_name(&args.dir, &symbol, &interval, &month);
+        let path = args.dir.join(&name);
+        let tmp = path.with_extension("zst.tmp");
+        let members = write_archive(&tmp, &name, &args.dir, &files, args.durability)?;
+        std::fs::rename(&tmp, &path)?;
+        if args.durability == Durability::Fsync {
+            sync_dir(&args.dir.join(ARCHIVE_DIR))?;
+        }
+
+        let mut bytes = 0;
+        for ((key, entry), member) in files.iter().zip(members) {
+            let entry = FileEntry {
+                archive: Some(member),
+                ..entry.clone()
+            };
+            let rows = reader.read(key, &entry)?.len();
+            if rows != entry.rows {
+                std::fs::remove_file(&path)?;
+                return Err(anyhow!(
+                    "{} read back {} rows from {:?}, the manifest recorded {}",
+                    key,
+                    rows,
+                    path,
+                    entry.rows
+                )
+                .context(Failure::Validation));
+            }
+            bytes += entry.bytes;
+            manifest.insert(&args.dir.join(key), entry);
+        }
+        manifest.save(args.durability)?;
+        for (key, _) in &files {
+            std::fs::remove_file(args.dir.join(key))?;
+        }
+        archived += files.len();
+        tracing::info!(
+            "archived {} file(s) into {:?}, {:.1} -> {:.1} MiB",
+            files.len(),
+            path,
+            bytes as f64 / (1 << 20) as f64,
+            std::fs::metadata(&path)?.len() as f64 / (1 << 20) as f64
+        );
+    }
+    tracing::info!("archived {} file(s) older than {}", archived, cutoff);
+    Ok(())
+}
+
+/// `archive/{symbol}-{interval}-{month}.tar.zst`, or `.2.tar.zst` and so on
+/// if that already exists.
+fn archive_name(dir: &Path, symbol: &str, interval: &str, month: &str) -> String {
+    let stem = format!("{}/{}-{}-{}", ARCHIVE_DIR, symbol, interval, month);
+    let mut name = format!("{}.tar.zst", stem);
+    let mut n = 1;
+    while dir.join(&name).exists() {
+        n += 1;
+        name = format!("{}.{}.tar.zst", stem, n);
+    }
+    name
+}
+
+fn write_archive(
+    path: &Path,
+    name: &str,
+    dir: &Path,
+    files: &[(String, FileEntry)],
+    durability: Durability,
+) -> Result<Vec<ArchiveMember>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut members = Vec::with_capacity(files.len());
+    let mut offset = 0;
+    for (key, _) in files {
+        let source = dir.join(key);
+        let data = std::fs::read(&source)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_path(key)?;
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            std::fs::metadata(&source)?
+                .modified()?
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs(),
+        );
+        header.set_cksum();
+        let mut block = Vec::with_capacity(TAR_BLOCK + data.len().next_multiple_of(TAR_BLOCK));
+        block.extend_from_slice(header.as_bytes());
+        block.extend_from_slice(&data);
+        block.resize(block.len().next_multiple_of(TAR_BLOCK), 0);
+
+        let frame = zstd::bulk::compress(&block, ZSTD_LEVEL)?;
+        out.write_all(&frame)?;
+        members.push(ArchiveMember {
+            file: name.to_string(),
+            offset,
+            len: frame.len() as u64,
+        });
+        offset += frame.len() as u64;
+    }
+    // tar's end-of-archive marker, two zero blocks
+    out.write_all(&zstd::bulk::compress(&[0; 2 * TAR_BLOCK], ZSTD_LEVEL)?)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    if durability == Durability::Fsync {
+        file.sync_all()?;
+    }
+    Ok(members)
+}
@@ -0,0 +1,284 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+use crate::format::{Format, Style};
+use crate::kline::{self, KlineRecord};
+use crate::layout::Layout;
+use crate::queue::Job;
+use crate::writer::{self, DayBatch, Durability};
+
+#[derive(clap::Args)]
+pub(crate) struct BenchArgs {
+    /// Directory of recorded `/api/v3/klines` JSON responses (`*.json`)
+    #[arg(long)]
+    fixtures: PathBuf,
+    /// How many times every fixture is replayed
+    #[arg(long, default_value_t = 20)]
+    iterations: usize,
+    /// Save this run's results as JSON
+    #[arg(long)]
+    save: Option<PathBuf>,
+    /// Results of a previous run (from `--save`) to compare against
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct BenchReport {
+    version: String,
+    requests: usize,
+    rows: usize,
+    fetch_secs: f64,
+    parse_secs: f64,
+    /// Missing in reports from before the compact rows
+    #[serde(default)]
+    owned_parse_secs: Option<f64>,
+    formats: Vec<FormatTiming>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FormatTiming {
+    format: String,
+    write_secs: f64,
+    bytes: u64,
+}
+
+impl BenchReport {
+    fn requests_per_sec(&self) -> f64 {
+        self.requests as f64 / self.fetch_secs
+    }
+
+    fn rows_per_sec(&self) -> f64 {
+        let write_secs: f64 = self.formats.iter().map(|f| f.write_secs).sum();
+        self.rows as f64 / (self.fetch_secs + self.parse_secs + write_secs)
+    }
+
+    fn per_row_nanos(&self, secs: f64) -> f64 {
+        secs * 1e9 / self.rows as f64
+    }
+}
+
+pub(crate) async fn run(args: BenchArgs) -> Result<()> {
+    let fixtures = load_fixtures(&args.fixtures)?;
+    let addr = serve_fixtures(fixtures.clone()).await?;
+    let client = crate::client::build()?;
+
+    let mut bodies = Vec::with_capacity(fixtures.len() * args.iterations);
+    let started = Instant::now();
+    for _ in 0..args.iterations {
+        for idx in 0..fixtures.len() {
+            let url = format!("http://{}/{}", addr, idx);
+            bodies.push(
+                client
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?,
+            );
+        }
+    }
+    let fetch = started.elapsed();
+
+    let started = Instant::now();
+    let mut rows = Vec::new();
+    for body in &bodies {
+        rows.extend(kline::parse(body)?);
+    }
+    let parse = started.elapsed();
+
+    // the owned serde model, for comparison with the compact rows
+    let started = Instant::now();
+    for body in &bodies {
+        drop(serde_json::from_slice::<Vec<KlineRecord>>(body)?);
+    }
+    let owned_parse = started.elapsed();
+
+    let dir = std::env::temp_dir().join(format!("kline-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let batch = DayBatch {
+        job: Job {
+            symbol: "BENCH".to_string(),
+            interval: "1s".to_string(),
+            day: NaiveDate::default(),
+        },
+        rows,
+        complete: true,
+    };
+    let mut formats = Vec::new();
+    for format in [Format::Csv, Format::CsvGz, Format::Parquet] {
+        let started = Instant::now();
+        let path = writer::write_file(
+            &dir,
+            Layout::FLAT,
+            Style::default(),
+            format,
+            &batch,
+            Durability::Flush,
+        )?;
+        formats.push(FormatTiming {
+            format: format.extension().to_string(),
+            write_secs: started.elapsed().as_secs_f64(),
+            bytes: std::fs::metadata(&path)?.len(),
+        });
+    }
+    std::fs::remove_dir_all(&dir)?;
+
+    let report = BenchReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        requests: bodies.len(),
+        rows: batch.rows.len(),
+        fetch_secs: fetch.as_secs_f64(),
+        parse_secs: parse.as_secs_f64(),
+        owned_parse_secs: Some(owned_parse.as_secs_f64()),
+        formats,
+    };
+    let baseline = match &args.baseline {
+        Some(path) => Some(
+            serde_json::from_slice::<BenchReport>(&std::fs::read(path)?)
+                .with_context(|| format!("invalid baseline {:?}", path))?,
+        ),
+        None => None,
+    };
+    print_table(&report, baseline.as_ref());
+    if let Some(path) = &args.save {
+        std::fs::write(path, serde_json::to_vec_pretty(&report)?)?;
+    }
+    Ok(())
+}
+
+fn load_fixtures(dir: &Path) -> Result<Arc<Vec<Vec<u8>>>> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("reading fixtures {:?}", dir))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+    if paths.is_empty() {
+        return Err(anyhow!("no *.json fixtures in {:?}", dir));
+    }
+    let fixtures = paths
+        .iter()
+        .map(std::fs::read)
+        .collect::<std::io::Result<Vec<_>>>()?;
+    Ok(Arc::new(fixtures))
+}
+
+/// Serves fixture `n` at `/n` over keep-alive HTTP/1.1, so fetch timings
+/// include the same client and connection handling as real downloads.
+async fn serve_fixtures(fixtures: Arc<Vec<Vec<u8>>>) -> Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let fixtures = fixtures.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut lines = BufReader::new(read).lines();
+                let mut path = None;
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.is_empty() {
+                        let body = path
+                            .take()
+                            .and_then(|p: String| p.trim_start_matches('/').parse::<usize>().ok())
+                            .and_then(|idx| fixtures.get(idx));
+                        let Some(body) = body else {
+                            let _ = write
+                                .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n")
+                                .await;
+                            continue;
+                        };
+                        let head = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+                            body.len()
+                        );
+                        if write.write_all(head.as_bytes()).await.is_err()
+                            || write.write_all(body).await.is_err()
+                        {
+                            break;
+                        }
+                    } else if path.is_none() {
+                        path = line.split(' ').nth(1).map(str::to_string);
+                    }
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
+
+fn print_table(report: &BenchReport, baseline: Option<&BenchReport>) {
+    let mut rows = vec![
+        (
+            "requests/sec".to_string(),
+            report.requests_per_sec(),
+            baseline.map(BenchReport::requests_per_sec),
+        ),
+        (
+            "rows/sec".to_string(),
+            report.rows_per_sec(),
+            baseline.map(BenchReport::rows_per_sec),
+        ),
+        (
+            "parse ns/row".to_string(),
+            report.per_row_nanos(report.parse_secs),
+            baseline.map(|b| b.per_row_nanos(b.parse_secs)),
+        ),
+    ];
+    if let Some(secs) = report.owned_parse_secs {
+        rows.push((
+            "parse owned ns/row".to_string(),
+            report.per_row_nanos(secs),
+            baseline.and_then(|b| b.owned_parse_secs.map(|secs| b.per_row_nanos(secs))),
+        ));
+    }
+    for format in &report.formats {
+        let previous = baseline.and_then(|b| {
+            b.formats
+                .iter()
+                .find(|f| f.format == format.format)
+                .map(|f| b.per_row_nanos(f.write_secs))
+        });
+        rows.push((
+            format!("write {} ns/row", format.format),
+            report.per_row_nanos(format.write_secs),
+            previous,
+        ));
+    }
+
+    println!(
+        "{} requests, {} rows, {:?} fetch",
+        report.requests,
+        report.rows,
+        Duration::from_secs_f64(report.fetch_secs)
+    );
+    match baseline {
+        Some(b) => println!(
+            "{:<20} {:>14} {:>14} {:>9}",
+            "metric",
+            format!("v{}", report.version),
+            format!("v{}", b.version),
+            "change"
+        ),
+        None => println!("{:<20} {:>14}", "metric", format!("v{}", report.version)),
+    }
+    for (name, current, previous) in rows {
+        match previous {
+            Some(previous) => println!(
+                "{:<20} {:>14.2} {:>14.2} {:>+8.1}%",
+                name,
+                current,
+                previous,
+                (current - previous) / previous * 100.0
+            ),
+            None => println!("{:<20} {:>14.2}", name, current),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+use crate::day::DayStart;
+use crate::kline::Kline;
+
+/// Sorts fetched candles into the days they open on, as a [`DayStart`]
+/// cuts them. Every candle lands in exactly one day, once: a response
+/// spanning a day boundary is split at it, and a candle fetched again
+/// replaces the copy already held.
+pub(crate) struct DayBucketer {
+    day_start: DayStart,
+    /// Candles of each day not yet taken, by open time
+    days: BTreeMap<NaiveDate, BTreeMap<i64, Kline>>,
+}
+
+impl DayBucketer {
+    pub(crate) fn new(day_start: DayStart) -> Self {
+        Self {
+            day_start,
+            days: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, rows: impl IntoIterator<Item = Kline>) {
+        for row in rows {
+            let day = self.day_start.day_of(row.open_time);
+            self.days.entry(day).or_default().insert(row.open_time, row);
+        }
+    }
+
+    /// Candles held, of all days.
+    pub(crate) fn len(&self) -> usize {
+        self.days.values().map(BTreeMap::len).sum()
+    }
+
+    /// Takes the candles of `day`, in order; none if nothing of it came, as
+    /// across an exchange outage.
+    pub(crate) fn take(&mut self, day: NaiveDate) -> Vec<Kline> {
+        self.days
+            .remove(&day)
+            .map(|rows| rows.into_values().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3_600_000;
+
+    fn kline(open_time: i64, close: &str) -> Kline {
+        Kline::new(
+            open_time,
+            open_time + HOUR - 1,
+            1,
+            ["1", "1", "1", close, "1", "1", "1", "1", "0"],
+        )
+    }
+
+    fn ms(rfc3339: &str) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    fn day(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn opens(rows: &[Kline]) -> Vec<i64> {
+        rows.iter().map(|r| r.open_time).collect()
+    }
+
+    #[test]
+    fn response_spanning_midnight_is_split_at_it() {
+        let mut bucketer = DayBucketer::new(DayStart::default());
+        let midnight = ms("2024-06-02T00:00:00Z");
+        bucketer.push((-2..2).map(|h| kline(midnight + h * HOUR, "1")));
+        assert_eq!(bucketer.len(), 4);
+        let first = bucketer.take(day("2024-06-01"));
+        assert_eq!(opens(&first), [midnight - 2 * HOUR, midnight - HOUR]);
+        let second = bucketer.take(day("2024-06-02"));
+        assert_eq!(opens(&second), [midnight, midnight + HOUR]);
+        assert_eq!(bucketer.len(), 0);
+    }
+
+    #[test]
+    fn candles_fetched_twice_are_kept_once() {
+        let mut bucketer = DayBucketer::new(DayStart::default());
+        let start = ms("2024-06-01T10:00:00Z");
+        bucketer.push([kline(start + HOUR, "1"), kline(start, "1")]);
+        // an overlapping response, with the later copy of the first hour
+        bucketer.push([kline(start, "2"), kline(start + 2 * HOUR, "1")]);
+        let rows = bucketer.take(day("2024-06-01"));
+        assert_eq!(opens(&rows), [start, start + HOUR, start + 2 * HOUR]);
+        assert_eq!(rows[0].close(), "2");
+    }
+
+    #[test]
+    fn empty_windows_leave_empty_days() {
+        let mut bucketer = DayBucketer::new(DayStart::default());
+        bucketer.push(Vec::new());
+        assert_eq!(bucketer.len(), 0);
+        assert!(bucketer.take(day("2024-06-01")).is_empty());
+        // an outage across midnight: the days either side keep their own
+        bucketer.push([
+            kline(ms("2024-06-01T21:00:00Z"), "1"),
+            kline(ms("2024-06-03T02:00:00Z"), "1"),
+        ]);
+        assert!(bucketer.take(day("2024-06-02")).is_empty());
+        assert_eq!(bucketer.take(day("2024-06-01")).len(), 1);
+        assert_eq!(bucketer.take(day("2024-06-03")).len(), 1);
+    }
+
+    #[test]
+    fn days_follow_a_utc_offset() {
+        let mut bucketer = DayBucketer::new("00:00@+05:30".parse().unwrap());
+        bucketer.push([
+            kline(ms("2024-06-01T18:29:59Z"), "1"),
+            kline(ms("2024-06-01T18:30:00Z"), "1"),
+        ]);
+        assert_eq!(
+            opens(&bucketer.take(day("2024-06-01"))),
+            [ms("2024-06-01T18:29:59Z")]
+        );
+        assert_eq!(
+            opens(&bucketer.take(day("2024-06-02"))),
+            [ms("2024-06-01T18:30:00Z")]
+        );
+    }
+
+    #[test]
+    fn days_across_dst_changes_hold_23_and_25_hours() {
+        let mut bucketer = DayBucketer::new("00:00@Europe/London".parse().unwrap());
+        // every hour of both changeover weekends in 2024
+        for (from, to) in [
+            ("2024-03-30T23:00:00Z", "2024-04-01T23:00:00Z"),
+            ("2024-10-26T22:00:00Z", "2024-10-28T00:00:00Z"),
+        ] {
+            bucketer.push(
+                (ms(from)..ms(to))
+                    .step_by(HOUR as usize)
+                    .map(|t| kline(t, "1")),
+            );
+        }
+        assert_eq!(bucketer.take(day("2024-03-30")).len(), 1);
+        assert_eq!(bucketer.take(day("2024-03-31")).len(), 23);
+        assert_eq!(bucketer.take(day("2024-04-01")).len(), 24);
+        assert_eq!(bucketer.take(day("2024-10-27")).len(), 25);
+        assert_eq!(bucketer.take(day("2024-10-26")).len(), 1);
+    }
+}
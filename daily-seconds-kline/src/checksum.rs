@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::download::OUT_DIR;
+use crate::exit::Failure;
+use crate::layout::data_files;
+use crate::manifest::Manifest;
+use crate::writer::{sync_dir, Durability};
+
+const CHECKSUM_FILE: &str = "checksums.json";
+
+#[derive(clap::Args)]
+pub(crate) struct ChecksumArgs {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(clap::Subcommand)]
+enum Action {
+    /// Hash every file in the manifest into `checksums.json`
+    Generate {
+        #[command(flatten)]
+        dataset: Dataset,
+        #[arg(long, value_enum, default_value_t)]
+        durability: Durability,
+    },
+    /// Check every file against `checksums.json`
+    Verify {
+        #[command(flatten)]
+        dataset: Dataset,
+    },
+}
+
+#[derive(clap::Args)]
+struct Dataset {
+    /// Dataset to hash, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    /// Secret the digests are signed with (HMAC-SHA256); without it
+    /// `checksums.json` only guards against corruption, not tampering
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+}
+
+/// SHA-256 of every file, by its path relative to the dataset, and an
+/// HMAC-SHA256 over the serialized `files` when signed.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Checksums {
+    algorithm: String,
+    files: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+pub(crate) async fn run(args: ChecksumArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || match args.action {
+        Action::Generate {
+            dataset,
+            durability,
+        } => generate(&dataset, durability),
+        Action::Verify { dataset } => verify(&dataset),
+    })
+    .await?
+}
+
+fn generate(dataset: &Dataset, durability: Durability) -> Result<()> {
+    let key = dataset.key()?;
+    let manifest = Manifest::load(&dataset.dir)?;
+    let mut files = BTreeMap::new();
+    for (key, entry) in manifest.files() {
+        // an archived file is covered by its archive's digest
+        let key = entry.archive.as_ref().map_or(key, |m| m.file.as_str());
+        if !files.contains_key(key) {
+            files.insert(key.to_string(), sha256(&dataset.dir.join(key))?);
+        }
+    }
+    let signature = key.as_deref().map(|k| sign(k, &files)).transpose()?;
+    let checksums = Checksums {
+        algorithm: "sha256".to_string(),
+        files,
+        signature,
+    };
+
+    let path = dataset.dir.join(CHECKSUM_FILE);
+    let tmp = path.with_extension("json.tmp");
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(&serde_json::to_vec_pretty(&checksums)?)?;
+    if durability == Durability::Fsync {
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp, &path)?;
+    if durability == Durability::Fsync {
+        sync_dir(&dataset.dir)?;
+    }
+    tracing::info!(
+        "hashed {} file(s) into {:?}{}",
+        checksums.files.len(),
+        path,
+        if key.is_some() { ", signed" } else { "" }
+    );
+    Ok(())
+}
+
+/// Fails if the signature doesn't match or a file is missing or changed.
+/// Files on disk that aren't listed are only reported.
+fn verify(dataset: &Dataset) -> Result<()> {
+    let key = dataset.key()?;
+    let path = dataset.dir.join(CHECKSUM_FILE);
+    let data = std::fs::read(&path).with_context(|| format!("reading {:?}", path))?;
+    let checksums: Checksums =
+        serde_json::from_slice(&data).with_context(|| format!("invalid {:?}", path))?;
+    if checksums.algorithm != "sha256" {
+        return Err(anyhow!("unsupported algorithm {:?}", checksums.algorithm));
+    }
+    match (&key, &checksums.signature) {
+        (Some(key), Some(signature)) => {
+            let mut mac = mac(key)?;
+            mac.update(&serde_json::to_vec(&checksums.files)?);
+            mac.verify_slice(&hex::decode(signature)?)
+                .map_err(|_| anyhow!("{:?} isn't signed with this key", path))
+                .context(Failure::Validation)?;
+        }
+        (Some(_), None) => {
+            return Err(anyhow!("{:?} isn't signed", path).context(Failure::Validation));
+        }
+        (None, Some(_)) => tracing::warn!("{:?} is signed, pass --key-file to check it", path),
+        (None, None) => {}
+    }
+
+    let mut bad = 0;
+    for (key, digest) in &checksums.files {
+        let file = dataset.dir.join(key);
+        match sha256(&file) {
+            Ok(actual) if actual == *digest => {}
+            Ok(_) => {
+                tracing::error!("{:?} has changed", file);
+                bad += 1;
+            }
+            Err(e) => {
+                tracing::error!("{:?}: {:#}", file, e);
+                bad += 1;
+            }
+        }
+    }
+    for file in data_files(&dataset.dir)? {
+        let listed = file
+            .strip_prefix(&dataset.dir)
+            .is_ok_and(|key| checksums.files.contains_key(&*key.to_string_lossy()));
+        if !listed {
+            tracing::warn!("{:?} isn't listed in {}", file, CHECKSUM_FILE);
+        }
+    }
+    if bad > 0 {
+        return Err(anyhow!(
+            "{} of {} file(s) failed verification",
+            bad,
+            checksums.files.len()
+        )
+        .context(Failure::Validation));
+    }
+    tracing::info!("verified {} file(s)", checksums.files.len());
+    Ok(())
+}
+
+impl Dataset {
+    fn key(&self) -> Result<Option<Vec<u8>>> {
+        let Some(path) = &self.key_file else {
+            return Ok(None);
+        };
+        let key = std::fs::read(path)
+            .with_context(|| format!("reading {:?}", path))
+            .context(Failure::Config)?;
+        let key = key.trim_ascii().to_vec();
+        if key.is_empty() {
+            return Err(anyhow!("{:?} is empty", path).context(Failure::Config));
+        }
+        Ok(Some(key))
+    }
+}
+
+fn mac(key: &[u8]) -> Result<Hmac<Sha256>> {
+    Hmac::<Sha256>::new_from_slice(key).map_err(|e| anyhow!("invalid key: {}", e))
+}
+
+fn sign(key: &[u8], files: &BTreeMap<String, String>) -> Result<String> {
+    let mut mac = mac(key)?;
+    mac.update(&serde_json::to_vec(files)?);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("opening {:?}", path))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
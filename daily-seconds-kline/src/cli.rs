@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::exit::{self, Failure};
+use crate::market::{self, Market};
+use crate::{
+    aggregate, archive, bench, checksum, convert, day, diff, download, downsample, export, gaps,
+    inspect, load, merge, prune, report, split, watch,
+};
+
+#[derive(clap::Args)]
+struct LogArgs {
+    /// Also write logs to this file, rotated by `--log-rotation`
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+    /// How often the log file rolls over
+    #[arg(long, global = true, value_enum, default_value_t = LogRotation::Daily)]
+    log_rotation: LogRotation,
+    /// Rotated log files to keep, older ones are deleted
+    #[arg(long, global = true, default_value_t = 7)]
+    log_max_files: usize,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+/// The returned guard flushes the log file when dropped, keep it alive
+/// until exit.
+fn init_log(args: &LogArgs) -> Result<Option<WorkerGuard>> {
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_thread_ids(true)
+        .with_thread_names(true);
+    let (file, guard) = match &args.log_file {
+        Some(path) => {
+            let rotation = match args.log_rotation {
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never => Rotation::NEVER,
+            };
+            let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+            let appender = RollingFileAppender::builder()
+                .rotation(rotation)
+                .filename_prefix(path.file_name().unwrap_or_default().to_string_lossy())
+                .max_log_files(args.log_max_files)
+                .build(dir.unwrap_or(Path::new(".")))?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_thread_ids(true)
+                .with_thread_names(true);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(stderr)
+        .with(file)
+        .init();
+    Ok(guard)
+}
+
+#[derive(Parser)]
+#[command(version, about, after_help = exit::HELP)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    log: LogArgs,
+    /// When each day begins, in UTC unless a zone is given, e.g. `08:00` or
+    /// `17:00@America/New_York`; a dataset keeps the one it was started with
+    #[arg(long, global = true, default_value_t)]
+    day_start: day::DayStart,
+    /// Zone trading days are aligned to, a UTC offset such as `+08:00` or a
+    /// zone name; `--day-start` is then wall-clock time there
+    #[arg(long, global = true)]
+    align_tz: Option<day::Zone>,
+    /// Market to download from; a dataset keeps the one it was started with
+    #[arg(long, global = true, value_enum, default_value_t)]
+    market: Market,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Download klines into daily CSV files (default)
+    Download(download::DownloadArgs),
+    /// Work through jobs that `download` put in a shared queue; writes pause
+    /// while disk space is low
+    Worker(download::WorkerArgs),
+    /// Rewrite a dataset's daily files in another format
+    Convert(convert::ConvertArgs),
+    /// Merge complete daily files into monthly or quarterly ones
+    Merge(merge::MergeArgs),
+    /// Split complete daily files into hourly ones
+    Split(split::SplitArgs),
+    /// Replace old 1s files with coarser resampled ones
+    Downsample(downsample::DownsampleArgs),
+    /// Add files of coarser intervals resampled from the 1s ones
+    Aggregate(aggregate::AggregateArgs),
+    /// Report missing days and candles in a range as JSON
+    Gaps(gaps::GapsArgs),
+    /// Compare two datasets row by row
+    Diff(diff::DiffArgs),
+    /// Print the first or last rows of a file or directory, with totals
+    Inspect(inspect::InspectArgs),
+    /// Generate or verify a (signed) list of file digests
+    Checksum(checksum::ChecksumArgs),
+    /// Delete or archive files older than a per-interval age
+    Prune(prune::PruneArgs),
+    /// Bundle old files into monthly tar.zst archives
+    Archive(archive::ArchiveArgs),
+    /// Import downloaded files into a database
+    Load(load::LoadArgs),
+    /// Write a few days into a spreadsheet
+    Export(export::ExportArgs),
+    /// Add daily files dropped into a directory to a dataset as they arrive
+    Watch(watch::WatchArgs),
+    /// Measure throughput against recorded klines responses
+    Bench(bench::BenchArgs),
+}
+
+/// Parses the command line and runs the command, the whole of the
+/// `daily-seconds-kline` binary.
+pub async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let _log_guard = match init_log(&cli.log).context(Failure::Config) {
+        Ok(guard) => guard,
+        Err(e) => return exit(e),
+    };
+    let _report_guard = report::init();
+    let day_start = match cli.align_tz {
+        Some(zone) => match cli.day_start.aligned_to(zone).context(Failure::Config) {
+            Ok(day_start) => day_start,
+            Err(e) => return exit(e),
+        },
+        None => cli.day_start,
+    };
+    day::init(day_start);
+    market::init(cli.market);
+    let command = cli
+        .command
+        .unwrap_or_else(|| Command::Download(download::DownloadArgs::parse_from(["download"])));
+    let result = match command {
+        Command::Download(args) => download::run(args).await,
+        Command::Worker(args) => download::run_worker(args).await,
+        Command::Convert(args) => convert::run(args).await,
+        Command::Merge(args) => merge::run(args).await,
+        Command::Split(args) => split::run(args).await,
+        Command::Downsample(args) => downsample::run(args).await,
+        Command::Aggregate(args) => aggregate::run(args).await,
+        Command::Gaps(args) => gaps::run(args).await,
+        Command::Diff(args) => diff::run(args).await,
+        Command::Inspect(args) => inspect::run(args).await,
+        Command::Checksum(args) => checksum::run(args).await,
+        Command::Prune(args) => prune::run(args).await,
+        Command::Archive(args) => archive::run(args).await,
+        Command::Load(args) => load::run(args).await,
+        Command::Export(args) => export::run(args).await,
+        Command::Watch(args) => watch::run(args).await,
+        Command::Bench(args) => bench::run(args).await,
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report::fatal(&e);
+            exit(e)
+        }
+    }
+}
+
+fn exit(e: anyhow::Error) -> ExitCode {
+    eprintln!("Error: {:?}", e);
+    ExitCode::from(Failure::exit_code(&e))
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the client shared by every request. Over TLS it negotiates HTTP/2
+/// via ALPN, so concurrent chunk requests are multiplexed over a single
+/// connection per host instead of paying a handshake each.
+pub(crate) fn build() -> Result<reqwest::Client> {
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .http2_adaptive_window(true)
+        .http2_keep_alive_interval(Duration::from_secs(30))
+        .http2_keep_alive_while_idle(true)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .build()?;
+    Ok(client)
+}
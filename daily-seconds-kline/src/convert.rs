@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::download::OUT_DIR;
+use crate::exit::Failure;
+use crate::format::{Format, StyleArgs};
+use crate::manifest::{FileEntry, Manifest};
+use crate::writer::{sync_dir, Durability};
+
+#[derive(clap::Args)]
+pub(crate) struct ConvertArgs {
+    /// Dataset to convert, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    #[arg(long, value_enum)]
+    from: Format,
+    #[arg(long, value_enum)]
+    to: Format,
+    /// Write the converted files here instead of replacing the originals
+    #[arg(long)]
+    out: Option<PathBuf>,
+    /// Write values in another style; only into `--out`
+    #[command(flatten)]
+    style: StyleArgs,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+pub(crate) async fn run(args: ConvertArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || convert(&args)).await?
+}
+
+/// Rewrites every manifest entry in `from` format, keeping its relative path
+/// apart from the extension. A file only replaces its original once the
+/// converted copy reads back with the row count the manifest recorded.
+fn convert(args: &ConvertArgs) -> Result<()> {
+    if args.from == args.to {
+        return Err(anyhow!("--from and --to are both {:?}", args.from).context(Failure::Config));
+    }
+    let mut source = Manifest::load(&args.dir)?;
+    let mut target = match &args.out {
+        Some(out) => {
+            std::fs::create_dir_all(out)?;
+            let mut target = Manifest::load(out)?;
+            target.set_layout(Some(source.layout()))?;
+            Some(target)
+        }
+        None => None,
+    };
+    let style = args.style.apply(source.style())?;
+    match &mut target {
+        Some(target) => target.set_style(style)?,
+        None => source.set_style(style)?,
+    }
+    let out_dir = args.out.as_deref().unwrap_or(&args.dir);
+    let files: Vec<(String, FileEntry)> = source
+        .files()
+        .filter(|(key, e)| e.archive.is_none() && Format::of(Path::new(key)) == Some(args.from))
+        .map(|(key, entry)| (key.to_string(), entry.clone()))
+        .collect();
+
+    let mut rows_total = 0;
+    for (key, entry) in &files {
+        let src = args.dir.join(key);
+        let rows = args
+            .from
+            .read(&src, source.style())
+            .with_context(|| format!("reading {:?}", src))?;
+        check_rows(&src, rows.len(), entry.rows)?;
+
+        let dst = out_dir.join(args.to.rename(Path::new(key)));
+        let parent = dst.parent().unwrap_or(out_dir);
+        std::fs::create_dir_all(parent)?;
+        let tmp = dst.with_extension(format!("{}.tmp", args.to.extension()));
+        args.to.write(&tmp, &rows, style, args.durability)?;
+        let written = args.to.read(&tmp, style)?.len();
+        if let Err(e) = check_rows(&tmp, written, entry.rows) {
+            std::fs::remove_file(&tmp)?;
+            return Err(e);
+        }
+        std::fs::rename(&tmp, &dst)?;
+        if args.durability == Durability::Fsync {
+            sync_dir(parent)?;
+        }
+
+        let converted = FileEntry {
+            bytes: std::fs::metadata(&dst)?.len(),
+            ..entry.clone()
+        };
+        match &mut target {
+            Some(target) => target.record(&dst, converted, args.durability)?,
+            None => {
+                source.remove(key);
+                source.record(&dst, converted, args.durability)?;
+                std::fs::remove_file(&src)?;
+            }
+        }
+        rows_total += rows.len();
+        tracing::info!("converted {:?} -> {:?}, {} rows", src, dst, rows.len());
+    }
+    // new downloads follow once no file is left in another format
+    let manifest = target.as_mut().unwrap_or(&mut source);
+    if manifest
+        .files()
+        .all(|(key, _)| Format::of(Path::new(key)) == Some(args.to))
+    {
+        manifest.set_format(Some(args.to))?;
+        manifest.save(args.durability)?;
+    }
+    tracing::info!(
+        "converted {} file(s), {} rows, from {:?} to {:?}",
+        files.len(),
+        rows_total,
+        args.from,
+        args.to
+    );
+    Ok(())
+}
+
+fn check_rows(path: &Path, found: usize, expected: usize) -> Result<()> {
+    if found != expected {
+        return Err(anyhow!(
+            "{:?} has {} rows, the manifest recorded {}",
+            path,
+            found,
+            expected
+        )
+        .context(Failure::Validation));
+    }
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Result};
+use chrono::{
+    DateTime, Days, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone,
+};
+use chrono_tz::Tz;
+
+/// When each day begins: a wall-clock time, in UTC unless a zone is given,
+/// e.g. `08:00`, `17:00@America/New_York` or `00:00@+05:30`. A day is named
+/// after the date it begins on, and lasts 23 or 25 hours across a DST
+/// change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DayStart {
+    time: NaiveTime,
+    zone: Zone,
+}
+
+/// The zone days are aligned to: a named one, with its DST changes, or a
+/// fixed offset from UTC, see `--align-tz`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Zone {
+    Named(Tz),
+    Offset(FixedOffset),
+}
+
+impl Default for DayStart {
+    fn default() -> Self {
+        Self {
+            time: NaiveTime::MIN,
+            zone: Zone::Named(Tz::UTC),
+        }
+    }
+}
+
+/// Step back from a time inside a gap in the local clock to the last one
+/// before it, gaps being whole quarter hours.
+const GAP_STEP: TimeDelta = TimeDelta::minutes(15);
+/// Steps in a day and an hour, longer than any gap.
+const MAX_GAP_STEPS: i32 = 25 * 4;
+
+impl Zone {
+    /// The first instant the wall clock reads `local`. A time inside a gap
+    /// is read with the offset from before it, which moves it later by the
+    /// gap's length: an hour for most DST changes, two in Antarctica/Troll,
+    /// a whole day where a zone once skipped one.
+    fn earliest_ms(self, local: NaiveDateTime) -> i64 {
+        fn earliest<Z: TimeZone>(zone: &Z, local: NaiveDateTime) -> Option<i64> {
+            (0..=MAX_GAP_STEPS)
+                .find_map(|step| {
+                    let back = GAP_STEP * step;
+                    zone.from_local_datetime(&(local - back))
+                        .earliest()
+                        .map(|t| t + back)
+                })
+                .map(|t| t.timestamp_millis())
+        }
+        match self {
+            Zone::Named(tz) => earliest(&tz, local),
+            Zone::Offset(offset) => earliest(&offset, local),
+        }
+        // no zone has skipped more than a day; read it as UTC if one did
+        .unwrap_or_else(|| local.and_utc().timestamp_millis())
+    }
+
+    /// The wall-clock time at `ms`.
+    fn local(self, ms: i64) -> NaiveDateTime {
+        let utc = DateTime::from_timestamp_millis(ms).unwrap();
+        match self {
+            Zone::Named(tz) => utc.with_timezone(&tz).naive_local(),
+            Zone::Offset(offset) => utc.with_timezone(&offset).naive_local(),
+        }
+    }
+}
+
+impl FromStr for Zone {
+    type Err = anyhow::Error;
+
+    /// A zone name such as `Asia/Tokyo`, or an offset such as `+08:00`,
+    /// `-05:30` or `+8`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (sign, offset) = match s.as_bytes().first() {
+            Some(b'+') => (1, &s[1..]),
+            Some(b'-') => (-1, &s[1..]),
+            _ => {
+                return s
+                    .parse()
+                    .map(Zone::Named)
+                    .map_err(|_| anyhow!("unknown time zone {:?}", s))
+            }
+        };
+        let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+        let offset = match (hours.parse::<i32>(), minutes.parse::<i32>()) {
+            (Ok(h), Ok(m)) if hours.len() <= 2 && (0..60).contains(&m) => {
+                FixedOffset::east_opt(sign * (h * 3600 + m * 60))
+            }
+            _ => None,
+        };
+        offset
+            .map(Zone::Offset)
+            .ok_or_else(|| anyhow!("invalid UTC offset {:?}, expected e.g. +08:00", s))
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Zone::Named(tz) => write!(f, "{}", tz),
+            Zone::Offset(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl DayStart {
+    /// The same start time in `zone`, for `--align-tz`.
+    pub(crate) fn aligned_to(self, zone: Zone) -> Result<Self> {
+        if self.zone != DayStart::default().zone {
+            return Err(anyhow!(
+                "--day-start {} already has a zone; leave out --align-tz",
+                self
+            ));
+        }
+        Ok(Self { zone, ..self })
+    }
+
+    pub(crate) fn start_ms(self, day: NaiveDate) -> i64 {
+        self.zone.earliest_ms(day.and_time(self.time))
+    }
+
+    /// Start of the day after the one `ms` falls in.
+    pub(crate) fn next_day_ms(self, ms: i64) -> i64 {
+        self.start_ms(self.day_of(ms).checked_add_days(Days::new(1)).unwrap())
+    }
+
+    /// The day `ms` falls in. Compared with the day's actual start, not the
+    /// wall clock, so that times a DST change skips or repeats near the
+    /// start land in the same day as [`DayStart::start_ms`] puts them.
+    pub(crate) fn day_of(self, ms: i64) -> NaiveDate {
+        let date = self.zone.local(ms).date();
+        if ms < self.start_ms(date) {
+            date.pred_opt().unwrap()
+        } else {
+            date
+        }
+    }
+}
+
+impl FromStr for DayStart {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (time, zone) = s.split_once('@').unwrap_or((s, "UTC"));
+        let time = NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| anyhow!("invalid time {:?}, expected HH:MM", time))?;
+        Ok(Self {
+            time,
+            zone: zone.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for DayStart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.time.format("%H:%M"))?;
+        if self.zone != DayStart::default().zone {
+            write!(f, "@{}", self.zone)?;
+        }
+        Ok(())
+    }
+}
+
+static DAY_START: OnceLock<DayStart> = OnceLock::new();
+
+/// Sets the day boundary for the rest of the process; call once at startup
+/// before anything asks for it.
+pub(crate) fn init(day_start: DayStart) {
+    DAY_START
+        .set(day_start)
+        .expect("day boundary is already set");
+}
+
+pub(crate) fn day_start() -> DayStart {
+    *DAY_START.get_or_init(DayStart::default)
+}
+
+pub(crate) fn day_start_ms(day: NaiveDate) -> i64 {
+    day_start().start_ms(day)
+}
+
+/// Start of the day after the one `ms` falls in.
+pub(crate) fn next_day_ms(ms: i64) -> i64 {
+    day_start().next_day_ms(ms)
+}
+
+/// The day `ms` falls in.
+pub(crate) fn day_of(ms: i64) -> NaiveDate {
+    day_start().day_of(ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3_600_000;
+
+    fn ms(rfc3339: &str) -> i64 {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    fn day(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn utc_days_start_at_midnight() {
+        let utc = DayStart::default();
+        let start = ms("2024-06-01T00:00:00Z");
+        assert_eq!(utc.start_ms(day("2024-06-01")), start);
+        assert_eq!(utc.day_of(start - 1), day("2024-05-31"));
+        assert_eq!(utc.day_of(start), day("2024-06-01"));
+        assert_eq!(utc.next_day_ms(start), start + 24 * HOUR);
+        assert_eq!(utc.next_day_ms(start - 1), start);
+    }
+
+    #[test]
+    fn dst_changes_shorten_and_lengthen_days() {
+        let london: DayStart = "00:00@Europe/London".parse().unwrap();
+        // clocks go forward at 01:00 UTC on 2024-03-31
+        let start = london.start_ms(day("2024-03-31"));
+        assert_eq!(start, ms("2024-03-31T00:00:00Z"));
+        assert_eq!(london.next_day_ms(start), start + 23 * HOUR);
+        assert_eq!(london.day_of(start + 23 * HOUR - 1), day("2024-03-31"));
+        assert_eq!(london.day_of(start + 23 * HOUR), day("2024-04-01"));
+        // and back at 01:00 UTC on 2024-10-27
+        let start = london.start_ms(day("2024-10-27"));
+        assert_eq!(start, ms("2024-10-26T23:00:00Z"));
+        assert_eq!(london.next_day_ms(start), start + 25 * HOUR);
+        assert_eq!(london.day_of(start + 25 * HOUR - 1), day("2024-10-27"));
+    }
+
+    #[test]
+    fn starts_near_dst_changes_agree_with_day_of() {
+        let london: DayStart = "01:30@Europe/London".parse().unwrap();
+        // 01:30 doesn't exist on 2024-03-31; 02:30 BST is 01:30 UTC
+        let start = london.start_ms(day("2024-03-31"));
+        assert_eq!(start, ms("2024-03-31T01:30:00Z"));
+        assert_eq!(london.day_of(start - 1), day("2024-03-30"));
+        assert_eq!(london.day_of(start), day("2024-03-31"));
+        // 01:30 happens twice on 2024-10-27; the day starts at the first
+        let start = london.start_ms(day("2024-10-27"));
+        assert_eq!(start, ms("2024-10-27T00:30:00Z"));
+        assert_eq!(london.day_of(start - 1), day("2024-10-26"));
+        assert_eq!(london.day_of(ms("2024-10-27T01:15:00Z")), day("2024-10-27"));
+    }
+
+    #[test]
+    fn starts_inside_two_hour_gaps_move_past_them() {
+        // Troll goes from +00 to +02 at 01:00 UTC on 2024-03-31
+        let troll: DayStart = "01:30@Antarctica/Troll".parse().unwrap();
+        let start = troll.start_ms(day("2024-03-31"));
+        assert_eq!(start, ms("2024-03-31T01:30:00Z"));
+        assert_eq!(troll.day_of(start - 1), day("2024-03-30"));
+        assert_eq!(troll.day_of(start), day("2024-03-31"));
+        // a day start aligned to the zone with `--align-tz` moves the same way
+        let aligned = "02:00"
+            .parse::<DayStart>()
+            .unwrap()
+            .aligned_to("Antarctica/Troll".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            aligned.start_ms(day("2024-03-31")),
+            ms("2024-03-31T02:00:00Z")
+        );
+    }
+
+    #[test]
+    fn offsets_align_days_without_dst() {
+        let ist: DayStart = "00:00@+05:30".parse().unwrap();
+        let start = ist.start_ms(day("2024-06-01"));
+        assert_eq!(start, ms("2024-05-31T18:30:00Z"));
+        assert_eq!(ist.day_of(start - 1), day("2024-05-31"));
+        assert_eq!(ist.next_day_ms(start), start + 24 * HOUR);
+        let nyse: DayStart = "17:00@-5".parse().unwrap();
+        assert_eq!(nyse.start_ms(day("2024-06-01")), ms("2024-06-01T22:00:00Z"));
+    }
+
+    #[test]
+    fn zones_parse_and_print() {
+        for s in [
+            "08:00",
+            "17:00@America/New_York",
+            "00:00@+05:30",
+            "00:00@-08:00",
+        ] {
+            assert_eq!(s.parse::<DayStart>().unwrap().to_string(), s);
+        }
+        assert_eq!("+8".parse::<Zone>().unwrap().to_string(), "+08:00");
+        for s in ["+24:00", "+05:60", "+0530", "Mars/Olympus"] {
+            assert!(s.parse::<Zone>().is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn align_tz_moves_the_start_into_the_zone() {
+        let zone: Zone = "+08:00".parse().unwrap();
+        let aligned = DayStart::default().aligned_to(zone).unwrap();
+        assert_eq!(aligned.to_string(), "00:00@+08:00");
+        assert!(aligned.aligned_to(zone).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+
+/// An exact decimal as `units / 10^scale`, printed with the same number of
+/// places it was parsed with (the larger of the two after a sum), so
+/// prices and volumes keep the exchange's text. (De)serializes as that text.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Decimal {
+    units: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    /// The nearest `f64`, for arithmetic that doesn't need to be exact.
+    pub fn to_f64(self) -> f64 {
+        self.units as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    fn rescale(self, scale: u32) -> i128 {
+        self.units * 10i128.pow(scale - self.scale)
+    }
+}
+
+impl std::str::FromStr for Decimal {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        let negative = int.starts_with('-');
+        let digits = format!("{}{}", int.trim_start_matches(['-', '+']), frac);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) || frac.len() > 18 {
+            return Err(anyhow!("invalid decimal {:?}", s));
+        }
+        let units: i128 = digits.parse()?;
+        Ok(Self {
+            units: if negative { -units } else { units },
+            scale: frac.len() as u32,
+        })
+    }
+}
+
+impl std::ops::Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        let scale = self.scale.max(other.scale);
+        Decimal {
+            units: self.rescale(scale) + other.rescale(scale),
+            scale,
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        self.rescale(scale).cmp(&other.rescale(scale))
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.units < 0 { "-" } else { "" };
+        let units = self.units.unsigned_abs();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, units);
+        }
+        let pow = 10u128.pow(self.scale);
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            units / pow,
+            units % pow,
+            width = self.scale as usize
+        )
+    }
+}
+
+impl TryFrom<String> for Decimal {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Decimal> for String {
+    fn from(d: Decimal) -> Self {
+        d.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_text_it_was_parsed_from() {
+        for text in ["0.00000000", "3500.10000000", "-1.5", "42"] {
+            let d: Decimal = text.parse().unwrap();
+            assert_eq!(d.to_string(), text);
+            assert_eq!(serde_json::to_string(&d).unwrap(), format!("{:?}", text));
+        }
+        let d: Decimal = serde_json::from_str("\"0.10\"").unwrap();
+        assert_eq!(d, "0.1".parse().unwrap());
+        assert_eq!(d.to_f64(), 0.1);
+    }
+
+    #[test]
+    fn sums_at_the_larger_scale() {
+        let a: Decimal = "1.25".parse().unwrap();
+        let b: Decimal = "0.0005".parse().unwrap();
+        assert_eq!((a + b).to_string(), "1.2505");
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_hold_exactly() {
+        for text in ["", "1e-8", "1.2.3", "0.0000000000000000001", "abc"] {
+            assert!(text.parse::<Decimal>().is_err(), "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+
+use crate::day::{day_start_ms, next_day_ms};
+use crate::decimal::Decimal;
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::kline::{timestamp, Kline};
+use crate::manifest::{FileEntry, Manifest};
+use crate::reader::DatasetReader;
+
+#[derive(clap::Args)]
+pub(crate) struct DiffArgs {
+    /// Dataset to compare, with its `manifest.json`
+    a: PathBuf,
+    /// Dataset to compare it with
+    b: PathBuf,
+    /// Differences printed per symbol and interval
+    #[arg(long, default_value_t = 20)]
+    examples: usize,
+}
+
+/// A symbol and interval.
+type Series = (String, String);
+
+/// One side of the comparison. Files are read when a day first needs them
+/// and dropped after their last day, so a merged month is read once.
+struct Dataset {
+    reader: DatasetReader,
+    manifest: Manifest,
+    cache: HashMap<String, (NaiveDate, Vec<Kline>)>,
+}
+
+impl Dataset {
+    fn open(dir: &Path) -> Result<Self> {
+        let manifest = Manifest::load(dir)?;
+        Ok(Self {
+            reader: DatasetReader::new(&manifest),
+            manifest,
+            cache: HashMap::new(),
+        })
+    }
+
+    fn days(&self, out: &mut BTreeMap<Series, BTreeSet<NaiveDate>>) {
+        for (key, e) in self.manifest.files() {
+            if Format::of(Path::new(key)).is_none() {
+                continue;
+            }
+            let days = out
+                .entry((e.symbol.clone(), e.interval.clone()))
+                .or_default();
+            let last = e.last_day.unwrap_or(e.day);
+            days.extend(e.day.iter_days().take_while(|d| *d <= last));
+        }
+    }
+
+    /// Every stored row of `series` opening on `day`, in time order.
+    fn rows(&mut self, series: &Series, day: NaiveDate) -> Result<Vec<Kline>> {
+        let keys: Vec<(String, FileEntry)> = self
+            .manifest
+            .files()
+            .filter(|(key, e)| {
+                e.symbol == series.0
+                    && e.interval == series.1
+                    && e.day <= day
+                    && day <= e.last_day.unwrap_or(e.day)
+                    && Format::of(Path::new(key)).is_some()
+            })
+            .map(|(key, e)| (key.to_string(), e.clone()))
+            .collect();
+        let start_ms = day_start_ms(day);
+        let end_ms = next_day_ms(start_ms);
+        let mut rows = Vec::new();
+        for (key, entry) in keys {
+            if !self.cache.contains_key(&key) {
+                let file_rows = self.reader.read(&key, &entry)?;
+                let last_day = entry.last_day.unwrap_or(entry.day);
+                self.cache.insert(key.clone(), (last_day, file_rows));
+            }
+            rows.extend(
+                self.cache[&key]
+                    .1
+                    .iter()
+                    .filter(|r| (start_ms..end_ms).contains(&r.open_time))
+                    .cloned(),
+            );
+        }
+        self.cache.retain(|_, (last_day, _)| *last_day > day);
+        rows.sort_by_key(|r| r.open_time);
+        Ok(rows)
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    rows_a: usize,
+    rows_b: usize,
+    only_a: usize,
+    only_b: usize,
+    differ: usize,
+    fields: BTreeMap<&'static str, usize>,
+    examples: Vec<String>,
+}
+
+pub(crate) async fn run(args: DiffArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || diff(&args)).await?
+}
+
+/// Compares the datasets row by row on open time. Decimals are compared by
+/// value, so `1.50` and `1.5000` match.
+fn diff(args: &DiffArgs) -> Result<()> {
+    let mut a = Dataset::open(&args.a)?;
+    let mut b = Dataset::open(&args.b)?;
+    let mut series_days = BTreeMap::new();
+    a.days(&mut series_days);
+    b.days(&mut series_days);
+
+    let mut differing = 0;
+    for (series, days) in &series_days {
+        let mut summary = Summary::default();
+        for day in days {
+            let rows_a = a.rows(series, *day)?;
+            let rows_b = b.rows(series, *day)?;
+            compare(&rows_a, &rows_b, args.examples, &mut summary);
+        }
+        println!(
+            "{} {}, {} day(s): {} rows in A, {} in B; {} only in A, {} only in B, {} differ",
+            series.0,
+            series.1,
+            days.len(),
+            summary.rows_a,
+            summary.rows_b,
+            summary.only_a,
+            summary.only_b,
+            summary.differ
+        );
+        for (field, count) in &summary.fields {
+            println!("  {} differs in {} row(s)", field, count);
+        }
+        for example in &summary.examples {
+            println!("  {}", example);
+        }
+        if summary.only_a + summary.only_b + summary.differ > 0 {
+            differing += 1;
+        }
+    }
+    if differing > 0 {
+        return Err(
+            anyhow!("{} of {} series differ", differing, series_days.len())
+                .context(Failure::Validation),
+        );
+    }
+    Ok(())
+}
+
+fn compare(a: &[Kline], b: &[Kline], examples: usize, summary: &mut Summary) {
+    summary.rows_a += a.len();
+    summary.rows_b += b.len();
+    let example = |summary: &mut Summary, text: String| {
+        if summary.examples.len() < examples {
+            summary.examples.push(text);
+        }
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        match (a.get(i), b.get(j)) {
+            (Some(x), Some(y)) if x.open_time == y.open_time => {
+                let mut fields: Vec<(&str, String, String)> = text_fields(x)
+                    .into_iter()
+                    .zip(text_fields(y))
+                    .filter(|((_, u), (_, v))| !same(u, v))
+                    .map(|((name, u), (_, v))| (name, u.to_string(), v.to_string()))
+                    .collect();
+                if x.close_time != y.close_time {
+                    let (u, v) = (x.close_time.to_string(), y.close_time.to_string());
+                    fields.push(("close_time", u, v));
+                }
+                if x.num_of_trades != y.num_of_trades {
+                    let (u, v) = (x.num_of_trades.to_string(), y.num_of_trades.to_string());
+                    fields.push(("num_of_trades", u, v));
+                }
+                if !fields.is_empty() {
+                    summary.differ += 1;
+                    for (name, u, v) in &fields {
+                        *summary.fields.entry(name).or_default() += 1;
+                        example(
+                            summary,
+                            format!("{} {}: {} vs {}", timestamp(x.open_time), name, u, v),
+                        );
+                    }
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(x), y) if y.is_none_or(|y| x.open_time < y.open_time) => {
+                summary.only_a += 1;
+                example(summary, format!("{} only in A", timestamp(x.open_time)));
+                i += 1;
+            }
+            (_, Some(y)) => {
+                summary.only_b += 1;
+                example(summary, format!("{} only in B", timestamp(y.open_time)));
+                j += 1;
+            }
+            (_, None) => unreachable!(),
+        }
+    }
+}
+
+fn text_fields(k: &Kline) -> [(&'static str, &str); 8] {
+    [
+        ("open", k.open_price()),
+        ("high", k.high()),
+        ("low", k.low()),
+        ("close", k.close()),
+        ("volume", k.volume()),
+        ("quote_volume", k.quote_volume()),
+        ("taker_buy_base_vol", k.taker_buy_base_vol()),
+        ("taker_buy_quote_vol", k.taker_buy_quote_vol()),
+    ]
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a == b
+        || matches!(
+            (a.parse::<Decimal>(), b.parse::<Decimal>()),
+            (Ok(x), Ok(y)) if x == y
+        )
+}
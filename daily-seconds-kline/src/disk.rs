@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::manifest::Manifest;
+
+/// Size of a headerless CSV kline row, used until the manifest knows better.
+const DEFAULT_BYTES_PER_ROW: f64 = 140.0;
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Expected size on disk of `rows` klines.
+pub(crate) fn estimate_bytes(rows: u64, manifest: &Manifest) -> u64 {
+    let per_row = manifest.bytes_per_row().unwrap_or(DEFAULT_BYTES_PER_ROW);
+    (rows as f64 * per_row).ceil() as u64
+}
+
+/// Keeps at least `min_free` bytes available in `dir`.
+pub(crate) struct SpaceGuard {
+    dir: PathBuf,
+    min_free: u64,
+    /// Wait for space to be freed instead of failing.
+    pause: bool,
+}
+
+impl SpaceGuard {
+    pub(crate) fn new(dir: PathBuf, min_free: u64, pause: bool) -> Self {
+        Self {
+            dir,
+            min_free,
+            pause,
+        }
+    }
+
+    /// Fails up front if a job needing `bytes` can't fit.
+    pub(crate) fn check(&self, bytes: u64) -> Result<()> {
+        let available = available(&self.dir)?;
+        if available < bytes + self.min_free {
+            return Err(anyhow!(
+                "not enough disk space in {:?}: {} MiB available, {} MiB needed plus {} MiB reserve",
+                self.dir,
+                available >> 20,
+                bytes.div_ceil(1 << 20),
+                self.min_free >> 20
+            ));
+        }
+        Ok(())
+    }
+
+    /// Called before each file is written, so a full disk stops the run
+    /// between files rather than in the middle of one.
+    pub(crate) async fn reserve(&self, bytes: u64) -> Result<()> {
+        loop {
+            match self.check(bytes) {
+                Err(e) if self.pause => {
+                    tracing::error!("{:#}, pausing writes", e);
+                    tokio::time::sleep(RECHECK_INTERVAL).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn available(dir: &Path) -> Result<u64> {
+    Ok(fs2::available_space(dir)?)
+}
@@ -0,0 +1,1489 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering::Relaxed};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{NaiveDate, Utc};
+use futures::stream::{FuturesOrdered, StreamExt};
+
+use crate::adaptive::{Adaptive, Observation, MAX_WINDOW_ROWS};
+use crate::bucket::DayBucketer;
+use crate::day::{self, day_start_ms, next_day_ms, DayStart};
+use crate::disk::{self, SpaceGuard};
+use crate::exit::Failure;
+use crate::format::{Format, StyleArgs};
+use crate::health::Health;
+use crate::kline::{self, Kline, Source};
+use crate::layout::{Layout, LayoutArgs};
+use crate::manifest::Manifest;
+use crate::market::{market, Market};
+use crate::planner::{self, Order};
+use crate::progress::Progress;
+use crate::prune::Retention;
+use crate::queue::{Job, Queue};
+use crate::reader::DatasetReader;
+use crate::replay::{ArchiveCache, ArchiveMeta, ResponseCache};
+use crate::report;
+use crate::session::Session;
+use crate::sink::Sink;
+use crate::stream::KlineStream;
+use crate::symbols::SymbolArgs;
+use crate::systemd;
+use crate::throttle::Throttle;
+use crate::trades::{self, DataType};
+use crate::vision;
+use crate::writer::{DailyWriter, DayBatch, Durability, Target};
+
+pub(crate) const SYMBOL: &str = "ETHUSDC";
+pub(crate) const INTERVAL: &str = "1s";
+pub(crate) const OUT_DIR: &str = "1s_klines";
+const START_DATE: &str = "2024-06-01";
+/// Intervals the exchange serves that fit in a daily file.
+const INTERVALS: [&str; 13] = [
+    "1s", "1m", "3m", "5m", "15m", "30m", "1h", "2h", "4h", "6h", "8h", "12h", "1d",
+];
+/// First retry delay, doubled on each further attempt at a window.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Longest `Retry-After` worth waiting out; longer bans fail the day.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+/// Share of the weight limit at which requests wait for the next minute.
+const WEIGHT_PAUSE: f64 = 0.95;
+/// Failed requests retried over a run, unless `--max-retries` says otherwise.
+pub(crate) const MAX_RETRIES: u32 = 10;
+/// Attempts at one request, unless `--max-window-attempts` says otherwise.
+pub(crate) const MAX_WINDOW_ATTEMPTS: u32 = 8;
+/// Requests `--refetch-gaps` spends on one day at most.
+const MAX_REFETCH_WINDOWS: usize = 20;
+
+/// A claimed job clipped to the end of the requested range.
+struct DayRange {
+    job: Job,
+    start_ms: i64,
+    end_ms: i64,
+    step_ms: i64,
+}
+
+impl DayRange {
+    fn new(job: Job, until_ms: i64) -> Result<Self> {
+        let step_ms = interval_ms(&job.interval)
+            .ok_or_else(|| anyhow!("queued job has unknown interval {:?}", job.interval))
+            .context(Failure::Config)?;
+        let start_ms = day_start_ms(job.day);
+        let end_ms = (next_day_ms(start_ms) - 1).min(until_ms);
+        Ok(Self {
+            job,
+            start_ms,
+            end_ms,
+            step_ms,
+        })
+    }
+
+    fn is_full_day(&self) -> bool {
+        self.end_ms == next_day_ms(self.start_ms) - 1
+    }
+}
+
+/// An inclusive `[start_ms, end_ms]` request range inside `days[day]`, so
+/// every response belongs to exactly one daily file.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    day: usize,
+    start_ms: i64,
+    end_ms: i64,
+}
+
+#[derive(Default)]
+struct Windows {
+    days: Vec<DayRange>,
+    day: usize,
+    next_start_ms: i64,
+}
+
+impl Windows {
+    fn push(&mut self, range: DayRange) {
+        if self.day == self.days.len() {
+            self.next_start_ms = range.start_ms;
+        }
+        self.days.push(range);
+    }
+
+    /// The next window of at most `rows` candles.
+    fn next(&mut self, rows: u32) -> Option<Window> {
+        loop {
+            let range = self.days.get(self.day)?;
+            if self.next_start_ms > range.end_ms {
+                self.day += 1;
+                self.next_start_ms = self.days.get(self.day).map_or(0, |d| d.start_ms);
+                continue;
+            }
+            let start_ms = self.next_start_ms;
+            let end_ms = (start_ms + i64::from(rows) * range.step_ms - 1).min(range.end_ms);
+            self.next_start_ms = end_ms + 1;
+            return Some(Window {
+                day: self.day,
+                start_ms,
+                end_ms,
+            });
+        }
+    }
+
+    /// Stops producing windows for `day` if it is the one in progress.
+    fn skip_day(&mut self, day: usize) {
+        if self.day == day {
+            self.next_start_ms = i64::MAX;
+        }
+    }
+}
+
+/// Length of a kline interval such as `1s`, `15m` or `1d`. Months have no
+/// fixed length and aren't supported.
+pub(crate) fn interval_ms(interval: &str) -> Option<i64> {
+    let unit = match interval.chars().last()? {
+        's' => 1000,
+        'm' => 60_000,
+        'h' => 3_600_000,
+        'd' => 86_400_000,
+        'w' => 7 * 86_400_000,
+        _ => return None,
+    };
+    let count: i64 = interval[..interval.len() - 1].parse().ok()?;
+    Some(count * unit)
+}
+
+#[derive(clap::Parser)]
+pub(crate) struct DownloadArgs {
+    /// What to download; trades come a whole finished day at a time and
+    /// take no `--interval`
+    #[arg(long, value_enum, default_value_t)]
+    data_type: DataType,
+    /// Length of each candle
+    #[arg(long, default_value = INTERVAL, value_parser = clap::builder::PossibleValuesParser::new(INTERVALS))]
+    interval: String,
+    /// First day to download
+    #[arg(long, default_value = START_DATE)]
+    start_date: NaiveDate,
+    /// Last day to download, inclusive; `--start-date` if left out, or
+    /// today with `--follow`. Today's file stops at the last closed candle
+    #[arg(long)]
+    end_date: Option<NaiveDate>,
+    /// Once caught up, keep today's files current from the kline WebSocket
+    /// stream, finishing each day as the next one begins; runs until stopped
+    #[arg(long)]
+    follow: bool,
+    /// With `--follow`, how often today's files are rewritten with the
+    /// candles closed since
+    #[arg(long, default_value_t = 60, requires = "follow")]
+    flush_secs: u64,
+    /// Job queue: a SQLite file (default `.queue.sqlite3` in `--out-dir`)
+    /// or a `postgres://` URL shared with workers
+    #[arg(long)]
+    queue: Option<String>,
+    /// Which end of the range to fetch first
+    #[arg(long, value_enum, default_value_t)]
+    order: Order,
+    /// Print the plan and its estimated cost without downloading
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    symbols: SymbolArgs,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(clap::Args)]
+pub(crate) struct WorkerArgs {
+    /// `postgres://` URL of the shared queue
+    #[arg(long)]
+    queue: String,
+    #[command(flatten)]
+    retention: Retention,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+/// Options shared by `download` and `worker`.
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Directory the daily files and their `manifest.json` go in; futures
+    /// get a dataset of their own below it, e.g. `1s_klines/um`
+    #[arg(long, default_value = OUT_DIR)]
+    out_dir: PathBuf,
+    /// Write the days here instead of into files in `--out-dir`: a bucket,
+    /// `s3://bucket/prefix`, to upload the files to, or a database as
+    /// `load` takes. Only the queue then knows which days are done; keep it
+    /// off the local disk with `--queue postgres://...`
+    #[arg(long)]
+    sink: Option<String>,
+    /// Serve `/healthz` and `/readyz` on this address
+    #[arg(long)]
+    health_addr: Option<SocketAddr>,
+    /// Limit all downloads together to this many bytes per second
+    #[arg(long)]
+    max_bandwidth: Option<u64>,
+    /// Save every raw response in this directory
+    #[arg(long)]
+    record: Option<PathBuf>,
+    /// Answer requests from responses saved in this directory, fetching and
+    /// saving only the ones it doesn't have
+    #[arg(long, conflicts_with = "record")]
+    replay_cache: Option<PathBuf>,
+    /// Disk space to keep free in the output directory, in MiB
+    #[arg(long, default_value_t = 1024)]
+    min_free_mb: u64,
+    /// What to wait for before a written file counts as done
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+    /// Keep only rows opening inside these windows, e.g. `13:30-20:00`,
+    /// `09:30-16:00@America/New_York` or `us-equities`
+    #[arg(long)]
+    session: Option<Session>,
+    /// Format of the daily files; an existing dataset keeps its own
+    #[arg(long, value_enum)]
+    output_format: Option<Format>,
+    /// Ask once more for candles missing from a day before writing it;
+    /// see `gaps` for a report afterwards
+    #[arg(long)]
+    refetch_gaps: bool,
+    /// Take whole days from the daily archives on data.binance.vision,
+    /// checked against their published checksums, and ask the API only for
+    /// days not published yet; needs days starting at midnight UTC
+    #[arg(long)]
+    vision: bool,
+    /// Keep `--vision` archives in this directory and download one again
+    /// only if its ETag or Last-Modified changed
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+    /// Size to keep the `--cache-dir` under, in MiB; the archives used
+    /// longest ago go first
+    #[arg(long, default_value_t = 4096)]
+    cache_max_mb: u64,
+    /// Log each symbol's days done and an ETA this often, in seconds; 0
+    /// turns it off
+    #[arg(long, default_value_t = 30)]
+    progress_secs: u64,
+    /// When the run ends, write a JSON summary here, `-` for stdout: days
+    /// written with their rows, missing candles and bytes, and retries
+    #[arg(long)]
+    summary: Option<PathBuf>,
+    #[command(flatten)]
+    layout: LayoutArgs,
+    #[command(flatten)]
+    style: StyleArgs,
+    #[command(flatten)]
+    limits: Limits,
+}
+
+/// When a run gives up on a broken endpoint. Days not finished by then stay
+/// queued and the run exits with a partial-success report.
+#[derive(clap::Args)]
+struct Limits {
+    /// Failed requests retried in total over the whole run, with backoff;
+    /// waiting out a rate limit isn't charged to it
+    #[arg(long, default_value_t = MAX_RETRIES)]
+    max_retries: u32,
+    /// Attempts at any one request, rate-limit waits included, before its
+    /// day fails
+    #[arg(long, default_value_t = MAX_WINDOW_ATTEMPTS)]
+    max_window_attempts: u32,
+    /// Abort after this many days in a row failed
+    #[arg(long)]
+    max_failed_days: Option<u32>,
+    /// Abort once the run has taken this many minutes
+    #[arg(long)]
+    max_elapsed_mins: Option<u64>,
+}
+
+impl RunArgs {
+    /// The dataset of `--market` in `--out-dir`.
+    fn dataset_dir(&self) -> PathBuf {
+        match market().dir() {
+            Some(dir) => self.out_dir.join(dir),
+            None => self.out_dir.clone(),
+        }
+    }
+
+    /// Progress of a run about to start, logged as `--progress-secs` asks.
+    fn progress(&self) -> Progress {
+        let progress = Progress::default();
+        if self.progress_secs > 0 {
+            progress.log_every(Duration::from_secs(self.progress_secs));
+        }
+        progress
+    }
+
+    /// What the days are written to, files in the dataset unless `--sink`
+    /// says otherwise.
+    async fn target(
+        &self,
+        manifest: Manifest,
+        space: SpaceGuard,
+        retention: Retention,
+    ) -> Result<Target> {
+        match &self.sink {
+            None => Ok(Target::Files {
+                manifest,
+                space,
+                retention,
+            }),
+            Some(url) if url.starts_with("sheets://") => Err(anyhow!(
+                "--sink can't be a Google Sheet, it only appends; see load"
+            )
+            .context(Failure::Config)),
+            Some(url) => Ok(Target::Sink(Sink::open(url, &manifest).await?)),
+        }
+    }
+}
+
+pub(crate) async fn run(args: DownloadArgs) -> Result<()> {
+    let now_ms = Utc::now().timestamp_millis();
+    let today = day::day_of(now_ms);
+    let end_date = match args.end_date {
+        Some(end_date) => end_date,
+        None if args.follow => today,
+        None => args.start_date,
+    };
+    if args.follow && end_date != today {
+        return Err(
+            anyhow!("--follow continues from today, not --end-date {}", end_date)
+                .context(Failure::Config),
+        );
+    }
+    if market() != Market::Spot && args.interval == "1s" {
+        return Err(anyhow!(
+            "{} has no 1s klines, pass --interval 1m or longer",
+            market().name()
+        )
+        .context(Failure::Config));
+    }
+    if end_date < args.start_date {
+        return Err(anyhow!(
+            "--end-date {} is before --start-date {}",
+            end_date,
+            args.start_date
+        )
+        .context(Failure::Config));
+    }
+    // the exchange's first trading day
+    let first = NaiveDate::from_ymd_opt(2017, 7, 14).unwrap();
+    if args.start_date < first || end_date > today {
+        return Err(anyhow!(
+            "--start-date {} to --end-date {} is outside {} to today",
+            args.start_date,
+            end_date,
+            first
+        )
+        .context(Failure::Config));
+    }
+    let start_time_ms = day_start_ms(args.start_date);
+    let max_end_time_ms = (next_day_ms(day_start_ms(end_date)) - 1).min(now_ms - 1);
+
+    tracing::info!(
+        "start_time_ms: {}, max_end_time_ms: {}",
+        start_time_ms,
+        max_end_time_ms
+    );
+    let out_dir = args.run.dataset_dir();
+    std::fs::create_dir_all(&out_dir)?;
+    let mut manifest = Manifest::load(&out_dir)?;
+    manifest.set_session(args.run.session.as_ref())?;
+    manifest.set_layout(args.run.layout.layout())?;
+    manifest.set_format(args.run.output_format)?;
+    manifest.set_style(args.run.style.apply(manifest.style())?)?;
+    manifest.set_data(args.data_type)?;
+    let broken = manifest.verify();
+    for (key, _) in &broken {
+        tracing::warn!("{} is missing or damaged, fetching it again", key);
+    }
+    let symbols = args.symbols.resolve(&crate::client::build()?).await?;
+    if args.run.layout.layout().is_none() && symbols.len() > 1 {
+        manifest.set_layout_if_new(Layout::PER_SYMBOL);
+    }
+    if args.data_type != DataType::Klines {
+        if market() != Market::Spot {
+            return Err(anyhow!("trades are only downloaded from spot").context(Failure::Config));
+        }
+        if args.follow || args.run.vision || args.run.session.is_some() || args.run.sink.is_some() {
+            return Err(
+                anyhow!("--follow, --vision, --session and --sink only apply to klines")
+                    .context(Failure::Config),
+            );
+        }
+        if args.dry_run {
+            for symbol in &symbols {
+                for day in args
+                    .start_date
+                    .iter_days()
+                    .take_while(|day| *day <= end_date)
+                {
+                    println!("{} {} {}", symbol, args.data_type.name(), day);
+                }
+            }
+            return Ok(());
+        }
+        if manifest.record_selection(args.symbols.query(), &symbols) || !broken.is_empty() {
+            manifest.save(args.run.durability)?;
+        }
+        let progress = args.run.progress();
+        let fetcher = Fetcher::new(&args.run, progress.clone())?;
+        let result = trades::download(
+            &fetcher,
+            manifest,
+            &symbols,
+            args.data_type,
+            args.start_date,
+            end_date,
+            args.run.durability,
+        )
+        .await;
+        return progress.finish(args.run.summary.as_deref(), result);
+    }
+    let plan = planner::plan(
+        &symbols,
+        &args.interval,
+        interval_ms(&args.interval).expect("listed intervals have a length"),
+        start_time_ms,
+        max_end_time_ms,
+        &manifest,
+        args.order,
+    );
+    let bytes = disk::estimate_bytes(plan.rows, &manifest);
+    tracing::info!(
+        "plan: {} day(s) to fetch, {} already complete, ~{} requests, weight {}, at least {} min, ~{} MiB",
+        plan.jobs.len(),
+        plan.skipped,
+        plan.requests,
+        plan.weight(),
+        plan.min_minutes(),
+        bytes.div_ceil(1 << 20)
+    );
+    if args.dry_run {
+        for job in &plan.jobs {
+            println!("{} {} {}", job.symbol, job.interval, job.day);
+        }
+        return Ok(());
+    }
+    if manifest.record_selection(args.symbols.query(), &symbols) || !broken.is_empty() {
+        manifest.save(args.run.durability)?;
+    }
+    let space = SpaceGuard::new(out_dir.clone(), args.run.min_free_mb << 20, false);
+    if args.run.sink.is_none() {
+        space.check(bytes)?;
+    }
+    let queue = Queue::open(args.queue.as_deref(), &out_dir).await?;
+    queue.enqueue(&plan.jobs).await?;
+    // the queue still has them done
+    for (_, entry) in &broken {
+        let last = entry.last_day.unwrap_or(entry.day);
+        for day in entry.day.iter_days().take_while(|day| *day <= last) {
+            let job = Job {
+                symbol: entry.symbol.clone(),
+                interval: entry.interval.clone(),
+                day,
+            };
+            queue.requeue(&job).await?;
+        }
+    }
+    let health = Health::default();
+    if let Some(addr) = args.run.health_addr {
+        health.clone().serve(addr).await.context(Failure::Config)?;
+    }
+    let progress = args.run.progress();
+    progress.planned(&plan.jobs);
+    let writer = DailyWriter::spawn(
+        queue.clone(),
+        args.run
+            .target(manifest, space, Retention::default())
+            .await?,
+        args.run.durability,
+        health.clone(),
+        progress.clone(),
+    );
+    let mut result = work(
+        queue.clone(),
+        writer,
+        health.clone(),
+        progress.clone(),
+        &args.run,
+        max_end_time_ms,
+    )
+    .await;
+    if args.follow && result.is_ok() {
+        result = follow(&args, &symbols, queue, health, progress.clone()).await;
+    }
+    systemd::stopping();
+    progress.finish(args.run.summary.as_deref(), result)
+}
+
+/// Drains a shared queue filled by `download` on another machine, with this
+/// process's own rate limiting.
+pub(crate) async fn run_worker(args: WorkerArgs) -> Result<()> {
+    let out_dir = args.run.dataset_dir();
+    std::fs::create_dir_all(&out_dir)?;
+    let queue = Queue::open(Some(&args.queue), &out_dir).await?;
+    let mut manifest = Manifest::load(&out_dir)?;
+    manifest.set_session(args.run.session.as_ref())?;
+    manifest.set_layout(args.run.layout.layout())?;
+    manifest.set_format(args.run.output_format)?;
+    manifest.set_style(args.run.style.apply(manifest.style())?)?;
+    let space = SpaceGuard::new(out_dir.clone(), args.run.min_free_mb << 20, true);
+    let health = Health::default();
+    if let Some(addr) = args.run.health_addr {
+        health.clone().serve(addr).await.context(Failure::Config)?;
+    }
+    let progress = args.run.progress();
+    let writer = DailyWriter::spawn(
+        queue.clone(),
+        args.run.target(manifest, space, args.retention).await?,
+        args.run.durability,
+        health.clone(),
+        progress.clone(),
+    );
+    let result = work(
+        queue,
+        writer,
+        health,
+        progress.clone(),
+        &args.run,
+        Utc::now().timestamp_millis() - 1,
+    )
+    .await;
+    systemd::stopping();
+    progress.finish(args.run.summary.as_deref(), result)
+}
+
+async fn work(
+    queue: Queue,
+    mut writer: DailyWriter,
+    health: Health,
+    progress: Progress,
+    run: &RunArgs,
+    until_ms: i64,
+) -> Result<()> {
+    if run.vision && day::day_start() != DayStart::default() {
+        return Err(anyhow!(
+            "--vision archives hold UTC days, not days starting at {}",
+            day::day_start()
+        )
+        .context(Failure::Config));
+    }
+    let limits = &run.limits;
+    let started = Instant::now();
+    let until_day = day::day_of(until_ms);
+    let fetcher = Fetcher::new(run, progress)?;
+    let mut windows = Windows::default();
+    let mut claimed_all = false;
+    let mut adaptive = Adaptive::new(market());
+    let mut in_flight = FuturesOrdered::new();
+    let mut bucketer = DayBucketer::new(day::day_start());
+    let mut current_day: Option<usize> = None;
+    let mut failed_day = None;
+    let mut failures = 0;
+    let mut failure = None;
+    let mut done = 0;
+    let mut by_symbol: BTreeMap<String, Tally> = BTreeMap::new();
+    let mut failed_in_a_row = 0;
+    let mut aborted = None;
+    health.set_ready();
+    systemd::start(&health);
+    loop {
+        if let Some(max) = limits.max_elapsed_mins {
+            if started.elapsed() >= Duration::from_secs(max * 60) {
+                aborted = Some(format!("ran for {} min", max));
+                break;
+            }
+        }
+        // keep the network busy while earlier responses are bucketed and written
+        while in_flight.len() < adaptive.concurrency() {
+            if let Some(window) = windows.next(adaptive.window_rows()) {
+                let job = windows.days[window.day].job.clone();
+                in_flight.push_back(fetcher.fetch(job, window));
+                continue;
+            }
+            if claimed_all {
+                break;
+            }
+            match queue.claim(until_day).await? {
+                Some(job) => {
+                    tracing::info!("claimed {} {} {}", job.symbol, job.interval, job.day);
+                    let range = DayRange::new(job, until_ms)?;
+                    if run.vision && range.is_full_day() {
+                        if let Some(rows) = fetcher.fetch_vision(&range).await {
+                            let complete =
+                                finish_day(&mut writer, &queue, &fetcher, &range, rows, run)
+                                    .await?;
+                            done += complete as usize;
+                            by_symbol.entry(range.job.symbol.clone()).or_default().done +=
+                                complete as usize;
+                            continue;
+                        }
+                    }
+                    windows.push(range);
+                }
+                None => claimed_all = true,
+            }
+        }
+        let Some((window, resp)) = in_flight.next().await else {
+            break;
+        };
+        health.progress();
+
+        if let Some(day) = current_day.filter(|day| *day != window.day) {
+            // start next day
+            if failed_day != Some(day) {
+                let range = &windows.days[day];
+                let rows = bucketer.take(range.job.day);
+                let complete = finish_day(&mut writer, &queue, &fetcher, range, rows, run).await?;
+                done += complete as usize;
+                by_symbol.entry(range.job.symbol.clone()).or_default().done += complete as usize;
+                failed_in_a_row = 0;
+            }
+        }
+        current_day = Some(window.day);
+        if failed_day == Some(window.day) {
+            continue;
+        }
+        match resp {
+            Ok((mut resp, obs)) => {
+                adaptive.observe(&obs);
+                resp.retain(|r| r.open_time >= window.start_ms && r.open_time <= window.end_ms);
+                bucketer.push(resp);
+                tracing::info!("candles held: {}", bucketer.len());
+            }
+            Err(e) => {
+                // leave the day for a later attempt, the others can still finish
+                let job = &windows.days[window.day].job;
+                tracing::warn!(
+                    "{} {} {} failed: {:#}",
+                    job.symbol,
+                    job.interval,
+                    job.day,
+                    e
+                );
+                report::job_failed(job, window.start_ms, window.end_ms, &e);
+                queue.mark_failed(job, &format!("{:#}", e)).await?;
+                by_symbol.entry(job.symbol.clone()).or_default().failed += 1;
+                bucketer.take(job.day);
+                windows.skip_day(window.day);
+                failed_day = Some(window.day);
+                failures += 1;
+                failed_in_a_row += 1;
+                if let Some(class) = Failure::of(&e) {
+                    failure = Some(failure.map_or(class, |f: Failure| f.min(class)));
+                }
+                if limits
+                    .max_failed_days
+                    .is_some_and(|max| failed_in_a_row >= max)
+                {
+                    aborted = Some(format!("{} day(s) in a row failed", failed_in_a_row));
+                    break;
+                }
+            }
+        }
+    }
+    if let Some(reason) = aborted {
+        // stop fetching; whatever is claimed but not finished goes back
+        drop(in_flight);
+        let first = current_day.map_or(0, |day| day + usize::from(failed_day == Some(day)));
+        let unfinished = &windows.days[first..];
+        for range in unfinished {
+            queue.release(&range.job).await?;
+        }
+        writer.finish().await?;
+        log_tally(&by_symbol);
+        return Err(anyhow!(
+            "aborted after {}: {} day(s) done, {} failed, {} released unfinished; run again to continue",
+            reason,
+            done,
+            failures,
+            unfinished.len()
+        )
+        .context(failure.unwrap_or(Failure::Partial)));
+    }
+    if let Some(day) = current_day.filter(|day| failed_day != Some(*day)) {
+        // write last time and exit
+        let range = &windows.days[day];
+        let rows = bucketer.take(range.job.day);
+        let complete = finish_day(&mut writer, &queue, &fetcher, range, rows, run).await?;
+        by_symbol.entry(range.job.symbol.clone()).or_default().done += complete as usize;
+    }
+
+    writer.finish().await?;
+    log_tally(&by_symbol);
+    if failures > 0 {
+        return Err(
+            anyhow!("{} day(s) failed, run again to retry them", failures)
+                .context(failure.unwrap_or(Failure::Partial)),
+        );
+    }
+    Ok(())
+}
+
+/// Days a run finished or failed for one symbol.
+#[derive(Default)]
+struct Tally {
+    done: usize,
+    failed: usize,
+}
+
+/// Reports each symbol on its own, so one that keeps failing stands out.
+fn log_tally(by_symbol: &BTreeMap<String, Tally>) {
+    for (symbol, tally) in by_symbol {
+        if tally.failed > 0 {
+            tracing::warn!(
+                "{}: {} day(s) done, {} failed",
+                symbol,
+                tally.done,
+                tally.failed
+            );
+        } else {
+            tracing::info!("{}: {} day(s) done", symbol, tally.done);
+        }
+    }
+}
+
+/// Writes a finished day, filtered to the session, and returns whether it
+/// completed its job. Only full days do; a day cut short by the requested
+/// range is written but goes back to pending.
+async fn finish_day(
+    writer: &mut DailyWriter,
+    queue: &Queue,
+    fetcher: &Fetcher,
+    range: &DayRange,
+    mut rows: Vec<Kline>,
+    run: &RunArgs,
+) -> Result<bool> {
+    if run.refetch_gaps {
+        fill_gaps(fetcher, range, &mut rows).await;
+    }
+    let expected = (range.end_ms - range.start_ms) / range.step_ms + 1;
+    fetcher.progress().gaps(
+        &range.job,
+        (expected as u64).saturating_sub(rows.len() as u64),
+    );
+    if let Some(session) = &run.session {
+        rows.retain(|r| session.contains(r.open_time));
+    }
+    let complete = range.is_full_day();
+    if !complete && rows.is_empty() {
+        queue.release(&range.job).await?;
+        return Ok(false);
+    }
+    writer
+        .write(DayBatch {
+            job: range.job.clone(),
+            rows,
+            complete,
+        })
+        .await?;
+    Ok(complete)
+}
+
+/// Requests the candles missing between `rows` once more, which a busy
+/// exchange sometimes leaves out of a response; real downtime stays missing.
+/// Failures only cost the candles they were after.
+async fn fill_gaps(fetcher: &Fetcher, range: &DayRange, rows: &mut Vec<Kline>) {
+    let step_ms = range.step_ms;
+    let mut windows = Vec::new();
+    let mut expected_ms = range.start_ms;
+    for open_time in rows.iter().map(|r| r.open_time).chain([range.end_ms + 1]) {
+        let mut start_ms = expected_ms;
+        while start_ms < open_time && windows.len() < MAX_REFETCH_WINDOWS {
+            let end_ms = (start_ms + i64::from(MAX_WINDOW_ROWS) * step_ms).min(open_time) - 1;
+            windows.push(Window {
+                day: 0,
+                start_ms,
+                end_ms,
+            });
+            start_ms = end_ms + 1;
+        }
+        expected_ms = expected_ms.max(open_time + step_ms);
+    }
+    if windows.is_empty() {
+        return;
+    }
+    let mut found = Vec::new();
+    for window in windows {
+        match fetcher.fetch(range.job.clone(), window).await.1 {
+            Ok((resp, _)) => found.extend(
+                resp.into_iter()
+                    .filter(|r| (window.start_ms..=window.end_ms).contains(&r.open_time)),
+            ),
+            Err(e) => tracing::warn!(
+                "refetching {}-{} of {} {}: {:#}",
+                window.start_ms,
+                window.end_ms,
+                range.job.symbol,
+                range.job.day,
+                e
+            ),
+        }
+    }
+    let job = &range.job;
+    tracing::info!(
+        "refetched {} missing candle(s) of {} {} {}",
+        found.len(),
+        job.symbol,
+        job.interval,
+        job.day
+    );
+    if !found.is_empty() {
+        rows.extend(found);
+        rows.sort_by_key(|r| r.open_time);
+        rows.dedup_by_key(|r| r.open_time);
+    }
+}
+
+/// A symbol's current day under `--follow`.
+struct LiveDay {
+    job: Job,
+    /// Every closed candle of the day so far, in order
+    rows: Vec<Kline>,
+    step_ms: i64,
+    /// Whether rows arrived since the file was last written
+    dirty: bool,
+}
+
+impl LiveDay {
+    /// Open time of the next candle due.
+    fn next_ms(&self) -> i64 {
+        self.rows
+            .last()
+            .map_or(day_start_ms(self.job.day), |r| r.open_time + self.step_ms)
+    }
+
+    /// Adds a closed candle, handing back the day it ended if it begins the
+    /// next one. Candles already held are ignored.
+    fn push(&mut self, row: Kline) -> Option<(Job, Vec<Kline>)> {
+        if row.open_time < self.next_ms() {
+            return None;
+        }
+        let day = day::day_of(row.open_time);
+        let ended = (day != self.job.day).then(|| {
+            let job = Job {
+                day,
+                ..self.job.clone()
+            };
+            (
+                std::mem::replace(&mut self.job, job),
+                std::mem::take(&mut self.rows),
+            )
+        });
+        self.rows.push(row);
+        self.dirty = true;
+        ended
+    }
+}
+
+/// Keeps each symbol's current day up to date from the kline stream once
+/// the backfill has caught up. Days are rewritten every `--flush-secs` and
+/// written complete when the next one begins; candles missed while the
+/// stream was down are fetched over REST after reconnecting.
+async fn follow(
+    args: &DownloadArgs,
+    symbols: &[String],
+    queue: Queue,
+    health: Health,
+    progress: Progress,
+) -> Result<()> {
+    let run = &args.run;
+    let step_ms = interval_ms(&args.interval).expect("listed intervals have a length");
+    let now_ms = Utc::now().timestamp_millis();
+    let today = day::day_of(now_ms);
+    let jobs: Vec<Job> = symbols
+        .iter()
+        .map(|symbol| Job {
+            symbol: symbol.clone(),
+            interval: args.interval.clone(),
+            day: today,
+        })
+        .collect();
+    queue.enqueue(&jobs).await?;
+    // pick up today's files where the backfill left them
+    let manifest = Manifest::load(&run.dataset_dir())?;
+    let (manifest, mut days) = tokio::task::spawn_blocking(move || {
+        let reader = DatasetReader::new(&manifest);
+        let days = jobs
+            .into_iter()
+            .map(|job| {
+                let file = manifest.files().find(|(_, f)| {
+                    f.symbol == job.symbol
+                        && f.interval == job.interval
+                        && f.day == job.day
+                        && f.last_day.is_none()
+                        && f.hour.is_none()
+                });
+                let mut rows = match file {
+                    Some((key, entry)) => reader.read(key, entry)?,
+                    None => Vec::new(),
+                };
+                // the backfill may have caught the last candle still open
+                rows.retain(|r| r.close_time < now_ms);
+                let live = LiveDay {
+                    job,
+                    rows,
+                    step_ms,
+                    dirty: false,
+                };
+                Ok((live.job.symbol.clone(), live))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        anyhow::Ok((manifest, days))
+    })
+    .await??;
+    let space = SpaceGuard::new(run.dataset_dir(), run.min_free_mb << 20, true);
+    let mut writer = DailyWriter::spawn(
+        queue.clone(),
+        run.target(manifest, space, Retention::default()).await?,
+        run.durability,
+        health.clone(),
+        progress.clone(),
+    );
+    let fetcher = Fetcher::new(run, progress)?;
+    let mut flush = tokio::time::interval(Duration::from_secs(args.flush_secs));
+    let mut attempt = 0;
+    loop {
+        if attempt > 0 {
+            let delay = backoff(attempt - 1);
+            tracing::info!("reconnecting in {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+        attempt += 1;
+        let mut stream = match KlineStream::connect(symbols, &args.interval).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("{:#}", e);
+                continue;
+            }
+        };
+        // what closed before the stream was up
+        let until_ms = Utc::now().timestamp_millis() - step_ms;
+        let mut caught_up = Ok(());
+        for live in days.values_mut() {
+            match catch_up(&fetcher, live, until_ms).await {
+                Ok(rows) => append(&mut writer, &queue, live, rows, run).await?,
+                Err(e) => {
+                    caught_up = Err(e);
+                    break;
+                }
+            }
+        }
+        if let Err(e) = caught_up {
+            tracing::warn!("catching up: {:#}", e);
+            continue;
+        }
+        attempt = 0;
+        let error = loop {
+            tokio::select! {
+                next = stream.next() => {
+                    health.progress();
+                    let (symbol, row) = match next {
+                        Ok(Some(candle)) => candle,
+                        Ok(None) => break anyhow!("the exchange closed the stream"),
+                        Err(e) => break e,
+                    };
+                    let Some(live) = days.get_mut(&symbol) else {
+                        continue;
+                    };
+                    let mut rows = Vec::new();
+                    if row.open_time > live.next_ms() {
+                        // candles the stream skipped
+                        match catch_up(&fetcher, live, row.open_time - 1).await {
+                            Ok(missed) => rows = missed,
+                            Err(e) => break e,
+                        }
+                    }
+                    rows.push(row);
+                    append(&mut writer, &queue, live, rows, run).await?;
+                }
+                _ = flush.tick() => {
+                    for live in days.values_mut().filter(|live| live.dirty) {
+                        live.dirty = false;
+                        write_live(&mut writer, live.job.clone(), live.rows.clone(), false, run)
+                            .await?;
+                    }
+                }
+            }
+        };
+        tracing::warn!("kline stream: {:#}", error);
+    }
+}
+
+/// Fetches the candles opening from the next one due up to `until_ms` over
+/// REST, whichever days they fall on.
+async fn catch_up(fetcher: &Fetcher, live: &LiveDay, until_ms: i64) -> Result<Vec<Kline>> {
+    let mut rows = Vec::new();
+    let mut start_ms = live.next_ms();
+    while start_ms <= until_ms {
+        let end_ms = (start_ms + i64::from(MAX_WINDOW_ROWS) * live.step_ms - 1).min(until_ms);
+        let window = Window {
+            day: 0,
+            start_ms,
+            end_ms,
+        };
+        let (resp, _) = fetcher.fetch(live.job.clone(), window).await.1?;
+        rows.extend(
+            resp.into_iter()
+                .filter(|r| (start_ms..=end_ms).contains(&r.open_time)),
+        );
+        start_ms = end_ms + 1;
+    }
+    if !rows.is_empty() {
+        tracing::info!(
+            "caught up {} candle(s) of {} {} over REST",
+            rows.len(),
+            live.job.symbol,
+            live.job.interval
+        );
+    }
+    Ok(rows)
+}
+
+/// Adds candles to a live day, writing out each day they end.
+async fn append(
+    writer: &mut DailyWriter,
+    queue: &Queue,
+    live: &mut LiveDay,
+    rows: Vec<Kline>,
+    run: &RunArgs,
+) -> Result<()> {
+    for row in rows {
+        let Some((job, rows)) = live.push(row) else {
+            continue;
+        };
+        tracing::info!("{} {} {} is over", job.symbol, job.interval, job.day);
+        queue.enqueue(std::slice::from_ref(&live.job)).await?;
+        write_live(writer, job, rows, true, run).await?;
+    }
+    Ok(())
+}
+
+/// Writes a live day filtered to the session; only a day that is over is
+/// complete.
+async fn write_live(
+    writer: &mut DailyWriter,
+    job: Job,
+    mut rows: Vec<Kline>,
+    complete: bool,
+    run: &RunArgs,
+) -> Result<()> {
+    if let Some(session) = &run.session {
+        rows.retain(|r| session.contains(r.open_time));
+    }
+    if !complete && rows.is_empty() {
+        return Ok(());
+    }
+    writer
+        .write(DayBatch {
+            job,
+            rows,
+            complete,
+        })
+        .await
+}
+
+/// Everything requests share over a run.
+pub(crate) struct Fetcher {
+    client: reqwest::Client,
+    market: Market,
+    throttle: Throttle,
+    cache: Option<ResponseCache>,
+    archives: Option<ArchiveCache>,
+    /// Retries left in the run's budget
+    retries: AtomicU32,
+    max_window_attempts: u32,
+    /// Requests hold off until then once the weight limit is nearly used up
+    paused_until_ms: AtomicI64,
+    progress: Progress,
+}
+
+/// How long a rate-limited response asked us to wait.
+#[derive(Debug)]
+struct RetryAfter(Duration);
+
+impl std::fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "retry after {:?}", self.0)
+    }
+}
+
+/// Exponential backoff for the `attempt`th retry, from half to all of the
+/// delay so that parallel requests don't retry in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let delay = RETRY_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_RETRY_DELAY);
+    // a randomly keyed hasher is random enough for jitter
+    let random = RandomState::new().build_hasher().finish();
+    delay / 2 + delay.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+impl Fetcher {
+    fn new(run: &RunArgs, progress: Progress) -> Result<Self> {
+        Ok(Self {
+            client: crate::client::build()?,
+            market: market(),
+            throttle: Throttle::new(run.max_bandwidth),
+            cache: ResponseCache::new(run.record.clone(), run.replay_cache.clone())?,
+            archives: run
+                .cache_dir
+                .clone()
+                .map(|dir| ArchiveCache::new(dir, run.cache_max_mb << 20))
+                .transpose()?,
+            retries: AtomicU32::new(run.limits.max_retries),
+            max_window_attempts: run.limits.max_window_attempts,
+            paused_until_ms: AtomicI64::new(0),
+            progress,
+        })
+    }
+
+    /// Requests to `market` with the default limits and nothing cached,
+    /// for [`crate::KlineClient`].
+    pub(crate) fn for_market(market: Market) -> Result<Self> {
+        Ok(Self {
+            client: crate::client::build()?,
+            market,
+            throttle: Throttle::new(None),
+            cache: None,
+            archives: None,
+            retries: AtomicU32::new(MAX_RETRIES),
+            max_window_attempts: MAX_WINDOW_ATTEMPTS,
+            paused_until_ms: AtomicI64::new(0),
+            progress: Progress::default(),
+        })
+    }
+
+    pub(crate) fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Fetches a window, see [`Fetcher::retrying`].
+    async fn fetch(&self, job: Job, window: Window) -> (Window, Result<(Vec<Kline>, Observation)>) {
+        let what = format!("{}-{}", window.start_ms, window.end_ms);
+        let result = self
+            .retrying(&what, || self.fetch_window(&job, window))
+            .await;
+        (window, result)
+    }
+
+    /// The body of an API response, see [`Fetcher::retrying`].
+    pub(crate) async fn get(&self, url: &str) -> Result<Vec<u8>> {
+        self.retrying(url, || async { Ok(self.request(url).await?.0) })
+            .await
+    }
+
+    /// Runs `attempt`, retrying transient errors with backoff while the
+    /// run's retry budget lasts, and rate limits after the wait the exchange
+    /// asks for, up to `--max-window-attempts` in all.
+    async fn retrying<T, F: Future<Output = Result<T>>>(
+        &self,
+        what: &str,
+        mut attempt_once: impl FnMut() -> F,
+    ) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_weight().await;
+            let result = attempt_once().await;
+            let Err(e) = &result else {
+                return result;
+            };
+            let (delay, charged) = match (Failure::of(e), e.downcast_ref::<RetryAfter>()) {
+                (Some(Failure::RateLimited), Some(RetryAfter(after)))
+                    if *after <= MAX_RETRY_AFTER =>
+                {
+                    (*after, false)
+                }
+                // server errors, timeouts and garbled bodies
+                (None | Some(Failure::Validation), _) => (backoff(attempt), true),
+                _ => return result,
+            };
+            if attempt + 1 >= self.max_window_attempts {
+                return result;
+            }
+            if charged
+                && self
+                    .retries
+                    .fetch_update(Relaxed, Relaxed, |n| n.checked_sub(1))
+                    .is_err()
+            {
+                return result;
+            }
+            self.progress.retried();
+            tracing::warn!("retrying {} in {:?}: {:#}", what, delay, e);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Waits out a pause set when the weight limit was nearly used up; the
+    /// exchange resets it every minute.
+    async fn wait_for_weight(&self) {
+        let wait_ms = self.paused_until_ms.load(Relaxed) - Utc::now().timestamp_millis();
+        if wait_ms > 0 {
+            tracing::info!("weight limit nearly used up, waiting {} ms", wait_ms);
+            tokio::time::sleep(Duration::from_millis(wait_ms as u64)).await;
+        }
+    }
+
+    async fn fetch_window(&self, job: &Job, window: Window) -> Result<(Vec<Kline>, Observation)> {
+        let url = format!(
+            "{}/klines?startTime={}&endTime={}&limit={}&symbol={}&interval={}",
+            self.market.api(),
+            window.start_ms,
+            window.end_ms,
+            MAX_WINDOW_ROWS,
+            job.symbol,
+            job.interval
+        );
+        let key = format!(
+            "{}-{}-{}-{}-{}.json",
+            job.symbol, job.interval, window.start_ms, window.end_ms, MAX_WINDOW_ROWS
+        );
+        let started = Instant::now();
+        if let Some(body) = self.cache_get(&key).await? {
+            let mut resp = kline::parse(&body)
+                .with_context(|| format!("cached response {}", key))
+                .context(Failure::Validation)?;
+            kline::stamp(&mut resp, Source::Rest);
+            let obs = Observation {
+                used_weight: None,
+                latency: started.elapsed(),
+            };
+            tracing::info!("cached: {}, response length: {}", key, resp.len());
+            return Ok((resp, obs));
+        }
+        let (body, used_weight) = self.request(&url).await?;
+        let mut resp = kline::parse(&body).context(Failure::Validation)?;
+        kline::stamp(&mut resp, Source::Rest);
+        if let Some(cache) = &self.cache {
+            cache.put(&key, &body).await?;
+        }
+        let obs = Observation {
+            used_weight,
+            latency: started.elapsed(),
+        };
+        tracing::info!("url: {}, response length: {}", url, resp.len());
+        Ok((resp, obs))
+    }
+
+    /// Sends one API request and returns its body and the weight used,
+    /// pausing later requests when the weight limit is nearly used up.
+    async fn request(&self, url: &str) -> Result<(Vec<u8>, Option<u32>)> {
+        let resp = self.client.get(url).send().await?;
+        let used_weight: Option<u32> = resp
+            .headers()
+            .get("x-mbx-used-weight-1m")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        if let Some(weight) = used_weight {
+            self.progress.used_weight(weight);
+        }
+        if used_weight
+            .is_some_and(|w| f64::from(w) >= f64::from(self.market.weight_limit()) * WEIGHT_PAUSE)
+        {
+            let next_minute_ms = (Utc::now().timestamp_millis() / 60_000 + 1) * 60_000;
+            self.paused_until_ms.fetch_max(next_minute_ms, Relaxed);
+        }
+        let retry_after = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .map(Duration::from_secs);
+        let mut resp = match (resp.error_for_status(), retry_after) {
+            (Ok(resp), _) => resp,
+            (Err(e), Some(after)) => return Err(anyhow::Error::new(e).context(RetryAfter(after))),
+            (Err(e), None) => return Err(e.into()),
+        };
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            self.throttle.consume(chunk.len()).await;
+            body.extend_from_slice(&chunk);
+        }
+        Ok((body, used_weight))
+    }
+
+    /// A whole day from its data.binance.vision archive, or None when the
+    /// archive isn't published yet or can't be used, leaving the day to the
+    /// API.
+    async fn fetch_vision(&self, range: &DayRange) -> Option<Vec<Kline>> {
+        let url = vision::day_url(self.market, &range.job);
+        let result = async {
+            let Some(data) = self.download(&url).await? else {
+                return Ok(None);
+            };
+            let checksum = self
+                .download(&format!("{}.CHECKSUM", url))
+                .await?
+                .ok_or_else(|| anyhow!("no checksum published"))?;
+            vision::check_sha256(&data, &String::from_utf8_lossy(&checksum))?;
+            let mut rows = vision::read_zip(&data)?;
+            rows.retain(|r| (range.start_ms..=range.end_ms).contains(&r.open_time));
+            kline::stamp(&mut rows, Source::Vision);
+            Ok::<_, anyhow::Error>(Some(rows))
+        }
+        .await;
+        match result {
+            Ok(Some(rows)) => {
+                tracing::info!("url: {}, rows: {}", url, rows.len());
+                Some(rows)
+            }
+            Ok(None) => {
+                tracing::info!("{} isn't published yet", url);
+                None
+            }
+            Err(e) => {
+                tracing::warn!("{}: {:#}, using the API instead", url, e);
+                None
+            }
+        }
+    }
+
+    /// The body at `url`, or None if there is nothing there. With an
+    /// archive cache, a copy already held is only downloaded again if the
+    /// server says it changed.
+    async fn download(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        let cached = match &self.archives {
+            Some(archives) => archives.get(url).await?,
+            None => None,
+        };
+        let mut request = self.client.get(url);
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.meta.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.meta.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let resp = request.send().await?;
+        match resp.status() {
+            reqwest::StatusCode::NOT_FOUND => return Ok(None),
+            reqwest::StatusCode::NOT_MODIFIED if cached.is_some() => {
+                tracing::debug!("{} unchanged, using the cached copy", url);
+                return Ok(cached.map(|c| c.body));
+            }
+            _ => {}
+        }
+        let mut resp = resp.error_for_status()?;
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let meta = ArchiveMeta {
+            url: url.to_string(),
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        };
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            self.throttle.consume(chunk.len()).await;
+            body.extend_from_slice(&chunk);
+        }
+        // without a validator there is nothing to ask about next time
+        if let Some(archives) = &self.archives {
+            if meta.etag.is_some() || meta.last_modified.is_some() {
+                if let Err(e) = archives.put(&meta, &body).await {
+                    tracing::warn!("caching {}: {:#}", url, e);
+                }
+            }
+        }
+        Ok(Some(body))
+    }
+
+    async fn cache_get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match &self.cache {
+            Some(cache) => cache.get(key).await,
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: i64 = 86_400_000;
+
+    fn range(day: i64, start_ms: i64, end_ms: i64) -> DayRange {
+        DayRange {
+            job: Job {
+                symbol: SYMBOL.to_string(),
+                interval: "1m".to_string(),
+                day: NaiveDate::from_ymd_opt(2024, 6, 1 + day as u32).unwrap(),
+            },
+            start_ms,
+            end_ms,
+            step_ms: 60_000,
+        }
+    }
+
+    fn drain(windows: &mut Windows, rows: u32) -> Vec<(usize, i64, i64)> {
+        std::iter::from_fn(|| windows.next(rows))
+            .map(|w| (w.day, w.start_ms, w.end_ms))
+            .collect()
+    }
+
+    #[test]
+    fn windows_stop_at_the_end_of_each_day() {
+        let mut windows = Windows::default();
+        windows.push(range(0, 0, DAY_MS - 1));
+        windows.push(range(1, DAY_MS, 2 * DAY_MS - 1));
+        let got = drain(&mut windows, 1000);
+        // 1440 minutes a day: a full window and the 440 left before midnight
+        assert_eq!(
+            got,
+            [
+                (0, 0, 60_000_000 - 1),
+                (0, 60_000_000, DAY_MS - 1),
+                (1, DAY_MS, DAY_MS + 60_000_000 - 1),
+                (1, DAY_MS + 60_000_000, 2 * DAY_MS - 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_of_a_day_cut_short_end_with_it() {
+        let mut windows = Windows::default();
+        windows.push(range(0, 0, 90 * 60_000 - 1));
+        assert_eq!(
+            drain(&mut windows, 60),
+            [(0, 0, 3_600_000 - 1), (0, 3_600_000, 90 * 60_000 - 1)]
+        );
+        assert!(windows.next(60).is_none());
+    }
+
+    #[test]
+    fn a_skipped_day_yields_no_more_windows() {
+        let mut windows = Windows::default();
+        windows.push(range(0, 0, DAY_MS - 1));
+        windows.push(range(1, DAY_MS, 2 * DAY_MS - 1));
+        assert_eq!(windows.next(1000).map(|w| w.day), Some(0));
+        windows.skip_day(0);
+        // skipping a day not in progress changes nothing
+        windows.skip_day(0);
+        let got = drain(&mut windows, 1000);
+        assert_eq!(got.first(), Some(&(1, DAY_MS, DAY_MS + 60_000_000 - 1)));
+        assert!(got.iter().all(|(day, _, _)| *day == 1));
+    }
+
+    #[test]
+    fn windows_resume_with_days_pushed_later() {
+        let mut windows = Windows::default();
+        assert!(windows.next(1000).is_none());
+        windows.push(range(0, 0, 10 * 60_000 - 1));
+        assert_eq!(drain(&mut windows, 1000), [(0, 0, 10 * 60_000 - 1)]);
+        windows.push(range(1, DAY_MS, DAY_MS + 5 * 60_000 - 1));
+        assert_eq!(
+            drain(&mut windows, 1000),
+            [(1, DAY_MS, DAY_MS + 5 * 60_000 - 1)]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Days, Utc};
+
+use crate::day::{self, day_of};
+use crate::download::{interval_ms, OUT_DIR};
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::kline::Kline;
+use crate::manifest::{FileEntry, Manifest};
+use crate::resample::resample_in_days;
+use crate::writer::{sync_dir, Durability};
+
+#[derive(clap::Args)]
+pub(crate) struct DownsampleArgs {
+    /// Dataset to tier, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    /// Days of 1s files to keep as they are, counting back from today
+    #[arg(long = "keep-1s-days")]
+    keep_days: u64,
+    /// Interval older files are downsampled to, one that divides a day
+    /// evenly such as `5m` or `1h`; buckets are counted from the day start
+    #[arg(long = "else", default_value = "1m")]
+    to: String,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+const SOURCE_INTERVAL: &str = "1s";
+const DAY_MS: i64 = 86_400_000;
+
+pub(crate) async fn run(args: DownsampleArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || downsample(&args)).await?
+}
+
+/// Replaces complete 1s files that ended more than `keep_days` ago with
+/// their resampled rows, named and partitioned like the original. The
+/// original is only deleted after the new file reads back with every bucket
+/// and the same trade count.
+fn downsample(args: &DownsampleArgs) -> Result<()> {
+    let step_ms = target_ms(&args.to)?;
+    let cutoff = day_of(Utc::now().timestamp_millis()) - Days::new(args.keep_days);
+    let mut manifest = Manifest::load(&args.dir)?;
+    let files: Vec<(String, FileEntry)> = manifest
+        .files()
+        .filter(|(_, e)| {
+            e.interval == SOURCE_INTERVAL
+                && e.complete
+                && e.archive.is_none()
+                && e.last_day.unwrap_or(e.day) < cutoff
+        })
+        .map(|(key, entry)| (key.to_string(), entry.clone()))
+        .collect();
+
+    let (mut rows_before, mut rows_after) = (0, 0);
+    for (key, entry) in &files {
+        let source = args.dir.join(key);
+        let Some(format) = Format::of(&source) else {
+            continue;
+        };
+        let rows = format
+            .read(&source, manifest.style())
+            .with_context(|| format!("reading {:?}", source))?;
+        if rows.len() != entry.rows {
+            return Err(anyhow!(
+                "{:?} has {} rows, the manifest recorded {}",
+                source,
+                rows.len(),
+                entry.rows
+            )
+            .context(Failure::Validation));
+        }
+        let coarse = resample_in_days(&rows, step_ms, day::day_start())
+            .with_context(|| format!("resampling {:?}", source))?;
+
+        let prefix = format!("{}-{}-", entry.symbol, entry.interval);
+        let file_name = source.file_name().unwrap_or_default().to_string_lossy();
+        let name = file_name.replacen(&prefix, &format!("{}-{}-", entry.symbol, args.to), 1);
+        if name == file_name {
+            return Err(anyhow!("{} isn't named {}*, can't rename it", key, prefix));
+        }
+        let path = manifest.path_for(&entry.symbol, &args.to, entry.day, &name);
+        std::fs::create_dir_all(path.parent().unwrap_or(&args.dir))?;
+        let tmp = path.with_extension(format!("{}.tmp", format.extension()));
+        format.write(&tmp, &coarse, manifest.style(), args.durability)?;
+        let written = format.read(&tmp, manifest.style())?;
+        let trades = |rows: &[Kline]| rows.iter().map(|r| r.num_of_trades).sum::<u64>();
+        if written.len() != coarse.len() || trades(&written) != trades(&rows) {
+            std::fs::remove_file(&tmp)?;
+            return Err(
+                anyhow!("{:?} doesn't match {:?} after downsampling", tmp, source)
+                    .context(Failure::Validation),
+            );
+        }
+        std::fs::rename(&tmp, &path)?;
+        if args.durability == Durability::Fsync {
+            sync_dir(path.parent().unwrap_or(&args.dir))?;
+        }
+
+        manifest.remove(key);
+        manifest.record(
+            &path,
+            FileEntry {
+                interval: args.to.clone(),
+                rows: coarse.len(),
+                bytes: std::fs::metadata(&path)?.len(),
+                resampled_from: Some(entry.interval.clone()),
+                ..entry.clone()
+            },
+            args.durability,
+        )?;
+        std::fs::remove_file(&source)?;
+        rows_before += rows.len();
+        rows_after += coarse.len();
+        tracing::info!(
+            "downsampled {:?} -> {:?}, {} -> {} rows",
+            source,
+            path,
+            rows.len(),
+            coarse.len()
+        );
+    }
+    tracing::info!(
+        "downsampled {} file(s) older than {}, {} -> {} rows",
+        files.len(),
+        cutoff,
+        rows_before,
+        rows_after
+    );
+    Ok(())
+}
+
+/// Length of the `--else` interval, which must split a day into whole
+/// buckets so that every one of them stays inside its day's file.
+fn target_ms(to: &str) -> Result<i64> {
+    interval_ms(to)
+        .filter(|ms| *ms > 1000 && *ms <= DAY_MS && DAY_MS % ms == 0)
+        .ok_or_else(|| {
+            anyhow!(
+                "can't downsample 1s to {:?}; the interval must divide a day evenly",
+                to
+            )
+        })
+        .context(Failure::Config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_must_divide_a_day() {
+        assert_eq!(target_ms("1m").unwrap(), 60_000);
+        assert_eq!(target_ms("1d").unwrap(), DAY_MS);
+        for to in ["1s", "7m", "3d", "1w", "1M", "nonsense"] {
+            assert!(target_ms(to).is_err(), "{}", to);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+
+/// Exit codes, listed in `--help`. 0 is success, 1 anything unclassified.
+pub(crate) const HELP: &str = "\
+Exit codes:
+  0  success
+  1  other error
+  2  invalid arguments or configuration
+  3  rejected by the exchange as unauthorized
+  4  rate limited or IP banned by the exchange
+  5  a response or stored file failed validation
+  6  partial success, some days failed and can be retried";
+
+/// A failure class scripts can branch on. Attach it with
+/// `.context(Failure::..)`; errors from the exchange are classified by
+/// their HTTP status without it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Failure {
+    Config = 2,
+    Auth = 3,
+    RateLimited = 4,
+    Validation = 5,
+    Partial = 6,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Failure::Config => "invalid configuration",
+            Failure::Auth => "unauthorized",
+            Failure::RateLimited => "rate limited",
+            Failure::Validation => "validation failed",
+            Failure::Partial => "partial success",
+        })
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl Failure {
+    /// The outermost class found in the error chain.
+    pub(crate) fn of(e: &anyhow::Error) -> Option<Failure> {
+        if let Some(failure) = e.downcast_ref::<Failure>() {
+            return Some(*failure);
+        }
+        e.chain().find_map(|cause| {
+            let e = cause.downcast_ref::<reqwest::Error>()?;
+            if e.is_decode() {
+                return Some(Failure::Validation);
+            }
+            match e.status()?.as_u16() {
+                // bad symbol or interval
+                400 => Some(Failure::Config),
+                401 => Some(Failure::Auth),
+                // 403 is Binance's WAF limit, 418 an IP ban after ignoring 429s
+                403 | 418 | 429 => Some(Failure::RateLimited),
+                _ => None,
+            }
+        })
+    }
+
+    pub(crate) fn exit_code(e: &anyhow::Error) -> u8 {
+        Failure::of(e).map_or(1, |f| f as u8)
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use rust_xlsxwriter::{Format as CellFormat, Workbook};
+
+use crate::day::{day_of, day_start_ms, next_day_ms};
+use crate::download::{INTERVAL, OUT_DIR};
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::kline::{timestamp, Kline};
+use crate::manifest::Manifest;
+use crate::reader::DatasetReader;
+
+/// Rows a worksheet can hold below its header.
+const SHEET_ROWS: usize = 1_048_575;
+
+const HEADER: [&str; 12] = [
+    "symbol",
+    "open_time",
+    "open",
+    "high",
+    "low",
+    "close",
+    "volume",
+    "close_time",
+    "quote_volume",
+    "trades",
+    "taker_buy_base_vol",
+    "taker_buy_quote_vol",
+];
+
+#[derive(clap::Args)]
+pub(crate) struct ExportArgs {
+    /// Dataset to export from, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Xlsx)]
+    format: ExportFormat,
+    /// Symbols to export, comma-separated; all of them if left out
+    #[arg(long, value_delimiter = ',')]
+    symbols: Vec<String>,
+    #[arg(long, default_value = INTERVAL)]
+    interval: String,
+    /// First day to export, e.g. 2024-06-01
+    #[arg(long)]
+    from: NaiveDate,
+    /// Last day to export, inclusive
+    #[arg(long)]
+    to: NaiveDate,
+    /// What each worksheet holds
+    #[arg(long, value_enum, default_value_t = SheetPer::Day)]
+    sheet_per: SheetPer,
+    /// Refuse to export more rows than this; spreadsheets get slow long
+    /// before Excel's limit of about a million rows per sheet
+    #[arg(long, default_value_t = 200_000)]
+    max_rows: usize,
+    /// Workbook to write
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum ExportFormat {
+    /// An Excel workbook
+    Xlsx,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum SheetPer {
+    Day,
+    Symbol,
+}
+
+pub(crate) async fn run(args: ExportArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || export(&args)).await?
+}
+
+/// Writes the range into a workbook for people to open in a spreadsheet,
+/// prices and volumes as numbers and times as UTC text.
+fn export(args: &ExportArgs) -> Result<()> {
+    let ExportFormat::Xlsx = args.format;
+    if args.to < args.from {
+        return Err(
+            anyhow!("--to {} is before --from {}", args.to, args.from).context(Failure::Config)
+        );
+    }
+    let max_rows = args.max_rows.min(SHEET_ROWS);
+    let manifest = Manifest::load(&args.dir)?;
+    let reader = DatasetReader::new(&manifest);
+    let start_ms = day_start_ms(args.from);
+    let end_ms = next_day_ms(day_start_ms(args.to));
+
+    // sheet name -> (symbol, row), sorted once all files are read
+    let mut sheets: BTreeMap<String, Vec<(String, Kline)>> = BTreeMap::new();
+    let mut total = 0;
+    for (key, entry) in manifest.files() {
+        if entry.interval != args.interval
+            || !(args.symbols.is_empty() || args.symbols.contains(&entry.symbol))
+            || entry.last_day.unwrap_or(entry.day) < args.from
+            || entry.day > args.to
+            || Format::of(Path::new(key)).is_none()
+        {
+            continue;
+        }
+        for row in reader.read(key, entry)? {
+            if !(start_ms..end_ms).contains(&row.open_time) {
+                continue;
+            }
+            total += 1;
+            if total > max_rows {
+                return Err(anyhow!(
+                    "more than {} rows in {}..={}; narrow the range or raise --max-rows",
+                    max_rows,
+                    args.from,
+                    args.to
+                )
+                .context(Failure::Config));
+            }
+            let sheet = match args.sheet_per {
+                SheetPer::Day => day_of(row.open_time).to_string(),
+                SheetPer::Symbol => entry.symbol.clone(),
+            };
+            sheets
+                .entry(sheet)
+                .or_default()
+                .push((entry.symbol.clone(), row));
+        }
+    }
+    if sheets.is_empty() {
+        return Err(anyhow!("no rows in {}..={}", args.from, args.to).context(Failure::Config));
+    }
+
+    let mut workbook = Workbook::new();
+    let bold = CellFormat::new().set_bold();
+    for (name, mut rows) in sheets {
+        rows.sort_by(|(a, x), (b, y)| (x.open_time, a).cmp(&(y.open_time, b)));
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(&name)?;
+        for (col, title) in HEADER.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *title, &bold)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        for (i, (symbol, r)) in rows.iter().enumerate() {
+            let row = i as u32 + 1;
+            sheet.write_string(row, 0, symbol)?;
+            sheet.write_string(row, 1, timestamp(r.open_time))?;
+            let decimals = [
+                (2u16, r.open_price()),
+                (3, r.high()),
+                (4, r.low()),
+                (5, r.close()),
+                (6, r.volume()),
+                (8, r.quote_volume()),
+                (10, r.taker_buy_base_vol()),
+                (11, r.taker_buy_quote_vol()),
+            ];
+            for (col, text) in decimals {
+                let value: f64 = text
+                    .parse()
+                    .with_context(|| format!("invalid decimal {:?}", text))?;
+                sheet.write_number(row, col, value)?;
+            }
+            sheet.write_string(row, 7, timestamp(r.close_time))?;
+            sheet.write_number(row, 9, r.num_of_trades as f64)?;
+        }
+        sheet.autofit();
+    }
+    workbook
+        .save(&args.out)
+        .with_context(|| format!("writing {:?}", args.out))?;
+    tracing::info!("exported {} rows to {:?}", total, args.out);
+    Ok(())
+}
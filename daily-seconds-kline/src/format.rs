@@ -0,0 +1,660 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{ChunkReader, FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::{Row, RowAccessor};
+use parquet::schema::parser::parse_message_type;
+use serde::ser::{Error as _, Serialize, SerializeTuple, Serializer};
+
+use crate::exit::Failure;
+use crate::kline::{Kline, KlineRecord};
+use crate::writer::Durability;
+
+const WRITE_BUFFER_CAPACITY: usize = 1 << 20;
+
+/// Decimals stay strings so values round-trip exactly, unless written with
+/// `Numeric::F64`.
+const PARQUET_SCHEMA: &str = "
+message kline {
+    REQUIRED INT64 open_time (TIMESTAMP(MILLIS, true));
+    REQUIRED BINARY open_price (STRING);
+    REQUIRED BINARY high (STRING);
+    REQUIRED BINARY low (STRING);
+    REQUIRED BINARY close (STRING);
+    REQUIRED BINARY volume (STRING);
+    REQUIRED INT64 close_time (TIMESTAMP(MILLIS, true));
+    REQUIRED BINARY quote_volume (STRING);
+    REQUIRED INT64 num_of_trades (INTEGER(64, false));
+    REQUIRED BINARY taker_buy_base_vol (STRING);
+    REQUIRED BINARY taker_buy_quote_vol (STRING);
+    REQUIRED BINARY unused (STRING);
+}";
+
+const PARQUET_SCHEMA_F64: &str = "
+message kline {
+    REQUIRED INT64 open_time (TIMESTAMP(MILLIS, true));
+    REQUIRED DOUBLE open_price;
+    REQUIRED DOUBLE high;
+    REQUIRED DOUBLE low;
+    REQUIRED DOUBLE close;
+    REQUIRED DOUBLE volume;
+    REQUIRED INT64 close_time (TIMESTAMP(MILLIS, true));
+    REQUIRED DOUBLE quote_volume;
+    REQUIRED INT64 num_of_trades (INTEGER(64, false));
+    REQUIRED DOUBLE taker_buy_base_vol;
+    REQUIRED DOUBLE taker_buy_quote_vol;
+    REQUIRED BINARY unused (STRING);
+}";
+
+/// Appended to either schema by `Style::provenance`; empty for rows
+/// without provenance.
+const PROVENANCE_COLUMNS: &str = "
+    OPTIONAL BINARY exchange (STRING);
+    OPTIONAL BINARY source (STRING);
+    OPTIONAL INT64 ingest_time (TIMESTAMP(MILLIS, true));
+";
+
+/// How prices and volumes are written. CSV datasets start out as text,
+/// Parquet ones as DOUBLE columns that query engines read as numbers.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Numeric {
+    /// The exchange's decimal text, exact to the last digit; Parquet
+    /// stores it as UTF8 strings to be cast before any arithmetic
+    #[default]
+    Text,
+    /// 64-bit floats, Parquet DOUBLE columns: smaller and nothing to parse
+    /// downstream, but only about 15 significant digits survive, so large
+    /// quote volumes and tiny prices may round, and trailing zeros go
+    F64,
+}
+
+/// CSV field separator.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Delimiter {
+    #[default]
+    Comma,
+    /// What Excel expects in locales with a decimal comma
+    Semicolon,
+    Tab,
+}
+
+impl Delimiter {
+    fn byte(self) -> u8 {
+        match self {
+            Delimiter::Comma => b',',
+            Delimiter::Semicolon => b';',
+            Delimiter::Tab => b'\t',
+        }
+    }
+}
+
+/// Decimal separator in CSV prices and volumes.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DecimalSeparator {
+    #[default]
+    Point,
+    Comma,
+}
+
+/// Which CSV fields are quoted.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Quoting {
+    /// Only fields holding a delimiter, quote or line break
+    #[default]
+    Necessary,
+    Always,
+    /// Every field that isn't a number
+    NonNumeric,
+}
+
+/// How values are written beyond the file format. A dataset records it in
+/// its manifest so files rewritten later keep it, and readers parse its CSV
+/// with it. Parquet only uses `numeric` and `provenance`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Style {
+    #[serde(default)]
+    pub(crate) numeric: Numeric,
+    #[serde(default)]
+    pub(crate) delimiter: Delimiter,
+    #[serde(default)]
+    pub(crate) decimal_separator: DecimalSeparator,
+    #[serde(default)]
+    pub(crate) quoting: Quoting,
+    /// Every row ends in `exchange`, `source` and `ingest_time` columns
+    #[serde(default)]
+    pub(crate) provenance: bool,
+}
+
+/// Style options; ones left out keep what the dataset already uses.
+#[derive(clap::Args)]
+pub(crate) struct StyleArgs {
+    /// How prices and volumes are written; a new dataset is text as CSV
+    /// and f64 as Parquet
+    #[arg(long, value_enum)]
+    numeric: Option<Numeric>,
+    /// CSV field separator
+    #[arg(long, value_enum)]
+    delimiter: Option<Delimiter>,
+    /// Decimal separator in CSV prices and volumes
+    #[arg(long, value_enum)]
+    decimal_separator: Option<DecimalSeparator>,
+    /// Which CSV fields are quoted
+    #[arg(long, value_enum)]
+    quoting: Option<Quoting>,
+    /// Append where and when each row was fetched: exchange, source
+    /// (rest, vision or ws) and ingest_time
+    #[arg(long)]
+    provenance: bool,
+}
+
+impl StyleArgs {
+    /// `style` with the options given here applied.
+    pub(crate) fn apply(&self, style: Style) -> Result<Style> {
+        let style = Style {
+            numeric: self.numeric.unwrap_or(style.numeric),
+            delimiter: self.delimiter.unwrap_or(style.delimiter),
+            decimal_separator: self.decimal_separator.unwrap_or(style.decimal_separator),
+            quoting: self.quoting.unwrap_or(style.quoting),
+            provenance: self.provenance || style.provenance,
+        };
+        if style.delimiter == Delimiter::Comma && style.decimal_separator == DecimalSeparator::Comma
+        {
+            return Err(anyhow!(
+                "--decimal-separator comma needs another --delimiter, e.g. semicolon"
+            )
+            .context(Failure::Config));
+        }
+        Ok(style)
+    }
+}
+
+/// How a daily file is laid out on disk.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    /// Headerless CSV in the exchange's column order
+    #[default]
+    Csv,
+    /// The same CSV, gzip-compressed
+    #[value(name = "csv.gz")]
+    #[serde(rename = "csv.gz")]
+    CsvGz,
+    /// Snappy-compressed Parquet, one row group per file, with typed
+    /// timestamps and trade counts and, unless `--numeric text`, DOUBLE
+    /// prices and volumes
+    Parquet,
+}
+
+impl Format {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::CsvGz => "csv.gz",
+            Format::Parquet => "parquet",
+        }
+    }
+
+    pub(crate) fn of(path: &Path) -> Option<Format> {
+        if path.file_name()?.to_str()?.ends_with(".csv.gz") {
+            return Some(Format::CsvGz);
+        }
+        match path.extension()?.to_str()? {
+            "csv" => Some(Format::Csv),
+            "parquet" => Some(Format::Parquet),
+            _ => None,
+        }
+    }
+
+    /// `path` with its data file extension swapped for this format's.
+    pub(crate) fn rename(self, path: &Path) -> PathBuf {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let stem = Format::of(path)
+            .and_then(|f| name.strip_suffix(f.extension()))
+            .and_then(|stem| stem.strip_suffix('.'))
+            .unwrap_or(&name);
+        path.with_file_name(format!("{}.{}", stem, self.extension()))
+    }
+
+    /// Writes `rows` to `path`, synced as far as `durability` asks. Syncing
+    /// the directory entry is up to the caller.
+    pub(crate) fn write(
+        self,
+        path: &Path,
+        rows: &[Kline],
+        style: Style,
+        durability: Durability,
+    ) -> Result<()> {
+        let file = File::create(path)?;
+        match self {
+            Format::Csv => {
+                let out = BufWriter::with_capacity(WRITE_BUFFER_CAPACITY, file);
+                close(write_csv(out, rows, style)?, durability)
+            }
+            Format::CsvGz => {
+                let out = BufWriter::with_capacity(WRITE_BUFFER_CAPACITY, file);
+                let gz = GzEncoder::new(out, flate2::Compression::default());
+                close(write_csv(gz, rows, style)?.finish()?, durability)
+            }
+            Format::Parquet => {
+                let file = write_parquet(file, rows, style)?;
+                if durability == Durability::Fsync {
+                    file.sync_all()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The bytes [`Format::write`] would put in a file, encoded in memory.
+    pub(crate) fn encode(self, rows: &[Kline], style: Style) -> Result<Vec<u8>> {
+        match self {
+            Format::Csv => write_csv(Vec::new(), rows, style),
+            Format::CsvGz => {
+                let gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                Ok(write_csv(gz, rows, style)?.finish()?)
+            }
+            Format::Parquet => write_parquet(Vec::new(), rows, style),
+        }
+    }
+
+    /// Writes rows other than klines, e.g. trades, as headerless CSV in
+    /// their field order; only `delimiter` and `quoting` of the style apply.
+    pub(crate) fn write_records<T: Serialize>(
+        self,
+        path: &Path,
+        rows: &[T],
+        style: Style,
+        durability: Durability,
+    ) -> Result<()> {
+        if self == Format::Parquet {
+            return Err(
+                anyhow!("only klines are written as Parquet, use csv or csv.gz")
+                    .context(Failure::Config),
+            );
+        }
+        let out = BufWriter::with_capacity(WRITE_BUFFER_CAPACITY, File::create(path)?);
+        match self {
+            Format::Csv => close(write_records(out, rows, style)?, durability),
+            _ => {
+                let gz = GzEncoder::new(out, flate2::Compression::default());
+                close(write_records(gz, rows, style)?.finish()?, durability)
+            }
+        }
+    }
+
+    pub(crate) fn read(self, path: &Path, style: Style) -> Result<Vec<Kline>> {
+        match self {
+            Format::Csv => read_csv(File::open(path)?, style),
+            Format::CsvGz => read_csv(MultiGzDecoder::new(File::open(path)?), style),
+            Format::Parquet => read_parquet(File::open(path)?),
+        }
+    }
+
+    /// Reads a file already in memory, e.g. an archive member.
+    pub(crate) fn read_bytes(self, data: Vec<u8>, style: Style) -> Result<Vec<Kline>> {
+        match self {
+            Format::Csv => read_csv(&data[..], style),
+            Format::CsvGz => read_csv(MultiGzDecoder::new(&data[..]), style),
+            Format::Parquet => read_parquet(bytes::Bytes::from(data)),
+        }
+    }
+}
+
+fn csv_writer<W: Write>(out: W, style: Style) -> csv::Writer<W> {
+    let quote_style = match style.quoting {
+        Quoting::Necessary => csv::QuoteStyle::Necessary,
+        Quoting::Always => csv::QuoteStyle::Always,
+        Quoting::NonNumeric => csv::QuoteStyle::NonNumeric,
+    };
+    csv::WriterBuilder::new()
+        .has_headers(false)
+        .delimiter(style.delimiter.byte())
+        .quote_style(quote_style)
+        .from_writer(out)
+}
+
+/// Writes the rows and hands back `out`, flushed.
+fn write_csv<W: Write>(out: W, rows: &[Kline], style: Style) -> Result<W> {
+    let mut wtr = csv_writer(out, style);
+    let plain = style.numeric == Numeric::Text
+        && style.decimal_separator == DecimalSeparator::Point
+        && !style.provenance;
+    for rec in rows {
+        if plain {
+            wtr.serialize(rec)?;
+        } else {
+            wtr.serialize(Styled(rec, style))?;
+        }
+    }
+    Ok(wtr.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// Like [`write_csv`] for rows that serialize themselves.
+fn write_records<W: Write, T: Serialize>(out: W, rows: &[T], style: Style) -> Result<W> {
+    let mut wtr = csv_writer(out, style);
+    for rec in rows {
+        wtr.serialize(rec)?;
+    }
+    Ok(wtr.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// Flushes a written file, synced as far as `durability` asks.
+fn close(out: BufWriter<File>, durability: Durability) -> Result<()> {
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    if durability == Durability::Fsync {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+fn read_csv(input: impl Read, style: Style) -> Result<Vec<Kline>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(style.delimiter.byte())
+        .from_reader(input);
+    rdr.deserialize::<KlineRecord>()
+        .map(|row| {
+            let mut row = row?;
+            if style.decimal_separator == DecimalSeparator::Comma {
+                for field in [
+                    &mut row.open_price,
+                    &mut row.high,
+                    &mut row.low,
+                    &mut row.close,
+                    &mut row.volume,
+                    &mut row.quote_volume,
+                    &mut row.taker_buy_base_vol,
+                    &mut row.taker_buy_quote_vol,
+                ] {
+                    *field = field.replace(',', ".");
+                }
+            }
+            Ok(Kline::from(&row))
+        })
+        .collect()
+}
+
+/// Writes the rows and hands back `out`.
+fn write_parquet<W: Write + Send>(out: W, rows: &[Kline], style: Style) -> Result<W> {
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let schema = match style.numeric {
+        Numeric::Text => PARQUET_SCHEMA,
+        Numeric::F64 => PARQUET_SCHEMA_F64,
+    };
+    let schema = if style.provenance {
+        let fields = schema.trim_end().trim_end_matches('}');
+        parse_message_type(&format!("{}{}}}", fields, PROVENANCE_COLUMNS))?
+    } else {
+        parse_message_type(schema)?
+    };
+    let schema = Arc::new(schema);
+    let mut writer = SerializedFileWriter::new(out, schema, Arc::new(props))?;
+    let mut group = writer.next_row_group()?;
+    let mut idx = 0;
+    while let Some(mut column) = group.next_column()? {
+        match idx {
+            0 => write_longs(&mut column, rows.iter().map(|r| r.open_time))?,
+            1 => write_decimals(&mut column, rows, Kline::open_price, style.numeric)?,
+            2 => write_decimals(&mut column, rows, Kline::high, style.numeric)?,
+            3 => write_decimals(&mut column, rows, Kline::low, style.numeric)?,
+            4 => write_decimals(&mut column, rows, Kline::close, style.numeric)?,
+            5 => write_decimals(&mut column, rows, Kline::volume, style.numeric)?,
+            6 => write_longs(&mut column, rows.iter().map(|r| r.close_time))?,
+            7 => write_decimals(&mut column, rows, Kline::quote_volume, style.numeric)?,
+            8 => write_longs(&mut column, rows.iter().map(|r| r.num_of_trades as i64))?,
+            9 => write_decimals(&mut column, rows, Kline::taker_buy_base_vol, style.numeric)?,
+            10 => write_decimals(&mut column, rows, Kline::taker_buy_quote_vol, style.numeric)?,
+            11 => write_text(
+                &mut column,
+                rows.iter().map(|r| ByteArray::from(r.unused())).collect(),
+            )?,
+            12 => write_optional_text(
+                &mut column,
+                rows.iter()
+                    .map(|r| r.provenance.as_ref().map(|p| &*p.exchange)),
+            )?,
+            13 => write_optional_text(
+                &mut column,
+                rows.iter()
+                    .map(|r| r.provenance.as_ref().map(|p| p.source.name())),
+            )?,
+            14 => {
+                let times: Vec<Option<i64>> = rows
+                    .iter()
+                    .map(|r| r.provenance.as_ref().map(|p| p.ingest_time))
+                    .collect();
+                let values: Vec<i64> = times.iter().flatten().copied().collect();
+                column.typed::<Int64Type>().write_batch(
+                    &values,
+                    Some(&definition_levels(&times)),
+                    None,
+                )?;
+            }
+            _ => return Err(anyhow!("unexpected parquet column {}", idx)),
+        }
+        column.close()?;
+        idx += 1;
+    }
+    group.close()?;
+    Ok(writer.into_inner()?)
+}
+
+fn write_longs(
+    column: &mut parquet::file::writer::SerializedColumnWriter,
+    values: impl Iterator<Item = i64>,
+) -> Result<()> {
+    let values: Vec<i64> = values.collect();
+    column
+        .typed::<Int64Type>()
+        .write_batch(&values, None, None)?;
+    Ok(())
+}
+
+fn write_decimals(
+    column: &mut parquet::file::writer::SerializedColumnWriter,
+    rows: &[Kline],
+    field: fn(&Kline) -> &str,
+    numeric: Numeric,
+) -> Result<()> {
+    match numeric {
+        Numeric::Text => write_text(
+            column,
+            rows.iter().map(|r| ByteArray::from(field(r))).collect(),
+        ),
+        Numeric::F64 => {
+            let values = rows
+                .iter()
+                .map(|r| field(r).parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()?;
+            column
+                .typed::<DoubleType>()
+                .write_batch(&values, None, None)?;
+            Ok(())
+        }
+    }
+}
+
+fn write_text(
+    column: &mut parquet::file::writer::SerializedColumnWriter,
+    values: Vec<ByteArray>,
+) -> Result<()> {
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, None, None)?;
+    Ok(())
+}
+
+fn write_optional_text<'a>(
+    column: &mut parquet::file::writer::SerializedColumnWriter,
+    values: impl Iterator<Item = Option<&'a str>>,
+) -> Result<()> {
+    let values: Vec<Option<&str>> = values.collect();
+    let present: Vec<ByteArray> = values
+        .iter()
+        .flatten()
+        .map(|v| ByteArray::from(*v))
+        .collect();
+    column.typed::<ByteArrayType>().write_batch(
+        &present,
+        Some(&definition_levels(&values)),
+        None,
+    )?;
+    Ok(())
+}
+
+/// 1 for each value present, 0 for each null.
+fn definition_levels<T>(values: &[Option<T>]) -> Vec<i16> {
+    values.iter().map(|v| v.is_some() as i16).collect()
+}
+
+fn read_parquet(input: impl ChunkReader + 'static) -> Result<Vec<Kline>> {
+    let reader = SerializedFileReader::new(input)?;
+    let mut rows = Vec::with_capacity(reader.metadata().file_metadata().num_rows() as usize);
+    for row in reader.get_row_iter(None)? {
+        let row = row?;
+        rows.push(Kline::from(&KlineRecord {
+            open_time: row.get_timestamp_millis(0)?,
+            open_price: decimal(&row, 1)?,
+            high: decimal(&row, 2)?,
+            low: decimal(&row, 3)?,
+            close: decimal(&row, 4)?,
+            volume: decimal(&row, 5)?,
+            close_time: row.get_timestamp_millis(6)?,
+            quote_volume: decimal(&row, 7)?,
+            num_of_trades: row.get_ulong(8)?,
+            taker_buy_base_vol: decimal(&row, 9)?,
+            taker_buy_quote_vol: decimal(&row, 10)?,
+            unused: row.get_string(11)?.clone(),
+            exchange: optional(&row, 12, |row, idx| Ok(row.get_string(idx)?.clone())),
+            source: optional(&row, 13, |row, idx| Ok(row.get_string(idx)?.clone())),
+            ingest_time: optional(&row, 14, |row, idx| Ok(row.get_timestamp_millis(idx)?)),
+        }));
+    }
+    Ok(rows)
+}
+
+/// A provenance column, `None` if the file has none or the row's is null.
+fn optional<T>(row: &Row, idx: usize, get: fn(&Row, usize) -> Result<T>) -> Option<T> {
+    if idx < row.len() {
+        get(row, idx).ok()
+    } else {
+        None
+    }
+}
+
+/// A decimal column as text, whether stored as a string or a DOUBLE.
+fn decimal(row: &Row, idx: usize) -> Result<String> {
+    match row.get_string(idx) {
+        Ok(text) => Ok(text.clone()),
+        Err(_) => Ok(row.get_double(idx)?.to_string()),
+    }
+}
+
+/// Serializes a row like [`Kline`] but with its decimals in the CSV style:
+/// rounded to the nearest float for `Numeric::F64`, with a comma for
+/// `DecimalSeparator::Comma`, and followed by the provenance columns if the
+/// style has them.
+struct Styled<'a>(&'a Kline, Style);
+
+impl Serialize for Styled<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Styled(k, style) = *self;
+        let decimal = |text: &str| -> Result<String, S::Error> {
+            let text = match style.numeric {
+                Numeric::Text => text.to_string(),
+                // `Display` never switches to exponents, which `Decimal` can't read
+                Numeric::F64 => text.parse::<f64>().map_err(S::Error::custom)?.to_string(),
+            };
+            Ok(match style.decimal_separator {
+                DecimalSeparator::Point => text,
+                DecimalSeparator::Comma => text.replace('.', ","),
+            })
+        };
+        let len = if style.provenance { 15 } else { 12 };
+        let mut t = serializer.serialize_tuple(len)?;
+        t.serialize_element(&k.open_time)?;
+        t.serialize_element(&decimal(k.open_price())?)?;
+        t.serialize_element(&decimal(k.high())?)?;
+        t.serialize_element(&decimal(k.low())?)?;
+        t.serialize_element(&decimal(k.close())?)?;
+        t.serialize_element(&decimal(k.volume())?)?;
+        t.serialize_element(&k.close_time)?;
+        t.serialize_element(&decimal(k.quote_volume())?)?;
+        t.serialize_element(&k.num_of_trades)?;
+        t.serialize_element(&decimal(k.taker_buy_base_vol())?)?;
+        t.serialize_element(&decimal(k.taker_buy_quote_vol())?)?;
+        t.serialize_element(k.unused())?;
+        if style.provenance {
+            let p = k.provenance.as_deref();
+            t.serialize_element(&p.map(|p| &*p.exchange))?;
+            t.serialize_element(&p.map(|p| p.source.name()))?;
+            t.serialize_element(&p.map(|p| p.ingest_time))?;
+        }
+        t.end()
+    }
+}
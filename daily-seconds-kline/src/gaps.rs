@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::day::{day_start_ms, next_day_ms};
+use crate::download::{interval_ms, INTERVAL, OUT_DIR, SYMBOL};
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::manifest::{FileEntry, Manifest};
+use crate::reader::DatasetReader;
+
+#[derive(clap::Args)]
+pub(crate) struct GapsArgs {
+    /// Dataset to scan, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    #[arg(long, default_value = SYMBOL)]
+    symbol: String,
+    #[arg(long, default_value = INTERVAL)]
+    interval: String,
+    /// First day to check, e.g. 2024-06-01
+    #[arg(long)]
+    from: NaiveDate,
+    /// Last day to check, inclusive
+    #[arg(long)]
+    to: NaiveDate,
+    /// JSON list of known exchange downtime windows,
+    /// `[{"start_ms": .., "end_ms": .., "note": ".."}]`; gaps inside one
+    /// are labelled with its note
+    #[arg(long)]
+    downtime: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    symbol: String,
+    interval: String,
+    from: NaiveDate,
+    to: NaiveDate,
+    /// Days without any file
+    missing_days: Vec<NaiveDate>,
+    /// Missing candles in the days that have files
+    gaps: Vec<Gap>,
+    missing_candles: i64,
+    /// Days with files but fewer candles than they should have
+    short_days: Vec<ShortDay>,
+    /// Rows that are there but wrong
+    bad_rows: Vec<BadRow>,
+    downtime: Vec<Downtime>,
+}
+
+#[derive(Serialize)]
+struct ShortDay {
+    day: NaiveDate,
+    rows: i64,
+    expected: i64,
+}
+
+#[derive(Serialize)]
+struct BadRow {
+    open_time: i64,
+    problem: String,
+}
+
+/// Candles missing from `start_ms` to `end_ms`, inclusive like the klines
+/// request range that would fill them.
+#[derive(Serialize)]
+struct Gap {
+    day: NaiveDate,
+    start_ms: i64,
+    end_ms: i64,
+    candles: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    downtime: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Downtime {
+    start_ms: i64,
+    end_ms: i64,
+    #[serde(default)]
+    note: String,
+}
+
+pub(crate) async fn run(args: GapsArgs) -> Result<()> {
+    let report = tokio::task::spawn_blocking(move || gaps(&args)).await??;
+    tracing::info!(
+        "{} day(s) missing, {} candle(s) missing in {} gap(s), {} bad row(s)",
+        report.missing_days.len(),
+        report.missing_candles,
+        report.gaps.len(),
+        report.bad_rows.len()
+    );
+    serde_json::to_writer_pretty(std::io::stdout().lock(), &report)?;
+    println!();
+    Ok(())
+}
+
+/// Compares the stored open times against every candle the range should
+/// have, up to now and inside the dataset's session if it has one, and
+/// checks each row's own times.
+fn gaps(args: &GapsArgs) -> Result<Report> {
+    let step_ms = interval_ms(&args.interval)
+        .ok_or_else(|| anyhow!("unsupported interval {:?}", args.interval))
+        .context(Failure::Config)?;
+    if args.to < args.from {
+        return Err(
+            anyhow!("--to {} is before --from {}", args.to, args.from).context(Failure::Config)
+        );
+    }
+    let downtime: Vec<Downtime> = match &args.downtime {
+        Some(path) => {
+            let file = std::fs::File::open(path).with_context(|| format!("opening {:?}", path))?;
+            serde_json::from_reader(file)
+                .with_context(|| format!("parsing {:?}", path))
+                .context(Failure::Config)?
+        }
+        None => Vec::new(),
+    };
+
+    let manifest = Manifest::load(&args.dir)?;
+    let covering = |day: NaiveDate| {
+        manifest.files().filter(move |(key, e)| {
+            e.symbol == args.symbol
+                && e.interval == args.interval
+                && e.day <= day
+                && day <= e.last_day.unwrap_or(e.day)
+                && Format::of(Path::new(key)).is_some()
+        })
+    };
+    let range_start_ms = day_start_ms(args.from);
+    let range_end_ms = next_day_ms(day_start_ms(args.to));
+    let now_ms = Utc::now().timestamp_millis();
+
+    let mut days = Vec::new();
+    let mut missing_days = Vec::new();
+    let mut files = BTreeMap::new();
+    for day in args.from.iter_days().take_while(|d| *d <= args.to) {
+        if day_start_ms(day) > now_ms {
+            break;
+        }
+        let day_files: Vec<(&str, &FileEntry)> = covering(day).collect();
+        if day_files.is_empty() {
+            missing_days.push(day);
+        } else {
+            files.extend(day_files);
+            days.push(day);
+        }
+    }
+
+    let mut open_times = BTreeSet::new();
+    let mut bad_rows = Vec::new();
+    let reader = DatasetReader::new(&manifest);
+    for (key, entry) in files {
+        let rows = reader.read(key, entry)?;
+        for row in rows {
+            if !(range_start_ms..range_end_ms).contains(&row.open_time) {
+                continue;
+            }
+            let problem = if !open_times.insert(row.open_time) {
+                "duplicate".to_string()
+            } else if row.open_time.rem_euclid(step_ms) != 0 {
+                format!("off the {} grid", args.interval)
+            } else if row.close_time != row.open_time + step_ms - 1 {
+                format!(
+                    "close_time {}, expected {}",
+                    row.close_time,
+                    row.open_time + step_ms - 1
+                )
+            } else {
+                continue;
+            };
+            bad_rows.push(BadRow {
+                open_time: row.open_time,
+                problem,
+            });
+        }
+    }
+
+    let session = manifest.session()?;
+    let mut gaps = Vec::new();
+    let mut short_days = Vec::new();
+    for day in days {
+        let mut rows = 0;
+        let mut expected = 0;
+        let start_ms = day_start_ms(day);
+        let end_ms = next_day_ms(start_ms).min(now_ms.div_euclid(step_ms) * step_ms);
+        // a session-filtered dataset only has candles inside the session
+        let ranges = match &session {
+            Some(session) => session.ranges(start_ms, end_ms),
+            None => vec![(start_ms, end_ms)],
+        };
+        for (start_ms, end_ms) in ranges {
+            rows += open_times.range(start_ms..end_ms).count() as i64;
+            expected += (end_ms - start_ms + step_ms - 1) / step_ms;
+            let mut expected_ms = start_ms;
+            let present = open_times.range(start_ms..end_ms).copied();
+            for open_time in present.chain([end_ms]) {
+                if open_time > expected_ms {
+                    gaps.push(Gap {
+                        day,
+                        start_ms: expected_ms,
+                        end_ms: open_time - 1,
+                        candles: (open_time - expected_ms + step_ms - 1) / step_ms,
+                        downtime: downtime
+                            .iter()
+                            .find(|d| d.start_ms <= expected_ms && open_time - 1 <= d.end_ms)
+                            .map(|d| d.note.clone()),
+                    });
+                }
+                expected_ms = open_time + step_ms;
+            }
+        }
+        if rows < expected {
+            short_days.push(ShortDay {
+                day,
+                rows,
+                expected,
+            });
+        }
+    }
+
+    Ok(Report {
+        symbol: args.symbol.clone(),
+        interval: args.interval.clone(),
+        from: args.from,
+        to: args.to,
+        missing_days,
+        missing_candles: gaps.iter().map(|g| g.candles).sum(),
+        gaps,
+        short_days,
+        bad_rows,
+        downtime: downtime
+            .into_iter()
+            .filter(|d| d.start_ms < range_end_ms && d.end_ms >= range_start_ms)
+            .collect(),
+    })
+}
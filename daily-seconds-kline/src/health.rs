@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// No processed response for this long marks the collector as stuck.
+const STALL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Default, serde::Serialize)]
+struct State {
+    ready: bool,
+    last_progress_ms: i64,
+    /// Last successful write per symbol
+    last_write_ms: BTreeMap<String, i64>,
+    sink_error: Option<String>,
+}
+
+/// Liveness and readiness as seen by the download loop and the writer.
+#[derive(Clone, Default)]
+pub(crate) struct Health(Arc<Mutex<State>>);
+
+impl Health {
+    pub(crate) fn set_ready(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.ready = true;
+        state.last_progress_ms = now();
+    }
+
+    /// A response came back, whether or not it had rows.
+    pub(crate) fn progress(&self) {
+        self.0.lock().unwrap().last_progress_ms = now();
+    }
+
+    pub(crate) fn written(&self, symbol: &str) {
+        let mut state = self.0.lock().unwrap();
+        state.last_write_ms.insert(symbol.to_string(), now());
+        state.sink_error = None;
+    }
+
+    pub(crate) fn sink_failed(&self, error: &anyhow::Error) {
+        self.0.lock().unwrap().sink_error = Some(format!("{:#}", error));
+    }
+
+    pub(crate) fn since_progress(&self) -> Duration {
+        let last = self.0.lock().unwrap().last_progress_ms;
+        Duration::from_millis((now() - last).max(0) as u64)
+    }
+
+    fn live(&self) -> bool {
+        self.since_progress() < STALL
+    }
+
+    fn ready(&self) -> bool {
+        let state = self.0.lock().unwrap();
+        state.ready && state.sink_error.is_none()
+    }
+
+    /// Serves `/healthz` (the loop is making progress) and `/readyz` (up and
+    /// the sink is writing), both with the current state as JSON.
+    pub(crate) async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        tracing::info!("health endpoint on http://{}", listener.local_addr()?);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let health = self.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    let Ok(Some(request)) = lines.next_line().await else {
+                        return;
+                    };
+                    let ok = match request.split(' ').nth(1) {
+                        Some("/healthz") => health.live(),
+                        Some("/readyz") => health.ready(),
+                        _ => {
+                            let _ = write
+                                .write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                                .await;
+                            return;
+                        }
+                    };
+                    let body = serde_json::to_vec(&*health.0.lock().unwrap()).unwrap_or_default();
+                    let status = if ok {
+                        "200 OK"
+                    } else {
+                        "503 Service Unavailable"
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    let _ = write.write_all(head.as_bytes()).await;
+                    let _ = write.write_all(&body).await;
+                });
+            }
+        });
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
@@ -0,0 +1,118 @@
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::decimal::Decimal;
+use crate::format::{Format, Style, StyleArgs};
+use crate::kline::{timestamp, Kline};
+use crate::layout::data_files;
+
+#[derive(clap::Args)]
+pub(crate) struct InspectArgs {
+    /// A stored file, or a directory to inspect every file below in path order
+    path: PathBuf,
+    /// Print the first rows
+    #[arg(long, conflicts_with = "tail")]
+    head: Option<usize>,
+    /// Print the last rows
+    #[arg(long)]
+    tail: Option<usize>,
+    /// How the CSV files are written, if not the defaults
+    #[command(flatten)]
+    style: StyleArgs,
+}
+
+const DEFAULT_HEAD: usize = 10;
+
+pub(crate) async fn run(args: InspectArgs) -> Result<()> {
+    let result = tokio::task::spawn_blocking(move || inspect(&args)).await?;
+    // `inspect .. | head` closing the pipe early isn't an error
+    match result {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>().map(|e| e.kind())
+                == Some(ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn inspect(args: &InspectArgs) -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    let files = files(&args.path)?;
+    let style = args.style.apply(Style::default())?;
+    let mut rows: Vec<Kline> = Vec::new();
+    for path in &files {
+        let format =
+            Format::of(path).ok_or_else(|| anyhow!("{:?} isn't a CSV or Parquet file", path))?;
+        rows.extend(
+            format
+                .read(path, style)
+                .with_context(|| format!("reading {:?}", path))?,
+        );
+    }
+
+    let shown = match (args.head, args.tail) {
+        (_, Some(n)) => &rows[rows.len().saturating_sub(n)..],
+        (n, None) => &rows[..n.unwrap_or(DEFAULT_HEAD).min(rows.len())],
+    };
+    writeln!(
+        out,
+        "{:<23} {:>16} {:>16} {:>16} {:>16} {:>18} {:>8}",
+        "open_time", "open", "high", "low", "close", "volume", "trades"
+    )?;
+    for r in shown {
+        writeln!(
+            out,
+            "{:<23} {:>16} {:>16} {:>16} {:>16} {:>18} {:>8}",
+            timestamp(r.open_time),
+            r.open_price(),
+            r.high(),
+            r.low(),
+            r.close(),
+            r.volume(),
+            r.num_of_trades
+        )?;
+    }
+    writeln!(out)?;
+    print_stats(&mut out, files.len(), &rows)
+}
+
+fn print_stats(out: &mut impl Write, files: usize, rows: &[Kline]) -> Result<()> {
+    writeln!(out, "files          {}", files)?;
+    writeln!(out, "rows           {}", rows.len())?;
+    let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+        return Ok(());
+    };
+    writeln!(out, "first open     {}", timestamp(first.open_time))?;
+    writeln!(out, "last open      {}", timestamp(last.open_time))?;
+    let mut low: Decimal = first.low().parse()?;
+    let mut high: Decimal = first.high().parse()?;
+    let mut volume: Decimal = "0".parse()?;
+    let mut quote_volume: Decimal = "0".parse()?;
+    for r in rows {
+        low = low.min(r.low().parse()?);
+        high = high.max(r.high().parse()?);
+        volume = volume + r.volume().parse()?;
+        quote_volume = quote_volume + r.quote_volume().parse()?;
+    }
+    writeln!(out, "min price      {}", low)?;
+    writeln!(out, "max price      {}", high)?;
+    writeln!(out, "volume         {}", volume)?;
+    writeln!(out, "quote volume   {}", quote_volume)?;
+    writeln!(
+        out,
+        "trades         {}",
+        rows.iter().map(|r| r.num_of_trades).sum::<u64>()
+    )?;
+    Ok(())
+}
+
+fn files(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    data_files(path)
+}
@@ -0,0 +1,355 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+
+use crate::KlineRow;
+
+/// Decimal fields in response order, after `open_time`.
+const TEXT_FIELDS: usize = 9;
+
+/// A kline as it comes off the wire, borrowing every decimal from the
+/// response body.
+#[derive(serde::Deserialize)]
+struct RawKline<'a>(
+    i64,
+    &'a str,
+    &'a str,
+    &'a str,
+    &'a str,
+    &'a str,
+    i64,
+    &'a str,
+    u64,
+    &'a str,
+    &'a str,
+    &'a str,
+);
+
+/// The owned serde model of a file's record, decimals as text.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct KlineRecord {
+    pub(crate) open_time: i64,
+    pub(crate) open_price: String,
+    pub(crate) high: String,
+    pub(crate) low: String,
+    pub(crate) close: String,
+    pub(crate) volume: String,
+    pub(crate) close_time: i64,
+    pub(crate) quote_volume: String,
+    pub(crate) num_of_trades: u64,
+    pub(crate) taker_buy_base_vol: String,
+    pub(crate) taker_buy_quote_vol: String,
+    pub(crate) unused: String,
+    /// Provenance columns, present in datasets written with `--provenance`
+    #[serde(default)]
+    pub(crate) exchange: Option<String>,
+    #[serde(default)]
+    pub(crate) source: Option<String>,
+    #[serde(default)]
+    pub(crate) ingest_time: Option<i64>,
+}
+
+/// The in-memory row: the decimals stay exact text but share one allocation
+/// instead of nine `String`s. Serializes to the same CSV record as
+/// [`KlineRecord`].
+#[derive(Debug, Clone)]
+pub(crate) struct Kline {
+    pub(crate) open_time: i64,
+    pub(crate) close_time: i64,
+    pub(crate) num_of_trades: u64,
+    /// Shared by every row of one response; `None` for derived rows and
+    /// files written without provenance
+    pub(crate) provenance: Option<Arc<Provenance>>,
+    text: Box<str>,
+    ends: [u32; TEXT_FIELDS],
+}
+
+/// Where and when a row was fetched, kept in datasets written with
+/// `--provenance` so merged sources stay auditable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Provenance {
+    pub(crate) exchange: Box<str>,
+    pub(crate) source: Source,
+    /// Milliseconds since the epoch
+    pub(crate) ingest_time: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Source {
+    /// The klines REST endpoint
+    Rest,
+    /// Daily archives from data.binance.vision
+    Vision,
+    /// The kline WebSocket stream
+    Ws,
+}
+
+impl Source {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Source::Rest => "rest",
+            Source::Vision => "vision",
+            Source::Ws => "ws",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "rest" => Some(Source::Rest),
+            "vision" => Some(Source::Vision),
+            "ws" => Some(Source::Ws),
+            _ => None,
+        }
+    }
+}
+
+impl Provenance {
+    /// Rows from Binance via `source`, ingested now.
+    pub(crate) fn now(source: Source) -> Arc<Self> {
+        Arc::new(Self {
+            exchange: "binance".into(),
+            source,
+            ingest_time: chrono::Utc::now().timestamp_millis(),
+        })
+    }
+
+    fn from_columns(
+        exchange: Option<&str>,
+        source: Option<&str>,
+        ingest_time: Option<i64>,
+    ) -> Option<Arc<Self>> {
+        Some(Arc::new(Self {
+            exchange: exchange?.into(),
+            source: Source::parse(source?)?,
+            ingest_time: ingest_time?,
+        }))
+    }
+}
+
+/// Parses a `/api/v3/klines` response body.
+pub(crate) fn parse(body: &[u8]) -> serde_json::Result<Vec<Kline>> {
+    let raw: Vec<RawKline> = serde_json::from_slice(body)?;
+    Ok(raw.iter().map(Kline::from_raw).collect())
+}
+
+/// `ms` since the epoch as a UTC time for people to read.
+pub(crate) fn timestamp(ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(ms).map_or(ms.to_string(), |t| {
+        t.format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+    })
+}
+
+/// Marks rows as fetched from `source` just now. Written only by datasets
+/// with `--provenance`.
+pub(crate) fn stamp(rows: &mut [Kline], source: Source) {
+    let provenance = Provenance::now(source);
+    for row in rows {
+        row.provenance = Some(provenance.clone());
+    }
+}
+
+impl Kline {
+    fn from_raw(r: &RawKline) -> Self {
+        Self::new(
+            r.0,
+            r.6,
+            r.8,
+            [r.1, r.2, r.3, r.4, r.5, r.7, r.9, r.10, r.11],
+        )
+    }
+
+    pub(crate) fn new(
+        open_time: i64,
+        close_time: i64,
+        num_of_trades: u64,
+        fields: [&str; TEXT_FIELDS],
+    ) -> Self {
+        let mut text = String::with_capacity(fields.iter().map(|f| f.len()).sum());
+        let ends = fields.map(|f| {
+            text.push_str(f);
+            text.len() as u32
+        });
+        Self {
+            open_time,
+            close_time,
+            num_of_trades,
+            provenance: None,
+            text: text.into_boxed_str(),
+            ends,
+        }
+    }
+
+    fn field(&self, idx: usize) -> &str {
+        let start = idx
+            .checked_sub(1)
+            .map_or(0, |prev| self.ends[prev] as usize);
+        &self.text[start..self.ends[idx] as usize]
+    }
+
+    pub(crate) fn open_price(&self) -> &str {
+        self.field(0)
+    }
+
+    pub(crate) fn high(&self) -> &str {
+        self.field(1)
+    }
+
+    pub(crate) fn low(&self) -> &str {
+        self.field(2)
+    }
+
+    pub(crate) fn close(&self) -> &str {
+        self.field(3)
+    }
+
+    pub(crate) fn volume(&self) -> &str {
+        self.field(4)
+    }
+
+    pub(crate) fn quote_volume(&self) -> &str {
+        self.field(5)
+    }
+
+    pub(crate) fn taker_buy_base_vol(&self) -> &str {
+        self.field(6)
+    }
+
+    pub(crate) fn taker_buy_quote_vol(&self) -> &str {
+        self.field(7)
+    }
+
+    pub(crate) fn unused(&self) -> &str {
+        self.field(8)
+    }
+}
+
+impl Serialize for Kline {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut t = serializer.serialize_tuple(12)?;
+        t.serialize_element(&self.open_time)?;
+        t.serialize_element(self.open_price())?;
+        t.serialize_element(self.high())?;
+        t.serialize_element(self.low())?;
+        t.serialize_element(self.close())?;
+        t.serialize_element(self.volume())?;
+        t.serialize_element(&self.close_time)?;
+        t.serialize_element(self.quote_volume())?;
+        t.serialize_element(&self.num_of_trades)?;
+        t.serialize_element(self.taker_buy_base_vol())?;
+        t.serialize_element(self.taker_buy_quote_vol())?;
+        t.serialize_element(self.unused())?;
+        t.end()
+    }
+}
+
+impl From<&KlineRecord> for Kline {
+    fn from(r: &KlineRecord) -> Self {
+        let mut k = Self::new(
+            r.open_time,
+            r.close_time,
+            r.num_of_trades,
+            [
+                &r.open_price,
+                &r.high,
+                &r.low,
+                &r.close,
+                &r.volume,
+                &r.quote_volume,
+                &r.taker_buy_base_vol,
+                &r.taker_buy_quote_vol,
+                &r.unused,
+            ],
+        );
+        k.provenance =
+            Provenance::from_columns(r.exchange.as_deref(), r.source.as_deref(), r.ingest_time);
+        k
+    }
+}
+
+impl TryFrom<&Kline> for KlineRow {
+    type Error = anyhow::Error;
+
+    fn try_from(k: &Kline) -> Result<Self> {
+        Ok(Self {
+            open_time: k.open_time,
+            open_price: k.open_price().parse()?,
+            high: k.high().parse()?,
+            low: k.low().parse()?,
+            close: k.close().parse()?,
+            volume: k.volume().parse()?,
+            close_time: k.close_time,
+            quote_volume: k.quote_volume().parse()?,
+            num_of_trades: k.num_of_trades,
+            taker_buy_base_vol: k.taker_buy_base_vol().parse()?,
+            taker_buy_quote_vol: k.taker_buy_quote_vol().parse()?,
+            unused: k.unused().to_string(),
+            exchange: k.provenance.as_ref().map(|p| p.exchange.to_string()),
+            source: k.provenance.as_ref().map(|p| p.source.name().to_string()),
+            ingest_time: k.provenance.as_ref().map(|p| p.ingest_time),
+        })
+    }
+}
+
+impl From<&KlineRow> for Kline {
+    fn from(r: &KlineRow) -> Self {
+        let mut k = Self::new(
+            r.open_time,
+            r.close_time,
+            r.num_of_trades,
+            [
+                &r.open_price.to_string(),
+                &r.high.to_string(),
+                &r.low.to_string(),
+                &r.close.to_string(),
+                &r.volume.to_string(),
+                &r.quote_volume.to_string(),
+                &r.taker_buy_base_vol.to_string(),
+                &r.taker_buy_quote_vol.to_string(),
+                &r.unused,
+            ],
+        );
+        k.provenance =
+            Provenance::from_columns(r.exchange.as_deref(), r.source.as_deref(), r.ingest_time);
+        k
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A candle as `/api/v3/klines` returns it, the ignored column last.
+    const BINANCE: &str = r#"[1717200000000,"3765.01000000","3766.50000000","3764.80000000","3766.00000000","12.34560000",1717200000999,"46490.12345670",42,"6.10000000","22965.30000000","0"]"#;
+
+    #[test]
+    fn binance_arrays_round_trip_through_kline_row() {
+        let row: KlineRow = serde_json::from_str(BINANCE).unwrap();
+        assert_eq!(row.close.to_string(), "3766.00000000");
+        assert_eq!(row.num_of_trades, 42);
+        assert_eq!(row.unused, "0");
+        assert_eq!(row.exchange, None);
+        assert_eq!(row.source, None);
+        assert_eq!(row.ingest_time, None);
+        let kline = Kline::from(&row);
+        assert!(kline.provenance.is_none());
+        assert_eq!(serde_json::to_string(&kline).unwrap(), BINANCE);
+        let again = KlineRow::try_from(&kline).unwrap();
+        assert_eq!(
+            serde_json::to_value(&again).unwrap(),
+            serde_json::to_value(&row).unwrap()
+        );
+    }
+
+    #[test]
+    fn headerless_csv_rows_leave_provenance_out() {
+        let csv = "1717200000000,3765.01,3766.5,3764.8,3766,12.3456,1717200000999,46490.1234567,42,6.1,22965.3,0\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(csv.as_bytes());
+        let row: KlineRow = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(row.unused, "0");
+        assert_eq!(row.exchange, None);
+        assert_eq!(row.taker_buy_quote_vol.to_string(), "22965.3");
+    }
+}
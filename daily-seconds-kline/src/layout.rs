@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+
+use crate::format::Format;
+use crate::manifest::MANIFEST_FILE;
+use crate::market::Market;
+
+/// Deepest nesting: symbol, interval, year, month and day.
+const MAX_DEPTH: u8 = 5;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum LayoutKind {
+    /// Every file directly in the output directory
+    Flat,
+    /// Files under `{symbol}/{interval}/{yyyy}/{mm}/{dd}/`, cut to
+    /// `--layout-depth` levels
+    Nested,
+}
+
+/// How a new dataset arranges its files; an existing one keeps its own.
+#[derive(clap::Args)]
+pub(crate) struct LayoutArgs {
+    /// Flat unless the new dataset holds several symbols, which each get a
+    /// directory of their own as with `--layout nested --layout-depth 1`
+    #[arg(long, value_enum)]
+    layout: Option<LayoutKind>,
+    /// Directory levels of `--layout nested`; 4 gives
+    /// `ETHUSDC/1s/2024/06/ETHUSDC-1s-2024-06-01.csv`
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=MAX_DEPTH as i64))]
+    layout_depth: u8,
+}
+
+impl LayoutArgs {
+    /// The layout asked for, if any.
+    pub(crate) fn layout(&self) -> Option<Layout> {
+        self.layout.map(|kind| match kind {
+            LayoutKind::Flat => Layout::FLAT,
+            LayoutKind::Nested => Layout {
+                depth: self.layout_depth,
+            },
+        })
+    }
+}
+
+/// Where files go below the output directory. Manifest keys are relative
+/// paths, so readers find files in any layout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Layout {
+    pub(crate) depth: u8,
+}
+
+impl Layout {
+    pub(crate) const FLAT: Layout = Layout { depth: 0 };
+    /// A directory per symbol, the files directly in it.
+    pub(crate) const PER_SYMBOL: Layout = Layout { depth: 1 };
+
+    /// Directory, relative to the output directory, for a file of `symbol`
+    /// and `interval` starting on `day`.
+    pub(crate) fn dir(self, symbol: &str, interval: &str, day: NaiveDate) -> PathBuf {
+        [
+            symbol.to_string(),
+            interval.to_string(),
+            day.format("%Y").to_string(),
+            day.format("%m").to_string(),
+            day.format("%d").to_string(),
+        ]
+        .into_iter()
+        .take(self.depth.into())
+        .collect()
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.depth {
+            0 => f.write_str("flat"),
+            depth => write!(f, "nested, depth {}", depth),
+        }
+    }
+}
+
+/// CSV and Parquet files of the dataset in `dir`, in path order.
+pub(crate) fn data_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = dataset_files(dir)?;
+    files.retain(|path| Format::of(path).is_some());
+    Ok(files)
+}
+
+/// Every file below `dir` but those of other datasets nested in it, such
+/// as the futures ones in `um/` and `cm/`, in path order.
+pub(crate) fn dataset_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let nested: Vec<&str> = [Market::UsdmFutures, Market::CoinmFutures]
+        .into_iter()
+        .filter_map(Market::dir)
+        .collect();
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in
+            std::fs::read_dir(&current).with_context(|| format!("listing {:?}", current))?
+        {
+            let path = entry?.path();
+            if !path.is_dir() {
+                files.push(path);
+                continue;
+            }
+            let futures = current == dir
+                && path
+                    .file_name()
+                    .is_some_and(|name| nested.iter().any(|n| name == *n));
+            if !futures && !path.join(MANIFEST_FILE).exists() {
+                dirs.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,85 @@
+//! Downloads Binance klines into daily files. The `daily-seconds-kline`
+//! binary is [`cli::main`]; other programs can page through klines with
+//! [`KlineClient`] and hand whole days to a [`DaySink`] such as
+//! [`FileSink`].
+
+mod adaptive;
+mod aggregate;
+mod api;
+mod archive;
+mod bench;
+mod bucket;
+mod checksum;
+pub mod cli;
+mod client;
+mod convert;
+mod day;
+mod decimal;
+mod diff;
+mod disk;
+mod download;
+mod downsample;
+mod exit;
+mod export;
+mod format;
+mod gaps;
+mod health;
+mod inspect;
+mod kline;
+mod layout;
+mod load;
+mod manifest;
+mod market;
+mod merge;
+mod planner;
+mod progress;
+mod prune;
+mod queue;
+mod reader;
+mod replay;
+mod report;
+mod resample;
+mod session;
+mod sink;
+mod split;
+mod stream;
+mod symbols;
+mod systemd;
+mod throttle;
+mod trades;
+mod vision;
+mod watch;
+mod writer;
+
+pub use api::{DaySink, FileSink, KlineClient};
+pub use day::DayStart;
+pub use decimal::Decimal;
+pub use market::Market;
+
+/// A kline with typed prices and volumes; the hot path uses
+/// [`kline::Kline`]. Decimals are exact, so days written through
+/// [`FileSink`] hold the exchange's own text.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct KlineRow {
+    pub open_time: i64,
+    pub open_price: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub close_time: i64,
+    pub quote_volume: Decimal,
+    pub num_of_trades: u64,
+    pub taker_buy_base_vol: Decimal,
+    pub taker_buy_quote_vol: Decimal,
+    /// The exchange's ignored last column, kept so that rows line up with
+    /// its arrays and with headerless files
+    pub unused: String,
+    /// Where the row came from, kept in datasets written with provenance
+    #[serde(default)]
+    pub exchange: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub ingest_time: Option<i64>,
+}
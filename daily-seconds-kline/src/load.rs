@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{self, StreamExt};
+
+use crate::download::OUT_DIR;
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::manifest::Manifest;
+use crate::reader::DatasetReader;
+use crate::sink::Sink;
+
+#[derive(clap::Args)]
+pub(crate) struct LoadArgs {
+    /// Database to load into: `postgres://`, `clickhouse://` or `sqlite://path`;
+    /// a bucket to upload the files to, `s3://bucket/prefix`;
+    /// or a Google Sheet to append daily candles to,
+    /// `sheets://SPREADSHEET_ID[/SHEET]?credentials=key.json[&rows=all]`
+    #[arg(long)]
+    sink: String,
+    /// Dataset to load, with its `manifest.json`
+    #[arg(default_value = OUT_DIR)]
+    dir: PathBuf,
+    /// Files read in parallel while earlier ones are written
+    #[arg(long, default_value_t = 4)]
+    readers: usize,
+}
+
+/// Streams every file in the manifest into the sink, in manifest order.
+pub(crate) async fn run(args: LoadArgs) -> Result<()> {
+    let manifest = Manifest::load(&args.dir)?;
+    let sink = Sink::open(&args.sink, &manifest).await?;
+    let files: Vec<_> = manifest
+        .files()
+        .filter(|(key, _)| Format::of(Path::new(key)).is_some())
+        .map(|(key, entry)| (key.to_string(), entry.clone()))
+        .collect();
+    let reader = DatasetReader::new(&manifest);
+    let total = files.len();
+    let started = Instant::now();
+    let mut loaded_rows = 0;
+
+    let mut reads = stream::iter(files)
+        .map(|(key, entry)| {
+            let reader = reader.clone();
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let rows = reader.read(&key, &entry)?;
+                    anyhow::Ok((key, entry, rows))
+                })
+                .await?
+            }
+        })
+        .buffered(args.readers.max(1));
+    let mut done = 0;
+    while let Some(read) = reads.next().await {
+        let (key, entry, rows) = read?;
+        if rows.len() != entry.rows {
+            return Err(anyhow!(
+                "{} has {} rows, the manifest recorded {}",
+                key,
+                rows.len(),
+                entry.rows
+            )
+            .context(Failure::Validation));
+        }
+        sink.write(&entry.symbol, &entry.interval, &rows)
+            .await
+            .with_context(|| format!("loading {}", key))?;
+        done += 1;
+        loaded_rows += rows.len();
+        tracing::info!(
+            "[{}/{}] loaded {}, {} rows, {:.0} rows/s",
+            done,
+            total,
+            key,
+            rows.len(),
+            loaded_rows as f64 / started.elapsed().as_secs_f64()
+        );
+    }
+    tracing::info!(
+        "loaded {} file(s), {} rows in {:?}",
+        done,
+        loaded_rows,
+        started.elapsed()
+    );
+    Ok(())
+}
@@ -0,0 +1,762 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::day::{self, DayStart};
+use crate::download::interval_ms;
+use crate::exit::Failure;
+use crate::format::{Format, Numeric, Style};
+use crate::layout::{dataset_files, Layout};
+use crate::market::{market, Market};
+use crate::queue::Job;
+use crate::reader::DatasetReader;
+use crate::session::Session;
+use crate::trades::DataType;
+use crate::writer::{sync_dir, Durability};
+
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+/// Age past which a temporary file is taken for one left by a write that
+/// never finished, not one another worker is still writing.
+const STALE_TMP: Duration = Duration::from_secs(3600);
+
+/// One written file, keyed in the manifest by its path relative to the
+/// output directory.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct FileEntry {
+    pub(crate) symbol: String,
+    pub(crate) interval: String,
+    pub(crate) day: NaiveDate,
+    /// Last day of a file merged from several, see `merge`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) last_day: Option<NaiveDate>,
+    /// Hour since the day's start of a file split from a day, see `split`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hour: Option<u32>,
+    /// Interval the rows were downsampled from, see `downsample`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) resampled_from: Option<String>,
+    /// Interval of the file beside it the rows were aggregated from, see
+    /// `aggregate`; unlike a downsampled file it stands in for nothing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) aggregated_from: Option<String>,
+    /// Where the file lives once bundled into an archive, see `archive`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) archive: Option<ArchiveMember>,
+    pub(crate) rows: usize,
+    pub(crate) bytes: u64,
+    /// Whether the file covers the whole day.
+    pub(crate) complete: bool,
+}
+
+/// A file's own zstd frame inside a `.tar.zst` archive, which decodes to
+/// its tar header and contents.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct ArchiveMember {
+    /// Archive path relative to the output directory
+    pub(crate) file: String,
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+}
+
+/// A symbol selection and what it resolved to, see `download --symbols`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub(crate) struct Selection {
+    pub(crate) query: String,
+    pub(crate) symbols: Vec<String>,
+    pub(crate) resolved_at: DateTime<Utc>,
+}
+
+/// Catalogue of the files in an output directory, kept in `manifest.json`.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub(crate) struct Manifest {
+    files: BTreeMap<String, FileEntry>,
+    /// `--day-start` the files were cut with, if not UTC midnight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    day_start: Option<String>,
+    /// `--market` the files come from, if not spot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    market: Option<Market>,
+    /// `--session` rows were filtered to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<String>,
+    /// Symbol selections each time they resolved differently, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    selections: Vec<Selection>,
+    /// Directory levels files are nested in, see `--layout`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout_depth: Option<u8>,
+    /// How values are written, if not the defaults
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    style: Option<Style>,
+    /// Format new files are written in, if not CSV
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<Format>,
+    /// What the files hold, if not klines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<DataType>,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Manifest {
+    /// The dataset in `dir`, which must hold klines of the current
+    /// `--market` cut at the current `--day-start`.
+    pub(crate) fn load(dir: &Path) -> Result<Self> {
+        Self::load_for(dir, market(), day::day_start())
+    }
+
+    /// Like [`Manifest::load`], for `market` and `day_start`.
+    pub(crate) fn load_for(dir: &Path, market: Market, day_start: DayStart) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let mut manifest = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<Manifest>(&data)
+                .with_context(|| format!("invalid manifest {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let recorded = manifest
+            .day_start
+            .clone()
+            .unwrap_or_else(|| DayStart::default().to_string());
+        if !manifest.files.is_empty() && recorded != day_start.to_string() {
+            return Err(anyhow!(
+                "{:?} holds days starting at {}, not {}; pass --day-start {}",
+                dir,
+                recorded,
+                day_start,
+                recorded
+            )
+            .context(Failure::Config));
+        }
+        manifest.day_start = (day_start != DayStart::default()).then(|| day_start.to_string());
+        let recorded = manifest.market.unwrap_or_default();
+        if !manifest.files.is_empty() && recorded != market {
+            return Err(anyhow!(
+                "{:?} holds {} klines, not {}; pass --market {}",
+                dir,
+                recorded.name(),
+                market.name(),
+                recorded.name()
+            )
+            .context(Failure::Config));
+        }
+        manifest.market = (market != Market::default()).then_some(market);
+        manifest.dir = dir.to_path_buf();
+        manifest.adopt_aggregates();
+        Ok(manifest)
+    }
+
+    /// `aggregate` used to record its files as downsampled; one whose
+    /// source is still listed beside it was aggregated.
+    fn adopt_aggregates(&mut self) {
+        let sources: BTreeSet<(String, String, NaiveDate)> = self
+            .files
+            .values()
+            .map(|f| (f.symbol.clone(), f.interval.clone(), f.day))
+            .collect();
+        for f in self.files.values_mut() {
+            let Some(from) = &f.resampled_from else {
+                continue;
+            };
+            if sources.contains(&(f.symbol.clone(), from.clone(), f.day)) {
+                f.aggregated_from = f.resampled_from.take();
+            }
+        }
+    }
+
+    /// The directory the manifest describes.
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn layout(&self) -> Layout {
+        Layout {
+            depth: self.layout_depth.unwrap_or(0),
+        }
+    }
+
+    /// Sets the layout new files go in; a dataset keeps the one it was
+    /// started with.
+    pub(crate) fn set_layout(&mut self, layout: Option<Layout>) -> Result<()> {
+        let Some(layout) = layout else {
+            return Ok(());
+        };
+        if !self.files.is_empty() && layout != self.layout() {
+            return Err(anyhow!(
+                "{:?} has a {} layout, not {}",
+                self.dir,
+                self.layout(),
+                layout
+            )
+            .context(Failure::Config));
+        }
+        self.layout_depth = (layout != Layout::FLAT).then_some(layout.depth);
+        Ok(())
+    }
+
+    /// Sets the layout of a dataset with no files yet, keeping the one a
+    /// dataset already has.
+    pub(crate) fn set_layout_if_new(&mut self, layout: Layout) {
+        if self.files.is_empty() && self.layout_depth.is_none() {
+            self.layout_depth = (layout != Layout::FLAT).then_some(layout.depth);
+        }
+    }
+
+    pub(crate) fn format(&self) -> Format {
+        self.format.unwrap_or_default()
+    }
+
+    /// Sets the format new files are written in; a dataset keeps its files
+    /// in one format, so only once all of them are in it. A new Parquet
+    /// dataset writes f64 values unless its style says otherwise.
+    pub(crate) fn set_format(&mut self, format: Option<Format>) -> Result<()> {
+        let Some(format) = format else {
+            return Ok(());
+        };
+        if format != self.format()
+            && self
+                .files
+                .keys()
+                .any(|key| Format::of(Path::new(key)) != Some(format))
+        {
+            return Err(anyhow!(
+                "{:?} is written as {}, not {}; convert it first",
+                self.dir,
+                self.format().extension(),
+                format.extension()
+            )
+            .context(Failure::Config));
+        }
+        self.format = (format != Format::default()).then_some(format);
+        if format == Format::Parquet && self.files.is_empty() && self.style.is_none() {
+            // typed columns, unless `--numeric text` asks for the exact text
+            self.style = Some(Style {
+                numeric: Numeric::F64,
+                ..Style::default()
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn data(&self) -> DataType {
+        self.data.unwrap_or_default()
+    }
+
+    /// Sets what new files hold; klines and trades don't share a dataset.
+    pub(crate) fn set_data(&mut self, data: DataType) -> Result<()> {
+        if !self.files.is_empty() && data != self.data() {
+            return Err(anyhow!(
+                "{:?} holds {}, not {}; use another --out-dir",
+                self.dir,
+                self.data().name(),
+                data.name()
+            )
+            .context(Failure::Config));
+        }
+        self.data = (data != DataType::default()).then_some(data);
+        Ok(())
+    }
+
+    pub(crate) fn style(&self) -> Style {
+        self.style.unwrap_or_default()
+    }
+
+    /// Sets how new files are written; a dataset keeps the style it was
+    /// started with so its files stay alike.
+    pub(crate) fn set_style(&mut self, style: Style) -> Result<()> {
+        if !self.files.is_empty() && style != self.style() {
+            return Err(anyhow!(
+                "{:?} is written as {:?}, not {:?}; convert it into a new directory instead",
+                self.dir,
+                self.style(),
+                style
+            )
+            .context(Failure::Config));
+        }
+        self.style = (style != Style::default()).then_some(style);
+        Ok(())
+    }
+
+    /// Where a file named `name` of `symbol` and `interval` starting on
+    /// `day` goes.
+    pub(crate) fn path_for(
+        &self,
+        symbol: &str,
+        interval: &str,
+        day: NaiveDate,
+        name: &str,
+    ) -> PathBuf {
+        self.dir
+            .join(self.layout().dir(symbol, interval, day))
+            .join(name)
+    }
+
+    /// Trading session the rows were filtered to.
+    pub(crate) fn session(&self) -> Result<Option<Session>> {
+        self.session
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .with_context(|| format!("invalid session in {:?}", self.dir.join(MANIFEST_FILE)))
+    }
+
+    /// Records the session new files are filtered to; a dataset keeps the
+    /// one it was started with.
+    pub(crate) fn set_session(&mut self, session: Option<&Session>) -> Result<()> {
+        let session = session.map(Session::to_string);
+        if !self.files.is_empty() && session != self.session {
+            return Err(anyhow!(
+                "{:?} holds rows filtered to session {}, not {}",
+                self.dir,
+                self.session.as_deref().unwrap_or("none"),
+                session.as_deref().unwrap_or("none")
+            )
+            .context(Failure::Config));
+        }
+        self.session = session;
+        Ok(())
+    }
+
+    /// Keeps what a selection resolved to unless it's the same as last
+    /// time; returns whether it was new. Takes effect with the next `save`
+    /// or `record`.
+    pub(crate) fn record_selection(&mut self, query: String, symbols: &[String]) -> bool {
+        let unchanged = self
+            .selections
+            .last()
+            .is_some_and(|s| s.query == query && s.symbols == symbols);
+        if !unchanged {
+            self.selections.push(Selection {
+                query,
+                symbols: symbols.to_vec(),
+                resolved_at: Utc::now(),
+            });
+        }
+        !unchanged
+    }
+
+    /// Entries by their path relative to the output directory.
+    pub(crate) fn files(&self) -> impl Iterator<Item = (&str, &FileEntry)> {
+        self.files.iter().map(|(key, entry)| (key.as_str(), entry))
+    }
+
+    /// Forgets a file; takes effect with the next `save` or `record`.
+    pub(crate) fn remove(&mut self, key: &str) -> Option<FileEntry> {
+        self.files.remove(key)
+    }
+
+    /// Whether a complete file covers the job's day, possibly downsampled
+    /// to a coarser interval since.
+    pub(crate) fn is_complete(&self, job: &Job) -> bool {
+        self.files.values().any(|f| {
+            f.complete
+                && f.day <= job.day
+                && job.day <= f.last_day.unwrap_or(f.day)
+                && f.symbol == job.symbol
+                && (f.interval == job.interval || f.resampled_from.as_ref() == Some(&job.interval))
+        })
+    }
+
+    /// Forgets files that are gone, or whose size or rows no longer match
+    /// what was recorded, e.g. cut short by a power loss before they reached
+    /// the disk or damaged since, so they are planned again. Also lists
+    /// daily files the manifest doesn't know yet, as written before there
+    /// was one, and removes temporary files unfinished writes left behind.
+    /// Returns what was forgotten; takes effect with the next `save`.
+    pub(crate) fn verify(&mut self) -> Vec<(String, FileEntry)> {
+        let on_disk = dataset_files(&self.dir).unwrap_or_else(|e| {
+            tracing::warn!("{:#}", e);
+            Vec::new()
+        });
+        remove_stale_tmp(&on_disk);
+        let listed: BTreeSet<String> = self.files.keys().cloned().collect();
+        let reader = DatasetReader::new(self);
+        let day_start = self.day_start();
+        let broken: Vec<String> = self
+            .files
+            .iter()
+            .filter(|(key, entry)| {
+                entry.archive.is_none() && !self.intact(&reader, day_start, key, entry)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        let broken = broken
+            .into_iter()
+            .filter_map(|key| self.files.remove_entry(&key))
+            .collect();
+        if self.data() == DataType::Klines {
+            for path in on_disk {
+                let key = path.strip_prefix(&self.dir).unwrap_or(&path);
+                if !listed.contains(key.to_string_lossy().as_ref()) {
+                    self.adopt(&path, day_start);
+                }
+            }
+        }
+        broken
+    }
+
+    /// Days are cut as the dataset records.
+    fn day_start(&self) -> DayStart {
+        self.day_start
+            .as_deref()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the file of `key` still has the size and rows it was recorded
+    /// with, the last of them on the days it covers.
+    fn intact(
+        &self,
+        reader: &DatasetReader,
+        day_start: DayStart,
+        key: &str,
+        entry: &FileEntry,
+    ) -> bool {
+        if std::fs::metadata(self.dir.join(key)).map_or(true, |m| m.len() != entry.bytes) {
+            return false;
+        }
+        // trades files hold no klines to count
+        if self.data() != DataType::Klines {
+            return true;
+        }
+        match reader.read(key, entry) {
+            Ok(rows) => {
+                rows.len() == entry.rows
+                    && rows.last().is_none_or(|last| {
+                        let day = day_start.day_of(last.open_time);
+                        entry.day <= day && day <= entry.last_day.unwrap_or(entry.day)
+                    })
+            }
+            Err(e) => {
+                tracing::warn!("{:#}", e);
+                false
+            }
+        }
+    }
+
+    /// Lists a daily file of the dataset's format found at `path`, named
+    /// `{symbol}-{interval}-{day}` like `download` names them, if its rows
+    /// all open on that day. It is complete if they run to the day's end.
+    fn adopt(&mut self, path: &Path, day_start: DayStart) {
+        let format = self.format();
+        if Format::of(path) != Some(format) {
+            return;
+        }
+        let Some((symbol, interval, day)) = path
+            .file_name()
+            .and_then(|name| {
+                name.to_str()?
+                    .strip_suffix(&format!(".{}", format.extension()))
+            })
+            .and_then(parse_name)
+        else {
+            return;
+        };
+        let rows = match format.read(path, self.style()) {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::warn!("not listing {:?}: {:#}", path, e);
+                return;
+            }
+        };
+        let (Some(first), Some(last)) = (rows.first(), rows.last()) else {
+            return;
+        };
+        if day_start.day_of(first.open_time) != day || day_start.day_of(last.open_time) != day {
+            tracing::warn!("not listing {:?}: it holds rows of other days", path);
+            return;
+        }
+        let end_ms = day_start.next_day_ms(day_start.start_ms(day));
+        let complete = last.close_time + 1 >= end_ms;
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return;
+        };
+        tracing::info!(
+            "listing {:?}, {} rows{}",
+            path,
+            rows.len(),
+            if complete { "" } else { ", incomplete" }
+        );
+        self.insert(
+            path,
+            FileEntry {
+                symbol,
+                interval,
+                day,
+                last_day: None,
+                hour: None,
+                resampled_from: None,
+                aggregated_from: None,
+                archive: None,
+                rows: rows.len(),
+                bytes: metadata.len(),
+                complete,
+            },
+        );
+    }
+
+    /// Average size of a row over the complete files, if there are any.
+    pub(crate) fn bytes_per_row(&self) -> Option<f64> {
+        let (rows, bytes) = self
+            .files
+            .values()
+            .filter(|f| f.complete)
+            .fold((0, 0), |(rows, bytes), f| (rows + f.rows, bytes + f.bytes));
+        (rows > 0).then(|| bytes as f64 / rows as f64)
+    }
+
+    /// Records `path` and rewrites the manifest atomically.
+    pub(crate) fn record(
+        &mut self,
+        path: &Path,
+        entry: FileEntry,
+        durability: Durability,
+    ) -> Result<()> {
+        self.insert(path, entry);
+        self.save(durability)
+    }
+
+    /// Records `path`; takes effect with the next `save` or `record`.
+    pub(crate) fn insert(&mut self, path: &Path, entry: FileEntry) {
+        let key = path.strip_prefix(&self.dir).unwrap_or(path);
+        self.files.insert(key.to_string_lossy().into_owned(), entry);
+    }
+
+    /// Rewrites the manifest atomically.
+    pub(crate) fn save(&self, durability: Durability) -> Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp", MANIFEST_FILE));
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)?;
+        if durability == Durability::Fsync {
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp, self.dir.join(MANIFEST_FILE))?;
+        if durability == Durability::Fsync {
+            sync_dir(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes the `*.tmp` files among `files` older than [`STALE_TMP`]; one
+/// that can't be removed is only logged.
+fn remove_stale_tmp(files: &[PathBuf]) {
+    for path in files {
+        let stale = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age > STALE_TMP);
+        if stale && path.extension().is_some_and(|e| e == "tmp") {
+            match std::fs::remove_file(path) {
+                Ok(()) => tracing::info!("removed {:?}, left by an unfinished write", path),
+                Err(e) => tracing::warn!("removing {:?}: {}", path, e),
+            }
+        }
+    }
+}
+
+/// Symbol, interval and day of a daily file's name without its extension,
+/// e.g. `ETHUSDC-1s-2024-06-01`.
+fn parse_name(stem: &str) -> Option<(String, String, NaiveDate)> {
+    let (rest, day) = stem.split_at_checked(stem.len().checked_sub(10)?)?;
+    let (symbol, interval) = rest.strip_suffix('-')?.split_once('-')?;
+    interval_ms(interval)?;
+    Some((symbol.to_string(), interval.to_string(), day.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kline::Kline;
+    use crate::writer::{self, DayBatch};
+
+    fn entry(day: &str) -> FileEntry {
+        FileEntry {
+            symbol: "ETHUSDC".to_string(),
+            interval: "1s".to_string(),
+            day: day.parse().unwrap(),
+            last_day: None,
+            hour: None,
+            resampled_from: None,
+            aggregated_from: None,
+            archive: None,
+            rows: 86_400,
+            bytes: 1,
+            complete: true,
+        }
+    }
+
+    #[test]
+    fn new_parquet_datasets_write_f64() {
+        let mut manifest = Manifest::default();
+        manifest.set_format(Some(Format::Parquet)).unwrap();
+        assert_eq!(manifest.style().numeric, Numeric::F64);
+        // `--numeric text` still wins
+        let text = Style::default();
+        manifest.set_style(text).unwrap();
+        assert_eq!(manifest.style(), text);
+    }
+
+    #[test]
+    fn parquet_datasets_with_files_keep_their_style() {
+        let mut manifest = Manifest {
+            format: Some(Format::Parquet),
+            ..Manifest::default()
+        };
+        manifest.files.insert(
+            "ETHUSDC-1s-2024-06-01.parquet".to_string(),
+            entry("2024-06-01"),
+        );
+        manifest.set_format(Some(Format::Parquet)).unwrap();
+        assert_eq!(manifest.style().numeric, Numeric::Text);
+    }
+
+    #[test]
+    fn verify_removes_stale_tmp_files() {
+        let dir = std::env::temp_dir().join(format!("kline-verify-{}", std::process::id()));
+        let sub = dir.join("ETHUSDC");
+        let futures = dir.join("um");
+        std::fs::create_dir_all(&sub).unwrap();
+        std::fs::create_dir_all(&futures).unwrap();
+        let stale = sub.join("ETHUSDC-1s-2024-06-01.csv.tmp");
+        let fresh = sub.join("ETHUSDC-1s-2024-06-02.csv.tmp");
+        let other = futures.join("ETHUSDT-1s-2024-06-01.csv.tmp");
+        for path in [&stale, &fresh, &other] {
+            std::fs::write(path, "open_time").unwrap();
+        }
+        let then = std::time::SystemTime::now() - 2 * STALE_TMP;
+        for path in [&stale, &other] {
+            std::fs::File::options()
+                .append(true)
+                .open(path)
+                .unwrap()
+                .set_modified(then)
+                .unwrap();
+        }
+        let mut manifest = Manifest {
+            dir: dir.clone(),
+            ..Manifest::default()
+        };
+        manifest.verify();
+        assert!(!stale.exists());
+        // it may still be another worker's write
+        assert!(fresh.exists());
+        // the futures dataset is another run's to clean up
+        assert!(other.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Seconds of 2024-06-01 UTC as `download` writes them.
+    fn seconds(range: std::ops::Range<i64>) -> Vec<Kline> {
+        let start_ms = DayStart::default().start_ms("2024-06-01".parse().unwrap());
+        range
+            .map(|s| {
+                let open_time = start_ms + s * 1000;
+                Kline::new(
+                    open_time,
+                    open_time + 999,
+                    1,
+                    ["1", "1", "1", "1", "1", "1", "1", "1", "0"],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn verify_forgets_files_damaged_at_the_same_size() {
+        let dir = std::env::temp_dir().join(format!("kline-damaged-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut manifest = Manifest::load_for(&dir, Market::Spot, DayStart::default()).unwrap();
+        let batch = DayBatch {
+            job: Job {
+                symbol: "ETHUSDC".to_string(),
+                interval: "1s".to_string(),
+                day: "2024-06-01".parse().unwrap(),
+            },
+            rows: seconds(0..100),
+            complete: false,
+        };
+        writer::write_day(&mut manifest, &batch, Durability::None).unwrap();
+        assert!(manifest.verify().is_empty());
+        // rewritten to the same length, one row short
+        let path = dir.join("ETHUSDC-1s-2024-06-01.csv");
+        let data = std::fs::read_to_string(&path).unwrap();
+        let (last, _) = data.trim_end().rsplit_once('\n').unwrap();
+        let padded = format!("{}\n{}", last, " ".repeat(data.len() - last.len() - 1));
+        std::fs::write(&path, padded).unwrap();
+        let broken = manifest.verify();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(manifest.files().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verify_lists_daily_files_from_before_the_manifest() {
+        let dir = std::env::temp_dir().join(format!("kline-adopt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, rows: &[Kline]| {
+            let mut wtr = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_path(dir.join(name))
+                .unwrap();
+            for row in rows {
+                wtr.serialize(row).unwrap();
+            }
+            wtr.flush().unwrap();
+        };
+        write("ETHUSDC-1s-2024-06-01.csv", &seconds(0..86_400));
+        write("ETHUSDC-1s-2024-06-02.csv", &seconds(86_400..86_500));
+        // its rows aren't of the day it's named after
+        write("BTCUSDC-1s-2024-06-03.csv", &seconds(0..10));
+        std::fs::write(dir.join("notes.csv"), "not klines").unwrap();
+        let mut manifest = Manifest::load_for(&dir, Market::Spot, DayStart::default()).unwrap();
+        assert!(manifest.verify().is_empty());
+        let listed: Vec<(&str, usize, bool)> = manifest
+            .files()
+            .map(|(key, e)| (key, e.rows, e.complete))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("ETHUSDC-1s-2024-06-01.csv", 86_400, true),
+                ("ETHUSDC-1s-2024-06-02.csv", 100, false),
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn aggregates_recorded_as_downsampled_are_adopted() {
+        let mut manifest = Manifest::default();
+        manifest
+            .files
+            .insert("a-1s".to_string(), entry("2024-06-01"));
+        for (key, day) in [("a-1m", "2024-06-01"), ("a-1m-old", "2024-05-01")] {
+            let downsampled = FileEntry {
+                interval: "1m".to_string(),
+                resampled_from: Some("1s".to_string()),
+                ..entry(day)
+            };
+            manifest.files.insert(key.to_string(), downsampled);
+        }
+        manifest.adopt_aggregates();
+        // its 1s file is still there, so it was aggregated
+        assert_eq!(
+            manifest.files["a-1m"].aggregated_from.as_deref(),
+            Some("1s")
+        );
+        assert_eq!(manifest.files["a-1m"].resampled_from, None);
+        // this one replaced its 1s file
+        assert_eq!(
+            manifest.files["a-1m-old"].resampled_from.as_deref(),
+            Some("1s")
+        );
+        assert_eq!(manifest.files["a-1m-old"].aggregated_from, None);
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::OnceLock;
+
+/// The Binance market klines come from. Futures endpoints accept up to 1500
+/// rows a request, but charge twice the weight above 1000, so requests stay
+/// at 1000 rows on every market.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Market {
+    /// Spot pairs on api.binance.com
+    #[default]
+    Spot,
+    /// USDⓈ-margined futures on fapi.binance.com, e.g. ETHUSDT perpetuals
+    UsdmFutures,
+    /// Coin-margined futures on dapi.binance.com, e.g. ETHUSD_PERP
+    CoinmFutures,
+}
+
+impl Market {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Market::Spot => "spot",
+            Market::UsdmFutures => "usdm-futures",
+            Market::CoinmFutures => "coinm-futures",
+        }
+    }
+
+    /// REST endpoints up to the version, e.g. `{api}/klines`.
+    pub(crate) fn api(self) -> &'static str {
+        match self {
+            Market::Spot => "https://api.binance.com/api/v3",
+            Market::UsdmFutures => "https://fapi.binance.com/fapi/v1",
+            Market::CoinmFutures => "https://dapi.binance.com/dapi/v1",
+        }
+    }
+
+    /// Combined WebSocket streams, see `download --follow`.
+    pub(crate) fn stream_url(self) -> &'static str {
+        match self {
+            Market::Spot => "wss://stream.binance.com:9443/stream",
+            Market::UsdmFutures => "wss://fstream.binance.com/stream",
+            Market::CoinmFutures => "wss://dstream.binance.com/stream",
+        }
+    }
+
+    /// Daily archives on data.binance.vision, see `download --vision`.
+    pub(crate) fn vision_url(self) -> &'static str {
+        match self {
+            Market::Spot => "https://data.binance.vision/data/spot/daily",
+            Market::UsdmFutures => "https://data.binance.vision/data/futures/um/daily",
+            Market::CoinmFutures => "https://data.binance.vision/data/futures/cm/daily",
+        }
+    }
+
+    /// Request weight allowed per minute, reported back in
+    /// `X-MBX-USED-WEIGHT-1M` on every market.
+    pub(crate) fn weight_limit(self) -> u32 {
+        match self {
+            Market::Spot => 6000,
+            Market::UsdmFutures | Market::CoinmFutures => 2400,
+        }
+    }
+
+    /// Weight of a klines request of up to 1000 rows.
+    pub(crate) fn klines_weight(self) -> u64 {
+        match self {
+            Market::Spot => 2,
+            Market::UsdmFutures | Market::CoinmFutures => 5,
+        }
+    }
+
+    /// Directory a futures dataset goes in below the output directory,
+    /// named as on data.binance.vision, with a manifest of its own; spot
+    /// files stay where they always were.
+    pub(crate) fn dir(self) -> Option<&'static str> {
+        match self {
+            Market::Spot => None,
+            Market::UsdmFutures => Some("um"),
+            Market::CoinmFutures => Some("cm"),
+        }
+    }
+}
+
+static MARKET: OnceLock<Market> = OnceLock::new();
+
+/// Sets the market for the rest of the process; call once at startup
+/// before anything asks for it.
+pub(crate) fn init(market: Market) {
+    MARKET.set(market).expect("market is already set");
+}
+
+pub(crate) fn market() -> Market {
+    *MARKET.get_or_init(Market::default)
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Months, NaiveDate};
+
+use crate::day::{day_start_ms, next_day_ms};
+use crate::download::{interval_ms, OUT_DIR};
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::kline::Kline;
+use crate::manifest::{FileEntry, Manifest};
+use crate::writer::{sync_dir, Durability};
+
+#[derive(clap::Args)]
+pub(crate) struct MergeArgs {
+    /// Dataset to merge, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = Granularity::Monthly)]
+    granularity: Granularity,
+    /// Delete the daily files once the merged file has been verified
+    #[arg(long)]
+    delete_sources: bool,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum Granularity {
+    Monthly,
+    Quarterly,
+}
+
+impl Granularity {
+    /// Label and first and last day of the period `day` falls in.
+    fn period(self, day: NaiveDate) -> (String, NaiveDate, NaiveDate) {
+        let months = match self {
+            Granularity::Monthly => 1,
+            Granularity::Quarterly => 3,
+        };
+        let first =
+            NaiveDate::from_ymd_opt(day.year(), day.month0() / months * months + 1, 1).unwrap();
+        let last = (first + Months::new(months)).pred_opt().unwrap();
+        let label = match self {
+            Granularity::Monthly => first.format("%Y-%m").to_string(),
+            Granularity::Quarterly => format!("{}-Q{}", first.year(), first.month0() / 3 + 1),
+        };
+        (label, first, last)
+    }
+}
+
+/// Complete daily files of one symbol, interval and format in one period.
+struct Group {
+    first: NaiveDate,
+    last: NaiveDate,
+    days: BTreeMap<NaiveDate, (String, FileEntry)>,
+}
+
+pub(crate) async fn run(args: MergeArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || merge(&args)).await?
+}
+
+/// Merges the periods whose days are all present and complete; a period
+/// still missing days is left alone.
+fn merge(args: &MergeArgs) -> Result<()> {
+    let mut manifest = Manifest::load(&args.dir)?;
+    let mut groups: BTreeMap<(String, String, String, &str), Group> = BTreeMap::new();
+    for (key, entry) in manifest.files() {
+        let Some(format) = Format::of(Path::new(key)) else {
+            continue;
+        };
+        if entry.last_day.is_some()
+            || entry.hour.is_some()
+            || entry.archive.is_some()
+            || !entry.complete
+        {
+            continue;
+        }
+        let (label, first, last) = args.granularity.period(entry.day);
+        groups
+            .entry((
+                entry.symbol.clone(),
+                entry.interval.clone(),
+                label,
+                format.extension(),
+            ))
+            .or_insert_with(|| Group {
+                first,
+                last,
+                days: BTreeMap::new(),
+            })
+            .days
+            .insert(entry.day, (key.to_string(), entry.clone()));
+    }
+
+    let mut merged = 0;
+    for ((symbol, interval, label, ext), group) in groups {
+        let expected = group
+            .first
+            .iter_days()
+            .take_while(|d| *d <= group.last)
+            .count();
+        if group.days.len() != expected {
+            tracing::info!(
+                "{} {} {}: {} of {} days complete, not merging",
+                symbol,
+                interval,
+                label,
+                group.days.len(),
+                expected
+            );
+            continue;
+        }
+        let step_ms = interval_ms(&interval)
+            .ok_or_else(|| anyhow!("unsupported interval {:?}", interval))
+            .context(Failure::Config)?;
+
+        let path = manifest.path_for(
+            &symbol,
+            &interval,
+            group.first,
+            &format!("{}-{}-{}.{}", symbol, interval, label, ext),
+        );
+        let format = Format::of(&path).unwrap();
+        let mut rows: Vec<Kline> = Vec::new();
+        for (day, (key, entry)) in &group.days {
+            let source = args.dir.join(key);
+            let day_rows = format
+                .read(&source, manifest.style())
+                .with_context(|| format!("reading {:?}", source))?;
+            if day_rows.len() != entry.rows {
+                return Err(anyhow!(
+                    "{:?} has {} rows, the manifest recorded {}",
+                    source,
+                    day_rows.len(),
+                    entry.rows
+                )
+                .context(Failure::Validation));
+            }
+            check_continuity(&rows, &day_rows, *day, step_ms)?;
+            rows.extend(day_rows);
+        }
+
+        let parent = path.parent().unwrap_or(&args.dir);
+        std::fs::create_dir_all(parent)?;
+        let tmp = path.with_extension(format!("{}.tmp", ext));
+        format.write(&tmp, &rows, manifest.style(), args.durability)?;
+        let written = format.read(&tmp, manifest.style())?.len();
+        if written != rows.len() {
+            std::fs::remove_file(&tmp)?;
+            return Err(anyhow!(
+                "{:?} read back {} rows, {} were written",
+                tmp,
+                written,
+                rows.len()
+            )
+            .context(Failure::Validation));
+        }
+        std::fs::rename(&tmp, &path)?;
+        if args.durability == Durability::Fsync {
+            sync_dir(parent)?;
+        }
+
+        // the manifest drops the dailies in the same rewrite that adds the
+        // merged file, so it never lists a file that is already deleted
+        if args.delete_sources {
+            for (key, _) in group.days.values() {
+                manifest.remove(key);
+            }
+        }
+        manifest.record(
+            &path,
+            FileEntry {
+                symbol: symbol.clone(),
+                interval: interval.clone(),
+                day: group.first,
+                last_day: Some(group.last),
+                hour: None,
+                resampled_from: None,
+                aggregated_from: None,
+                archive: None,
+                rows: rows.len(),
+                bytes: std::fs::metadata(&path)?.len(),
+                complete: true,
+            },
+            args.durability,
+        )?;
+        if args.delete_sources {
+            for (key, _) in group.days.values() {
+                std::fs::remove_file(args.dir.join(key))?;
+            }
+        }
+        merged += 1;
+        tracing::info!(
+            "merged {} days into {:?}, {} rows",
+            group.days.len(),
+            path,
+            rows.len()
+        );
+    }
+    tracing::info!("merged {} period(s)", merged);
+    Ok(())
+}
+
+/// Checks that `next` lies within `day`, is in order and follows on from the
+/// rows before it. A gap across midnight is only a warning since the
+/// exchange has outages; rows out of place are an error.
+fn check_continuity(before: &[Kline], next: &[Kline], day: NaiveDate, step_ms: i64) -> Result<()> {
+    let start_ms = day_start_ms(day);
+    let end_ms = next_day_ms(start_ms);
+    if let Some(row) = next
+        .iter()
+        .find(|r| r.open_time < start_ms || r.open_time >= end_ms)
+    {
+        return Err(
+            anyhow!("{}: row at {} is outside the day", day, row.open_time)
+                .context(Failure::Validation),
+        );
+    }
+    if next.windows(2).any(|w| w[1].open_time <= w[0].open_time) {
+        return Err(anyhow!("{}: rows out of order", day).context(Failure::Validation));
+    }
+    if let (Some(prev), Some(first)) = (before.last(), next.first()) {
+        if first.open_time - prev.open_time > step_ms {
+            tracing::warn!(
+                "{}: {} ms missing across midnight, {} -> {}",
+                day,
+                first.open_time - prev.open_time - step_ms,
+                prev.open_time,
+                first.open_time
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::adaptive::MAX_WINDOW_ROWS;
+use crate::day::{day_of, day_start_ms, next_day_ms};
+use crate::manifest::Manifest;
+use crate::market::market;
+use crate::queue::Job;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub(crate) enum Order {
+    #[default]
+    OldestFirst,
+    NewestFirst,
+}
+
+/// The days a run still has to fetch, in the order they should be claimed.
+pub(crate) struct Plan {
+    pub(crate) jobs: Vec<Job>,
+    pub(crate) skipped: usize,
+    pub(crate) rows: u64,
+    pub(crate) requests: u64,
+}
+
+impl Plan {
+    pub(crate) fn weight(&self) -> u64 {
+        self.requests * market().klines_weight()
+    }
+
+    /// Lower bound on the run time imposed by the per-minute weight limit.
+    pub(crate) fn min_minutes(&self) -> u64 {
+        self.weight().div_ceil(u64::from(market().weight_limit()))
+    }
+}
+
+/// Expands `[start_ms, end_ms]` into one job per symbol and day, leaving
+/// out days the manifest already has complete.
+pub(crate) fn plan(
+    symbols: &[String],
+    interval: &str,
+    interval_ms: i64,
+    start_ms: i64,
+    end_ms: i64,
+    manifest: &Manifest,
+    order: Order,
+) -> Plan {
+    let mut jobs = Vec::new();
+    let mut skipped = 0;
+    let mut total_rows = 0;
+    let mut requests = 0;
+    let first = day_of(start_ms);
+    let last = day_of(end_ms);
+    for day in first.iter_days().take_while(|day| *day <= last) {
+        let start_ms = day_start_ms(day);
+        let day_ms = (next_day_ms(start_ms).min(end_ms + 1) - start_ms).max(0);
+        let rows = (day_ms / interval_ms) as u64;
+        for symbol in symbols {
+            let job = Job {
+                symbol: symbol.clone(),
+                interval: interval.to_string(),
+                day,
+            };
+            if manifest.is_complete(&job) {
+                skipped += 1;
+                continue;
+            }
+            total_rows += rows;
+            requests += rows.div_ceil(u64::from(MAX_WINDOW_ROWS));
+            jobs.push(job);
+        }
+    }
+    if let Order::NewestFirst = order {
+        jobs.reverse();
+    }
+    Plan {
+        jobs,
+        skipped,
+        rows: total_rows,
+        requests,
+    }
+}
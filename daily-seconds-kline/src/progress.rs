@@ -0,0 +1,238 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use tokio::task::JoinHandle;
+
+use crate::exit::Failure;
+use crate::market::market;
+use crate::queue::Job;
+
+/// Symbol, interval and day of a daily file.
+type DayKey = (String, String, NaiveDate);
+
+struct State {
+    started: Instant,
+    /// Days planned and done per symbol; a worker only learns of days as
+    /// it writes them
+    symbols: BTreeMap<String, SymbolState>,
+    days: BTreeMap<DayKey, DayState>,
+    /// Candles missing from days about to be written
+    gaps: BTreeMap<DayKey, u64>,
+    used_weight: Option<u32>,
+    retries: u32,
+    logger: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct SymbolState {
+    planned: usize,
+    done: usize,
+    rows: u64,
+}
+
+#[derive(serde::Serialize)]
+struct DayState {
+    rows: usize,
+    gaps: u64,
+    bytes: u64,
+    complete: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            symbols: BTreeMap::new(),
+            days: BTreeMap::new(),
+            gaps: BTreeMap::new(),
+            used_weight: None,
+            retries: 0,
+            logger: None,
+        }
+    }
+}
+
+/// The run's final report, for monitoring to pick up.
+#[derive(serde::Serialize)]
+struct Summary {
+    ok: bool,
+    error: Option<String>,
+    elapsed_secs: u64,
+    /// Days written complete
+    days_written: usize,
+    rows: u64,
+    bytes: u64,
+    gaps: u64,
+    retries: u32,
+    /// Every day written, incomplete ones included, last write only
+    days: Vec<DaySummary>,
+}
+
+#[derive(serde::Serialize)]
+struct DaySummary {
+    symbol: String,
+    interval: String,
+    day: NaiveDate,
+    #[serde(flatten)]
+    state: DayState,
+}
+
+/// How far a run has come, shared by the download loop, the requests and
+/// the writer.
+#[derive(Clone, Default)]
+pub(crate) struct Progress(Arc<Mutex<State>>);
+
+impl Progress {
+    pub(crate) fn planned(&self, jobs: &[Job]) {
+        let mut state = self.0.lock().unwrap();
+        for job in jobs {
+            state.symbols.entry(job.symbol.clone()).or_default().planned += 1;
+        }
+    }
+
+    pub(crate) fn used_weight(&self, weight: u32) {
+        self.0.lock().unwrap().used_weight = Some(weight);
+    }
+
+    pub(crate) fn retried(&self) {
+        self.0.lock().unwrap().retries += 1;
+    }
+
+    /// Candles missing from a day that is about to be written.
+    pub(crate) fn gaps(&self, job: &Job, missing: u64) {
+        self.0.lock().unwrap().gaps.insert(key(job), missing);
+    }
+
+    /// A day was written; a day written again, like today's, replaces its
+    /// earlier write.
+    pub(crate) fn written(&self, job: &Job, rows: usize, bytes: u64, complete: bool) {
+        let mut state = self.0.lock().unwrap();
+        let key = key(job);
+        let gaps = state.gaps.remove(&key).unwrap_or(0);
+        let day = DayState {
+            rows,
+            gaps,
+            bytes,
+            complete,
+        };
+        let was_complete = state.days.insert(key, day).is_some_and(|d| d.complete);
+        let symbol = state.symbols.entry(job.symbol.clone()).or_default();
+        if complete && !was_complete {
+            symbol.done += 1;
+            symbol.rows += rows as u64;
+        }
+    }
+
+    /// Logs the progress every `period` until [`Progress::finish`].
+    pub(crate) fn log_every(&self, period: Duration) {
+        let progress = self.clone();
+        let logger = tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(period);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                progress.log();
+            }
+        });
+        self.0.lock().unwrap().logger = Some(logger);
+    }
+
+    /// Each symbol part of the way through its days, then the whole run
+    /// with an ETA from the pace so far.
+    fn log(&self) {
+        let state = self.0.lock().unwrap();
+        for (symbol, s) in &state.symbols {
+            if s.done > 0 && s.done < s.planned {
+                tracing::info!(
+                    "{}: {}/{} day(s), {} rows",
+                    symbol,
+                    s.done,
+                    s.planned,
+                    s.rows
+                );
+            }
+        }
+        let planned: usize = state.symbols.values().map(|s| s.planned).sum();
+        let done: usize = state.symbols.values().map(|s| s.done).sum();
+        // only days written are known without a plan
+        let of = if planned > 0 {
+            format!("/{}", planned)
+        } else {
+            String::new()
+        };
+        let weight = state.used_weight.map_or("-".to_string(), |w| w.to_string());
+        let eta = if done > 0 && planned > done {
+            let left = state
+                .started
+                .elapsed()
+                .mul_f64((planned - done) as f64 / done as f64);
+            format!(", ETA {} min", left.as_secs().div_ceil(60))
+        } else {
+            String::new()
+        };
+        tracing::info!(
+            "{}{} day(s) done, weight {}/{}, {} retries{}",
+            done,
+            of,
+            weight,
+            market().weight_limit(),
+            state.retries,
+            eta
+        );
+    }
+
+    /// Stops the log and writes the summary to `path`, `-` for stdout. The
+    /// run's own error wins over one writing the summary.
+    pub(crate) fn finish(&self, path: Option<&Path>, result: Result<()>) -> Result<()> {
+        let logger = self.0.lock().unwrap().logger.take();
+        if let Some(logger) = logger {
+            logger.abort();
+            self.log();
+        }
+        let Some(path) = path else {
+            return result;
+        };
+        let written = self.write_summary(path, &result);
+        result.and(written)
+    }
+
+    fn write_summary(&self, path: &Path, result: &Result<()>) -> Result<()> {
+        let mut state = self.0.lock().unwrap();
+        let days: Vec<DaySummary> = std::mem::take(&mut state.days)
+            .into_iter()
+            .map(|((symbol, interval, day), state)| DaySummary {
+                symbol,
+                interval,
+                day,
+                state,
+            })
+            .collect();
+        let summary = Summary {
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            elapsed_secs: state.started.elapsed().as_secs(),
+            days_written: days.iter().filter(|d| d.state.complete).count(),
+            rows: days.iter().map(|d| d.state.rows as u64).sum(),
+            bytes: days.iter().map(|d| d.state.bytes).sum(),
+            gaps: days.iter().map(|d| d.state.gaps).sum(),
+            retries: state.retries,
+            days,
+        };
+        let json = serde_json::to_string_pretty(&summary)?;
+        if path == Path::new("-") {
+            println!("{}", json);
+            return Ok(());
+        }
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("writing the summary to {:?}", path))
+            .context(Failure::Config)
+    }
+}
+
+fn key(job: &Job) -> DayKey {
+    (job.symbol.clone(), job.interval.clone(), job.day)
+}
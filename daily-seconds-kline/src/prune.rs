@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{Days, Months, NaiveDate, Utc};
+
+use crate::download::OUT_DIR;
+use crate::exit::Failure;
+use crate::manifest::{FileEntry, Manifest};
+use crate::writer::Durability;
+
+#[derive(clap::Args)]
+pub(crate) struct PruneArgs {
+    /// Dataset to prune, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    #[command(flatten)]
+    retention: Retention,
+    /// List what would be pruned without touching anything
+    #[arg(long)]
+    dry_run: bool,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+/// Which files to prune and where they go; `worker` applies it after every
+/// file it writes.
+#[derive(clap::Args, Clone, Default)]
+pub(crate) struct Retention {
+    /// Keep files of an interval this long, e.g. `1s=30d` or `1m=12mo`;
+    /// repeatable. Intervals without a rule and files bundled by `archive` are kept
+    #[arg(long = "keep", value_name = "INTERVAL=AGE")]
+    rules: Vec<Rule>,
+    /// Move pruned files into this dataset instead of deleting them
+    #[arg(long)]
+    archive_to: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    interval: String,
+    age: Age,
+}
+
+/// How long to keep files: `30d`, `8w` or `6mo`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Age {
+    Days(u64),
+    Months(u32),
+}
+
+impl FromStr for Age {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid age {:?}, e.g. 30d, 8w or 6mo", s);
+        let (count, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(0));
+        let count: u32 = count.parse().map_err(|_| invalid())?;
+        match unit {
+            "d" => Ok(Age::Days(count.into())),
+            "w" => Ok(Age::Days(u64::from(count) * 7)),
+            "mo" => Ok(Age::Months(count)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Age {
+    /// Files whose last day is before this are older.
+    pub(crate) fn cutoff(self, today: NaiveDate) -> NaiveDate {
+        match self {
+            Age::Days(days) => today - Days::new(days),
+            Age::Months(months) => today - Months::new(months),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (interval, age) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected INTERVAL=AGE, e.g. 1s=30d"))?;
+        Ok(Self {
+            interval: interval.to_string(),
+            age: age.parse()?,
+        })
+    }
+}
+
+impl Retention {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn expired(&self, manifest: &Manifest, today: NaiveDate) -> Vec<(String, FileEntry)> {
+        manifest
+            .files()
+            .filter(|(_, e)| {
+                e.archive.is_none()
+                    && self.rules.iter().any(|r| {
+                        r.interval == e.interval
+                            && e.last_day.unwrap_or(e.day) < r.age.cutoff(today)
+                    })
+            })
+            .map(|(key, entry)| (key.to_string(), entry.clone()))
+            .collect()
+    }
+
+    /// Deletes or archives the expired files of `manifest`, returning how
+    /// many. The archive's manifest is saved before the files leave this
+    /// one, so a crash in between lists them twice rather than nowhere.
+    pub(crate) fn apply(
+        &self,
+        dir: &Path,
+        manifest: &mut Manifest,
+        durability: Durability,
+    ) -> Result<usize> {
+        let expired = self.expired(manifest, Utc::now().date_naive());
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        let mut archive = match &self.archive_to {
+            Some(archive_dir) => {
+                std::fs::create_dir_all(archive_dir)
+                    .with_context(|| format!("creating {:?}", archive_dir))?;
+                let mut archive = Manifest::load(archive_dir)?;
+                archive.set_layout(Some(manifest.layout()))?;
+                Some((archive_dir, archive))
+            }
+            None => None,
+        };
+        for (key, entry) in &expired {
+            let source = dir.join(key);
+            match &mut archive {
+                Some((archive_dir, archive)) => {
+                    let target = archive_dir.join(key);
+                    move_file(&source, &target)?;
+                    archive.insert(&target, entry.clone());
+                    tracing::info!("archived {:?} to {:?}", source, target);
+                }
+                None => {
+                    std::fs::remove_file(&source)
+                        .or_else(|e| match e.kind() {
+                            std::io::ErrorKind::NotFound => Ok(()),
+                            _ => Err(e),
+                        })
+                        .with_context(|| format!("deleting {:?}", source))?;
+                    tracing::info!("deleted {:?}", source);
+                }
+            }
+            manifest.remove(key);
+        }
+        if let Some((_, archive)) = &archive {
+            archive.save(durability)?;
+        }
+        manifest.save(durability)?;
+        Ok(expired.len())
+    }
+}
+
+pub(crate) async fn run(args: PruneArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || prune(&args)).await?
+}
+
+fn prune(args: &PruneArgs) -> Result<()> {
+    if args.retention.is_empty() {
+        return Err(anyhow!("nothing to prune without a --keep rule").context(Failure::Config));
+    }
+    let mut manifest = Manifest::load(&args.dir)?;
+    if args.dry_run {
+        let expired = args.retention.expired(&manifest, Utc::now().date_naive());
+        for (key, entry) in &expired {
+            println!("{} {} bytes", key, entry.bytes);
+        }
+        tracing::info!(
+            "would prune {} file(s), {} MiB",
+            expired.len(),
+            expired.iter().map(|(_, e)| e.bytes).sum::<u64>() >> 20
+        );
+        return Ok(());
+    }
+    let pruned = args
+        .retention
+        .apply(&args.dir, &mut manifest, args.durability)?;
+    tracing::info!("pruned {} file(s)", pruned);
+    Ok(())
+}
+
+/// Renames `source`, or copies and deletes it if `target` is on another
+/// file system.
+fn move_file(source: &Path, target: &Path) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::rename(source, target) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            std::fs::copy(source, target)?;
+            std::fs::remove_file(source)?;
+            Ok(())
+        }
+        result => result.with_context(|| format!("moving {:?} to {:?}", source, target)),
+    }
+}
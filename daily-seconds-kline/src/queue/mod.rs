@@ -0,0 +1,134 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+
+use crate::market::market;
+
+mod postgres;
+mod sqlite;
+
+/// How long a claimed job stays reserved for the worker that claimed it.
+const LEASE: Duration = Duration::from_secs(30 * 60);
+/// How long a failed job waits before any worker may claim it again.
+const FAILED_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// One unit of work: a single day of one symbol and interval, as
+/// `--day-start` cuts days.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Job {
+    pub(crate) symbol: String,
+    pub(crate) interval: String,
+    pub(crate) day: NaiveDate,
+}
+
+/// Persisted download jobs. Workers claim a job under a lease and it only
+/// becomes `done` after its file has been written and synced, so a crashed
+/// worker's jobs become claimable again once the lease runs out.
+///
+/// The default store is a SQLite file next to the output; a `postgres://`
+/// URL shares one queue between workers on several machines.
+#[derive(Clone)]
+pub(crate) struct Queue {
+    store: Store,
+    worker: String,
+    opened_at: i64,
+}
+
+#[derive(Clone)]
+enum Store {
+    Sqlite(sqlite::SqliteQueue),
+    Postgres(postgres::PostgresQueue),
+}
+
+impl Queue {
+    /// Jobs of the current `--market`; a queue shared between markets
+    /// keeps theirs apart.
+    pub(crate) async fn open(url: Option<&str>, out_dir: &Path) -> Result<Self> {
+        let market = market().name();
+        let store = match url {
+            Some(url) if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+                Store::Postgres(postgres::PostgresQueue::connect(url, market).await?)
+            }
+            Some(path) => Store::Sqlite(sqlite::SqliteQueue::open(Path::new(path), market)?),
+            None => Store::Sqlite(sqlite::SqliteQueue::open(
+                &out_dir.join(".queue.sqlite3"),
+                market,
+            )?),
+        };
+        Ok(Self {
+            store,
+            worker: worker_id(),
+            opened_at: now(),
+        })
+    }
+
+    /// Adds jobs that aren't queued yet; existing ones keep their state.
+    /// Jobs are claimed in the order given.
+    pub(crate) async fn enqueue(&self, jobs: &[Job]) -> Result<()> {
+        match &self.store {
+            Store::Sqlite(q) => q.enqueue(jobs).await,
+            Store::Postgres(q) => q.enqueue(jobs).await,
+        }
+    }
+
+    /// Reserves the next job up to `until` that is neither done nor held by
+    /// another worker. A job is handed out at most once per `Queue`, so days
+    /// given back unfinished aren't fetched again in the same run.
+    pub(crate) async fn claim(&self, until: NaiveDate) -> Result<Option<Job>> {
+        match &self.store {
+            Store::Sqlite(q) => q.claim(&self.worker, self.opened_at, until).await,
+            Store::Postgres(q) => q.claim(&self.worker, self.opened_at, until).await,
+        }
+    }
+
+    pub(crate) async fn mark_done(&self, job: &Job) -> Result<()> {
+        self.set_state(job, "done", None, Duration::ZERO).await
+    }
+
+    /// Gives back a job whose day isn't over yet, so it stays pending.
+    pub(crate) async fn release(&self, job: &Job) -> Result<()> {
+        self.set_state(job, "pending", None, Duration::ZERO).await
+    }
+
+    /// Makes a done job claimable again, e.g. once its file turned out to
+    /// be lost.
+    pub(crate) async fn requeue(&self, job: &Job) -> Result<()> {
+        self.release(job).await
+    }
+
+    pub(crate) async fn mark_failed(&self, job: &Job, error: &str) -> Result<()> {
+        self.set_state(job, "failed", Some(error), FAILED_BACKOFF)
+            .await
+    }
+
+    async fn set_state(
+        &self,
+        job: &Job,
+        state: &str,
+        error: Option<&str>,
+        hold: Duration,
+    ) -> Result<()> {
+        let leased_until = now() + hold.as_millis() as i64;
+        match &self.store {
+            Store::Sqlite(q) => q.set_state(job, state, error, leased_until).await,
+            Store::Postgres(q) => q.set_state(job, state, error, leased_until).await,
+        }
+    }
+}
+
+/// Identifies this process in the queue's `worker` column.
+fn worker_id() -> String {
+    let host = std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+    format!("{}-{}", host, std::process::id())
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use tokio_postgres::{Client, NoTls};
+
+use super::{now, Job, LEASE};
+
+#[derive(Clone)]
+pub(crate) struct PostgresQueue {
+    client: Arc<Client>,
+    /// Only this market's jobs are queued and claimed
+    market: &'static str,
+}
+
+impl PostgresQueue {
+    pub(crate) async fn connect(url: &str, market: &'static str) -> Result<Self> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::error!("queue connection closed: {}", e);
+            }
+        });
+        // queues from before `--market` only held spot jobs
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS kline_jobs (
+                    market TEXT NOT NULL DEFAULT 'spot',
+                    symbol TEXT NOT NULL,
+                    interval TEXT NOT NULL,
+                    day DATE NOT NULL,
+                    state TEXT NOT NULL DEFAULT 'pending',
+                    priority INTEGER NOT NULL DEFAULT 0,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    last_error TEXT,
+                    worker TEXT,
+                    leased_until BIGINT NOT NULL DEFAULT 0,
+                    updated_at BIGINT NOT NULL,
+                    PRIMARY KEY (market, symbol, interval, day)
+                );
+                DO $$ BEGIN
+                    IF NOT EXISTS (
+                        SELECT 1 FROM information_schema.columns
+                        WHERE table_name = 'kline_jobs' AND column_name = 'market'
+                    ) THEN
+                        ALTER TABLE kline_jobs ADD COLUMN market TEXT NOT NULL DEFAULT 'spot';
+                        ALTER TABLE kline_jobs DROP CONSTRAINT kline_jobs_pkey;
+                        ALTER TABLE kline_jobs ADD PRIMARY KEY (market, symbol, interval, day);
+                    END IF;
+                END $$;",
+            )
+            .await?;
+        Ok(Self {
+            client: Arc::new(client),
+            market,
+        })
+    }
+
+    pub(crate) async fn enqueue(&self, jobs: &[Job]) -> Result<()> {
+        let stmt = self
+            .client
+            .prepare(
+                "INSERT INTO kline_jobs (symbol, interval, day, priority, updated_at, market)
+                 VALUES ($1, $2, $3, $4, $5, $6)
+                 ON CONFLICT (market, symbol, interval, day) DO UPDATE SET priority = excluded.priority",
+            )
+            .await?;
+        for (priority, job) in jobs.iter().enumerate() {
+            self.client
+                .execute(
+                    &stmt,
+                    &[
+                        &job.symbol,
+                        &job.interval,
+                        &job.day,
+                        &(priority as i32),
+                        &now(),
+                        &self.market,
+                    ],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// `SKIP LOCKED` lets concurrent workers claim different rows without
+    /// waiting on each other.
+    pub(crate) async fn claim(
+        &self,
+        worker: &str,
+        since: i64,
+        until: NaiveDate,
+    ) -> Result<Option<Job>> {
+        let now = now();
+        let row = self
+            .client
+            .query_opt(
+                "UPDATE kline_jobs SET state = 'running', worker = $2, leased_until = $3, updated_at = $1
+                 WHERE (market, symbol, interval, day) = (
+                     SELECT market, symbol, interval, day FROM kline_jobs
+                     WHERE market = $6 AND state != 'done' AND leased_until < $1 AND day <= $4
+                         AND (COALESCE(worker, '') != $2 OR updated_at < $5)
+                     ORDER BY priority, day, symbol, interval
+                     LIMIT 1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING symbol, interval, day",
+                &[
+                    &now,
+                    &worker,
+                    &(now + LEASE.as_millis() as i64),
+                    &until,
+                    &since,
+                    &self.market,
+                ],
+            )
+            .await?;
+        Ok(row.map(|row| Job {
+            symbol: row.get(0),
+            interval: row.get(1),
+            day: row.get(2),
+        }))
+    }
+
+    pub(crate) async fn set_state(
+        &self,
+        job: &Job,
+        state: &str,
+        error: Option<&str>,
+        leased_until: i64,
+    ) -> Result<()> {
+        self.client
+            .execute(
+                "UPDATE kline_jobs SET state = $4, last_error = $5, leased_until = $6, updated_at = $7,
+                     attempts = attempts + CASE WHEN $4 = 'failed' THEN 1 ELSE 0 END
+                 WHERE market = $8 AND symbol = $1 AND interval = $2 AND day = $3",
+                &[
+                    &job.symbol,
+                    &job.interval,
+                    &job.day,
+                    &state,
+                    &error,
+                    &leased_until,
+                    &now(),
+                    &self.market,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
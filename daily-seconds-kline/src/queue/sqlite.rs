@@ -0,0 +1,217 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use super::{now, Job, LEASE};
+
+#[derive(Clone)]
+pub(crate) struct SqliteQueue {
+    conn: Arc<Mutex<Connection>>,
+    /// Only this market's jobs are queued and claimed
+    market: &'static str,
+}
+
+impl SqliteQueue {
+    pub(crate) fn open(path: &Path, market: &'static str) -> Result<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        conn.busy_timeout(std::time::Duration::from_secs(10))?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        // queues from before `--market` only held spot jobs
+        let unkeyed: bool = tx.query_row(
+            "SELECT COUNT(*) = 1 FROM sqlite_master WHERE name = 'jobs'
+                 AND NOT EXISTS (SELECT 1 FROM pragma_table_info('jobs') WHERE name = 'market')",
+            [],
+            |row| row.get(0),
+        )?;
+        if unkeyed {
+            tx.execute_batch("ALTER TABLE jobs RENAME TO spot_jobs")?;
+        }
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                market TEXT NOT NULL DEFAULT 'spot',
+                symbol TEXT NOT NULL,
+                interval TEXT NOT NULL,
+                day TEXT NOT NULL,
+                state TEXT NOT NULL DEFAULT 'pending',
+                priority INTEGER NOT NULL DEFAULT 0,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                worker TEXT,
+                leased_until INTEGER NOT NULL DEFAULT 0,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY (market, symbol, interval, day)
+            )",
+        )?;
+        if unkeyed {
+            tx.execute_batch(
+                "INSERT INTO jobs (symbol, interval, day, state, priority, attempts, last_error,
+                         worker, leased_until, updated_at)
+                     SELECT symbol, interval, day, state, priority, attempts, last_error,
+                         worker, leased_until, updated_at
+                     FROM spot_jobs;
+                 DROP TABLE spot_jobs;",
+            )?;
+        }
+        tx.commit()?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            market,
+        })
+    }
+
+    /// Runs `f` on the connection off the runtime: with `synchronous=FULL`
+    /// every commit waits for the disk.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection, &'static str) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let (conn, market) = (self.conn.clone(), self.market);
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap(), market)).await?
+    }
+
+    pub(crate) async fn enqueue(&self, jobs: &[Job]) -> Result<()> {
+        let jobs = jobs.to_vec();
+        self.blocking(move |conn, market| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO jobs (market, symbol, interval, day, priority, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT (market, symbol, interval, day) DO UPDATE SET priority = excluded.priority",
+                )?;
+                for (priority, job) in jobs.iter().enumerate() {
+                    stmt.execute(params![
+                        market,
+                        job.symbol,
+                        job.interval,
+                        job.day,
+                        priority as i64,
+                        now()
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub(crate) async fn claim(
+        &self,
+        worker: &str,
+        since: i64,
+        until: NaiveDate,
+    ) -> Result<Option<Job>> {
+        let worker = worker.to_string();
+        self.blocking(move |conn, market| {
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let now = now();
+            let job = tx
+                .query_row(
+                    "SELECT symbol, interval, day FROM jobs
+                     WHERE market = ?5 AND state != 'done' AND leased_until < ?1 AND day <= ?2
+                         AND (COALESCE(worker, '') != ?3 OR updated_at < ?4)
+                     ORDER BY priority, day, symbol, interval
+                     LIMIT 1",
+                    params![now, until, worker, since, market],
+                    |row| {
+                        Ok(Job {
+                            symbol: row.get(0)?,
+                            interval: row.get(1)?,
+                            day: row.get(2)?,
+                        })
+                    },
+                )
+                .optional()?;
+            if let Some(job) = &job {
+                tx.execute(
+                    "UPDATE jobs SET state = 'running', worker = ?4, leased_until = ?5, updated_at = ?6
+                     WHERE market = ?7 AND symbol = ?1 AND interval = ?2 AND day = ?3",
+                    params![
+                        job.symbol,
+                        job.interval,
+                        job.day,
+                        worker,
+                        now + LEASE.as_millis() as i64,
+                        now,
+                        market
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(job)
+        })
+        .await
+    }
+
+    pub(crate) async fn set_state(
+        &self,
+        job: &Job,
+        state: &str,
+        error: Option<&str>,
+        leased_until: i64,
+    ) -> Result<()> {
+        let (job, state, error) = (job.clone(), state.to_string(), error.map(str::to_string));
+        self.blocking(move |conn, market| {
+            conn.execute(
+                "UPDATE jobs SET state = ?4, last_error = ?5, leased_until = ?6, updated_at = ?7,
+                     attempts = attempts + CASE WHEN ?4 = 'failed' THEN 1 ELSE 0 END
+                 WHERE market = ?8 AND symbol = ?1 AND interval = ?2 AND day = ?3",
+                params![
+                    job.symbol,
+                    job.interval,
+                    job.day,
+                    state,
+                    error,
+                    leased_until,
+                    now(),
+                    market
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_failures_count_as_attempts() {
+        let path = std::env::temp_dir().join(format!("kline-queue-{}.db", std::process::id()));
+        let queue = SqliteQueue::open(&path, "spot").unwrap();
+        let job = Job {
+            symbol: "ETHUSDC".to_string(),
+            interval: "1s".to_string(),
+            day: "2024-06-01".parse().unwrap(),
+        };
+        queue.enqueue(std::slice::from_ref(&job)).await.unwrap();
+        let attempts = || {
+            queue
+                .conn
+                .lock()
+                .unwrap()
+                .query_row("SELECT attempts FROM jobs", [], |row| row.get::<_, i64>(0))
+                .unwrap()
+        };
+        // released unfinished, then requeued once done
+        for state in ["pending", "done", "pending"] {
+            queue.set_state(&job, state, None, 0).await.unwrap();
+        }
+        assert_eq!(attempts(), 0);
+        queue
+            .set_state(&job, "failed", Some("boom"), 0)
+            .await
+            .unwrap();
+        assert_eq!(attempts(), 1);
+        drop(queue);
+        std::fs::remove_file(path).unwrap();
+    }
+}
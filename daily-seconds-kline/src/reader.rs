@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::format::{Format, Style};
+use crate::kline::Kline;
+use crate::manifest::{ArchiveMember, FileEntry, Manifest};
+
+/// Reads the files of a dataset, whether they are stored loose or bundled
+/// by `archive`.
+#[derive(Clone)]
+pub(crate) struct DatasetReader {
+    dir: PathBuf,
+    style: Style,
+}
+
+impl DatasetReader {
+    pub(crate) fn new(manifest: &Manifest) -> Self {
+        Self {
+            dir: manifest.dir().to_path_buf(),
+            style: manifest.style(),
+        }
+    }
+
+    /// Rows of the manifest entry `key`.
+    pub(crate) fn read(&self, key: &str, entry: &FileEntry) -> Result<Vec<Kline>> {
+        let format = Format::of(Path::new(key))
+            .ok_or_else(|| anyhow!("{} isn't a CSV or Parquet file", key))?;
+        match &entry.archive {
+            None => {
+                let path = self.dir.join(key);
+                format
+                    .read(&path, self.style)
+                    .with_context(|| format!("reading {:?}", path))
+            }
+            Some(member) => self
+                .member(key, member)
+                .and_then(|data| format.read_bytes(data, self.style))
+                .with_context(|| format!("reading {} from {}", key, member.file)),
+        }
+    }
+
+    /// Contents of an archived file; only its own frame is decompressed.
+    fn member(&self, key: &str, member: &ArchiveMember) -> Result<Vec<u8>> {
+        let mut file = File::open(self.dir.join(&member.file))?;
+        file.seek(SeekFrom::Start(member.offset))?;
+        let frame = zstd::stream::decode_all(file.take(member.len))?;
+        let mut archive = tar::Archive::new(&frame[..]);
+        let mut entry = archive
+            .entries()?
+            .next()
+            .ok_or_else(|| anyhow!("no tar entry at offset {}", member.offset))??;
+        if entry.path()? != Path::new(key) {
+            return Err(anyhow!(
+                "offset {} holds {:?}",
+                member.offset,
+                entry.path()?
+            ));
+        }
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
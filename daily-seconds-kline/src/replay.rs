@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+/// Raw REST responses kept on disk, so iterating on what happens to the rows
+/// doesn't hit the exchange again. Recording always fetches and overwrites;
+/// replaying serves cached responses and records only the missing ones.
+pub(crate) struct ResponseCache {
+    dir: PathBuf,
+    replay: bool,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(record: Option<PathBuf>, replay: Option<PathBuf>) -> Result<Option<Self>> {
+        let cache = match (record, replay) {
+            (_, Some(dir)) => Self { dir, replay: true },
+            (Some(dir), None) => Self { dir, replay: false },
+            (None, None) => return Ok(None),
+        };
+        std::fs::create_dir_all(&cache.dir)
+            .with_context(|| format!("creating response cache {:?}", cache.dir))?;
+        Ok(Some(cache))
+    }
+
+    pub(crate) async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        if !self.replay {
+            return Ok(None);
+        }
+        match tokio::fs::read(self.dir.join(key)).await {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub(crate) async fn put(&self, key: &str, body: &[u8]) -> Result<()> {
+        // write aside first so an interrupted run can't leave a truncated entry
+        let path = self.dir.join(key);
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, body).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+}
+
+/// Downloaded archives kept on disk by URL, with the ETag and
+/// Last-Modified they came with, so an unchanged archive is only asked
+/// about again, not downloaded. The least recently used entries go once
+/// the cache outgrows `max_bytes`.
+pub(crate) struct ArchiveCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+/// An archive as it was last downloaded.
+pub(crate) struct CachedArchive {
+    pub(crate) body: Vec<u8>,
+    pub(crate) meta: ArchiveMeta,
+}
+
+/// Validators to make the next request for an archive conditional on.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct ArchiveMeta {
+    pub(crate) url: String,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+}
+
+impl ArchiveCache {
+    pub(crate) fn new(dir: PathBuf, max_bytes: u64) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("creating archive cache {:?}", dir))?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    /// The cached copy of `url`, marked as just used.
+    pub(crate) async fn get(&self, url: &str) -> Result<Option<CachedArchive>> {
+        let (body_path, meta_path) = self.paths(url);
+        let meta = match tokio::fs::read(&meta_path).await {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let meta = match serde_json::from_slice::<ArchiveMeta>(&meta) {
+            // another URL hashing the same is as good as missing
+            Ok(meta) if meta.url == url => meta,
+            _ => return Ok(None),
+        };
+        let body = match tokio::fs::read(&body_path).await {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let file = tokio::fs::File::options()
+            .append(true)
+            .open(&body_path)
+            .await?;
+        file.into_std().await.set_modified(SystemTime::now())?;
+        Ok(Some(CachedArchive { body, meta }))
+    }
+
+    /// Keeps `body` for `meta.url`, then evicts down to the size limit.
+    pub(crate) async fn put(&self, meta: &ArchiveMeta, body: &[u8]) -> Result<()> {
+        let (body_path, meta_path) = self.paths(&meta.url);
+        // the body goes first, so an interrupted write leaves at worst a
+        // body whose old validators no longer match and get a full download
+        for (path, data) in [
+            (&body_path, body.to_vec()),
+            (&meta_path, serde_json::to_vec(meta)?),
+        ] {
+            let tmp = path.with_extension("tmp");
+            tokio::fs::write(&tmp, data).await?;
+            tokio::fs::rename(&tmp, path).await?;
+        }
+        self.evict().await
+    }
+
+    /// Removes the least recently used entries until the cache fits.
+    async fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        let mut dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+            total += metadata.len();
+            if path.extension().is_some_and(|e| e == "zip") {
+                entries.push((metadata.modified()?, path));
+            }
+        }
+        entries.sort();
+        for (_, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            tracing::debug!("evicting {:?} from the archive cache", path);
+            for path in [path.with_extension("json"), path] {
+                match tokio::fs::metadata(&path).await {
+                    Ok(metadata) => {
+                        tokio::fs::remove_file(&path).await?;
+                        total = total.saturating_sub(metadata.len());
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the body and metadata of `url` are kept, named by its hash.
+    fn paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let name = hex::encode(Sha256::digest(url.as_bytes()));
+        let base = self.dir.join(name);
+        (base.with_extension("zip"), base.with_extension("json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(url: &str) -> ArchiveMeta {
+        ArchiveMeta {
+            url: url.to_string(),
+            etag: Some(format!("\"{}\"", url.len())),
+            last_modified: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn archives_come_back_with_their_validators() {
+        let dir = std::env::temp_dir().join(format!("kline-archives-{}-a", std::process::id()));
+        let cache = ArchiveCache::new(dir.clone(), 1 << 20).unwrap();
+        let url = "https://data.binance.vision/a.zip";
+        assert!(cache.get(url).await.unwrap().is_none());
+        cache.put(&meta(url), b"zip").await.unwrap();
+        let cached = cache.get(url).await.unwrap().unwrap();
+        assert_eq!(cached.body, b"zip");
+        assert_eq!(cached.meta.etag, meta(url).etag);
+        assert!(cache
+            .get(&format!("{}.CHECKSUM", url))
+            .await
+            .unwrap()
+            .is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_archives_are_evicted() {
+        let dir = std::env::temp_dir().join(format!("kline-archives-{}-b", std::process::id()));
+        // room for two bodies and their metadata, not three
+        let cache = ArchiveCache::new(dir.clone(), 2 * (1000 + 100)).unwrap();
+        let body = vec![0; 1000];
+        cache.put(&meta("a"), &body).await.unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put(&meta("b"), &body).await.unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        // using `a` makes `b` the oldest
+        assert!(cache.get("a").await.unwrap().is_some());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put(&meta("c"), &body).await.unwrap();
+        assert!(cache.get("a").await.unwrap().is_some());
+        assert!(cache.get("b").await.unwrap().is_none());
+        assert!(cache.get("c").await.unwrap().is_some());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
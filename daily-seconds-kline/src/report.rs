@@ -0,0 +1,48 @@
+//! Optional Sentry reporting, enabled with the `sentry` feature and a
+//! `SENTRY_DSN` environment variable. Without either these are no-ops.
+
+use crate::queue::Job;
+
+#[cfg(feature = "sentry")]
+pub(crate) type Guard = Option<sentry::ClientInitGuard>;
+#[cfg(not(feature = "sentry"))]
+pub(crate) struct Guard;
+
+/// Installs the client and its panic handler. Keep the guard alive until
+/// exit so queued events get sent.
+pub(crate) fn init() -> Guard {
+    #[cfg(feature = "sentry")]
+    {
+        let dsn = std::env::var("SENTRY_DSN").ok()?;
+        let mut options = sentry::ClientOptions::new();
+        options.release = sentry::release_name!();
+        Some(sentry::init((dsn, options)))
+    }
+    #[cfg(not(feature = "sentry"))]
+    Guard
+}
+
+/// A day that failed and was left for a later attempt.
+pub(crate) fn job_failed(job: &Job, start_ms: i64, end_ms: i64, error: &anyhow::Error) {
+    #[cfg(feature = "sentry")]
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("symbol", &job.symbol);
+            scope.set_tag("interval", &job.interval);
+            scope.set_tag("day", job.day);
+            scope.set_extra("window_start_ms", start_ms.into());
+            scope.set_extra("window_end_ms", end_ms.into());
+        },
+        || sentry::integrations::anyhow::capture_anyhow(error),
+    );
+    #[cfg(not(feature = "sentry"))]
+    let _ = (job, start_ms, end_ms, error);
+}
+
+/// The error that ended the run.
+pub(crate) fn fatal(error: &anyhow::Error) {
+    #[cfg(feature = "sentry")]
+    sentry::integrations::anyhow::capture_anyhow(error);
+    #[cfg(not(feature = "sentry"))]
+    let _ = error;
+}
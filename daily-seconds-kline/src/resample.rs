@@ -0,0 +1,259 @@
+use anyhow::{anyhow, Result};
+
+use crate::day::{day_of, day_start_ms, next_day_ms, DayStart};
+use crate::decimal::Decimal;
+use crate::kline::Kline;
+
+/// Aggregates consecutive rows into klines of `step_ms`, counted from the
+/// start of the day each row falls in, as `day_start` cuts them, so none
+/// straddles two daily files even when days don't start at a multiple of
+/// `step_ms`; the last bucket of a day ends with it. Sums and extremes are
+/// computed on the exact decimals, never on floats. Buckets without rows
+/// are left out, like the exchange does.
+pub(crate) fn resample_in_days(
+    rows: &[Kline],
+    step_ms: i64,
+    day_start: DayStart,
+) -> Result<Vec<Kline>> {
+    aggregate(rows, |open_time| {
+        let day_ms = day_start.start_ms(day_start.day_of(open_time));
+        let start_ms = day_ms + (open_time - day_ms).div_euclid(step_ms) * step_ms;
+        (
+            start_ms,
+            (start_ms + step_ms).min(day_start.next_day_ms(day_ms)),
+        )
+    })
+}
+
+/// Aggregates rows into one kline per day, as `--day-start` cuts them.
+pub(crate) fn resample_days(rows: &[Kline]) -> Result<Vec<Kline>> {
+    aggregate(rows, |open_time| {
+        let start_ms = day_start_ms(day_of(open_time));
+        (start_ms, next_day_ms(start_ms))
+    })
+}
+
+/// Aggregates consecutive rows by the `[start_ms, end_ms)` bucket their
+/// open time falls in.
+fn aggregate(rows: &[Kline], bucket_of: impl Fn(i64) -> (i64, i64)) -> Result<Vec<Kline>> {
+    let mut out = Vec::new();
+    let mut bucket: Option<Bucket> = None;
+    for row in rows {
+        let (start_ms, end_ms) = bucket_of(row.open_time);
+        match &mut bucket {
+            Some(b) if b.open_time == start_ms => b.add(row)?,
+            Some(b) if b.open_time > start_ms => {
+                return Err(anyhow!("rows out of order at {}", row.open_time));
+            }
+            _ => {
+                if let Some(b) = bucket.take() {
+                    out.push(b.finish());
+                }
+                bucket = Some(Bucket::new(start_ms, end_ms, row)?);
+            }
+        }
+    }
+    if let Some(b) = bucket {
+        out.push(b.finish());
+    }
+    Ok(out)
+}
+
+struct Bucket {
+    open_time: i64,
+    end_ms: i64,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+    quote_volume: Decimal,
+    num_of_trades: u64,
+    taker_buy_base_vol: Decimal,
+    taker_buy_quote_vol: Decimal,
+}
+
+impl Bucket {
+    fn new(open_time: i64, end_ms: i64, row: &Kline) -> Result<Self> {
+        Ok(Self {
+            open_time,
+            end_ms,
+            open: row.open_price().parse()?,
+            high: row.high().parse()?,
+            low: row.low().parse()?,
+            close: row.close().parse()?,
+            volume: row.volume().parse()?,
+            quote_volume: row.quote_volume().parse()?,
+            num_of_trades: row.num_of_trades,
+            taker_buy_base_vol: row.taker_buy_base_vol().parse()?,
+            taker_buy_quote_vol: row.taker_buy_quote_vol().parse()?,
+        })
+    }
+
+    fn add(&mut self, row: &Kline) -> Result<()> {
+        let high = row.high().parse()?;
+        if high > self.high {
+            self.high = high;
+        }
+        let low = row.low().parse()?;
+        if low < self.low {
+            self.low = low;
+        }
+        self.close = row.close().parse()?;
+        self.volume = self.volume + row.volume().parse()?;
+        self.quote_volume = self.quote_volume + row.quote_volume().parse()?;
+        self.num_of_trades += row.num_of_trades;
+        self.taker_buy_base_vol = self.taker_buy_base_vol + row.taker_buy_base_vol().parse()?;
+        self.taker_buy_quote_vol = self.taker_buy_quote_vol + row.taker_buy_quote_vol().parse()?;
+        Ok(())
+    }
+
+    fn finish(self) -> Kline {
+        Kline::new(
+            self.open_time,
+            self.end_ms - 1,
+            self.num_of_trades,
+            [
+                &self.open.to_string(),
+                &self.high.to_string(),
+                &self.low.to_string(),
+                &self.close.to_string(),
+                &self.volume.to_string(),
+                &self.quote_volume.to_string(),
+                &self.taker_buy_base_vol.to_string(),
+                &self.taker_buy_quote_vol.to_string(),
+                "0",
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: i64 = 1000;
+    const MINUTE: i64 = 60 * SECOND;
+
+    /// A 1s kline opening `second`s after the epoch.
+    fn second(second: i64, [open, high, low, close]: [&str; 4], volume: &str) -> Kline {
+        let open_time = second * SECOND;
+        Kline::new(
+            open_time,
+            open_time + SECOND - 1,
+            1,
+            [open, high, low, close, volume, volume, volume, volume, "0"],
+        )
+    }
+
+    fn text(k: &Kline) -> [&str; 5] {
+        [k.open_price(), k.high(), k.low(), k.close(), k.volume()]
+    }
+
+    #[test]
+    fn buckets_ohlcv_on_exact_decimals() {
+        let rows = [
+            second(0, ["10.00", "10.50", "9.90", "10.10"], "0.1"),
+            second(1, ["10.10", "11.00", "10.00", "10.20"], "0.2"),
+            second(59, ["10.20", "10.30", "9.80", "10.00"], "0.30"),
+            second(60, ["10.00", "10.00", "10.00", "10.00"], "1"),
+        ];
+        let out = resample_in_days(&rows, MINUTE, DayStart::default()).unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].open_time, 0);
+        assert_eq!(out[0].close_time, MINUTE - 1);
+        // 0.1 + 0.2 stays 0.3, as floats wouldn't have it
+        assert_eq!(text(&out[0]), ["10.00", "11.00", "9.80", "10.00", "0.60"]);
+        assert_eq!(out[0].num_of_trades, 3);
+        assert_eq!(out[1].open_time, MINUTE);
+        assert_eq!(text(&out[1]), ["10.00", "10.00", "10.00", "10.00", "1"]);
+    }
+
+    #[test]
+    fn partial_bucket_keeps_its_full_span() {
+        let rows: Vec<Kline> = (120..150)
+            .map(|s| second(s, ["1", "2", "0.5", "1.5"], "0.01"))
+            .collect();
+        let out = resample_in_days(&rows, MINUTE, DayStart::default()).unwrap();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].open_time, 2 * MINUTE);
+        assert_eq!(out[0].close_time, 3 * MINUTE - 1);
+        assert_eq!(out[0].volume(), "0.30");
+        assert_eq!(out[0].num_of_trades, 30);
+    }
+
+    #[test]
+    fn missing_seconds_and_buckets_are_left_out() {
+        let rows = [
+            second(3, ["1", "1", "1", "1"], "1"),
+            second(41, ["2", "2", "2", "2"], "1"),
+            // nothing traded for the two minutes after
+            second(185, ["3", "3", "3", "3"], "1"),
+        ];
+        let out = resample_in_days(&rows, MINUTE, DayStart::default()).unwrap();
+        let opens: Vec<i64> = out.iter().map(|k| k.open_time).collect();
+        assert_eq!(opens, [0, 3 * MINUTE]);
+        assert_eq!(text(&out[0]), ["1", "2", "1", "2", "2"]);
+    }
+
+    #[test]
+    fn rows_out_of_order_are_an_error() {
+        let rows = [
+            second(61, ["1", "1", "1", "1"], "1"),
+            second(1, ["1", "1", "1", "1"], "1"),
+        ];
+        assert!(resample_in_days(&rows, MINUTE, DayStart::default()).is_err());
+    }
+
+    #[test]
+    fn in_days_clips_the_last_bucket_at_midnight() {
+        const HOUR: i64 = 60 * MINUTE;
+        let day_ms = 86_400 * SECOND;
+        // 7h buckets start at 00, 07, 14 and 21h; the last only lasts 3h
+        let rows = [
+            second(21 * 3600, ["1", "1", "1", "1"], "1"),
+            second(day_ms / SECOND - 1, ["2", "2", "2", "2"], "1"),
+            second(day_ms / SECOND, ["3", "3", "3", "3"], "1"),
+        ];
+        let out = resample_in_days(&rows, 7 * HOUR, DayStart::default()).unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].open_time, 21 * HOUR);
+        assert_eq!(out[0].close_time, day_ms - 1);
+        assert_eq!(out[0].volume(), "2");
+        assert_eq!(out[1].open_time, day_ms);
+        assert_eq!(out[1].close_time, day_ms + 7 * HOUR - 1);
+    }
+
+    #[test]
+    fn in_days_counts_buckets_from_an_offset_day_start() {
+        const HOUR: i64 = 60 * MINUTE;
+        // days start at 18:30 UTC the evening before
+        let day_start: DayStart = "00:00@+05:30".parse().unwrap();
+        let day_ms = 18 * HOUR + 30 * MINUTE;
+        let rows = [
+            second(day_ms / SECOND - 1, ["1", "1", "1", "1"], "1"),
+            second(day_ms / SECOND, ["2", "2", "2", "2"], "1"),
+            second((day_ms + HOUR) / SECOND - 1, ["3", "3", "3", "3"], "1"),
+        ];
+        let out = resample_in_days(&rows, HOUR, day_start).unwrap();
+        let spans: Vec<(i64, i64)> = out.iter().map(|k| (k.open_time, k.close_time)).collect();
+        assert_eq!(
+            spans,
+            [(day_ms - HOUR, day_ms - 1), (day_ms, day_ms + HOUR - 1)]
+        );
+        assert_eq!(out[1].volume(), "2");
+    }
+
+    #[test]
+    fn days_aggregate_whole_days() {
+        let day_ms = 86_400 * SECOND;
+        let rows = [
+            second(0, ["1", "5", "1", "4"], "1.5"),
+            second(day_ms / SECOND - 1, ["4", "4", "0.5", "3"], "2.5"),
+        ];
+        let out = resample_days(&rows).unwrap();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].close_time, day_ms - 1);
+        assert_eq!(text(&out[0]), ["1", "5", "0.5", "3", "4.0"]);
+    }
+}
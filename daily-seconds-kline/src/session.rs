@@ -0,0 +1,130 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Days, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
+use chrono_tz::Tz;
+
+use crate::day::day_of;
+
+/// Hours of US equities trading, 13:30-20:00 UTC in summer.
+const US_EQUITIES: &str = "09:30-16:00@America/New_York";
+
+/// Wall-clock windows rows are kept in, in UTC unless a zone is given, e.g.
+/// `13:30-20:00`, `00:00-08:00,16:00-24:00` or `09:30-16:00@America/New_York`;
+/// `us-equities` is short for the last one. A window whose end comes before
+/// its start runs past midnight.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Session {
+    windows: Vec<(NaiveTime, Option<NaiveTime>)>,
+    zone: Tz,
+}
+
+impl FromStr for Session {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = if s == "us-equities" { US_EQUITIES } else { s };
+        let (windows, zone) = s.split_once('@').unwrap_or((s, "UTC"));
+        let windows = windows
+            .split(',')
+            .map(|w| {
+                let (start, end) = w
+                    .split_once('-')
+                    .ok_or_else(|| anyhow!("invalid window {:?}, expected HH:MM-HH:MM", w))?;
+                // `24:00` ends a window at midnight
+                let end = (end != "24:00").then(|| parse_time(end)).transpose()?;
+                Ok((parse_time(start)?, end))
+            })
+            .collect::<Result<_>>()?;
+        let zone = zone
+            .parse()
+            .map_err(|_| anyhow!("unknown time zone {:?}", zone))?;
+        Ok(Self { windows, zone })
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .map_err(|_| anyhow!("invalid time {:?}, expected HH:MM", s))
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (start, end)) in self.windows.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}-", start.format("%H:%M"))?;
+            match end {
+                Some(end) => write!(f, "{}", end.format("%H:%M"))?,
+                None => f.write_str("24:00")?,
+            }
+        }
+        if self.zone != Tz::UTC {
+            write!(f, "@{}", self.zone)?;
+        }
+        Ok(())
+    }
+}
+
+impl Session {
+    /// Whether the candle opening at `ms` is in a window.
+    pub(crate) fn contains(&self, ms: i64) -> bool {
+        let time = DateTime::from_timestamp_millis(ms)
+            .unwrap()
+            .with_timezone(&self.zone)
+            .time();
+        self.windows.iter().any(|&(start, end)| match end {
+            None => time >= start,
+            Some(end) if start < end => start <= time && time < end,
+            Some(end) => time >= start || time < end,
+        })
+    }
+
+    /// The parts of `[from_ms, to_ms)` inside a window, in order.
+    pub(crate) fn ranges(&self, from_ms: i64, to_ms: i64) -> Vec<(i64, i64)> {
+        let mut ranges = Vec::new();
+        // local dates around the range, a window may start the day before
+        let first = day_of(from_ms) - Days::new(2);
+        let last = day_of(to_ms) + Days::new(1);
+        for date in first.iter_days().take_while(|d| *d <= last) {
+            for &(start, end) in &self.windows {
+                let end_date = match end {
+                    Some(end) if start < end => date,
+                    _ => date + Days::new(1),
+                };
+                let start_ms = self.local_ms(date.and_time(start));
+                let end_ms = self.local_ms(end_date.and_time(end.unwrap_or(NaiveTime::MIN)));
+                let (start_ms, end_ms) = (start_ms.max(from_ms), end_ms.min(to_ms));
+                if start_ms < end_ms {
+                    ranges.push((start_ms, end_ms));
+                }
+            }
+        }
+        ranges.sort_unstable();
+        // overlapping windows count once
+        let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+        for (start_ms, end_ms) in ranges {
+            match merged.last_mut() {
+                Some(last) if start_ms <= last.1 => last.1 = last.1.max(end_ms),
+                _ => merged.push((start_ms, end_ms)),
+            }
+        }
+        merged
+    }
+
+    fn local_ms(&self, local: NaiveDateTime) -> i64 {
+        // a time inside a DST gap counts from when the clocks resume
+        self.zone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.zone
+                    .from_local_datetime(&(local + TimeDelta::hours(1)))
+                    .earliest()
+            })
+            .expect("DST gaps are at most an hour")
+            .timestamp_millis()
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+
+use crate::exit::Failure;
+use crate::kline::Kline;
+
+const DEFAULT_PORT: u16 = 8123;
+
+/// Talks to ClickHouse over its HTTP interface. `ReplacingMergeTree`
+/// deduplicates rows loaded twice when parts are merged.
+pub(crate) struct ClickHouseSink {
+    client: reqwest::Client,
+    url: Url,
+    user: String,
+    password: String,
+}
+
+impl ClickHouseSink {
+    /// `clickhouse://[user[:password]@]host[:port][/database]`
+    pub(crate) async fn connect(url: &str) -> Result<Self> {
+        let parsed =
+            Url::parse(url).map_err(|e| anyhow!("{}: {}", url, e).context(Failure::Config))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| anyhow!("{}: no host", url).context(Failure::Config))?;
+        let mut http = Url::parse(&format!(
+            "http://{}:{}/",
+            host,
+            parsed.port().unwrap_or(DEFAULT_PORT)
+        ))?;
+        let database = parsed.path().trim_start_matches('/');
+        if !database.is_empty() {
+            http.query_pairs_mut().append_pair("database", database);
+        }
+        let sink = Self {
+            client: crate::client::build()?,
+            url: http,
+            user: match parsed.username() {
+                "" => "default".to_string(),
+                user => user.to_string(),
+            },
+            password: parsed.password().unwrap_or_default().to_string(),
+        };
+        sink.query(
+            "CREATE TABLE IF NOT EXISTS klines (
+                symbol LowCardinality(String),
+                interval LowCardinality(String),
+                open_time Int64,
+                open Decimal(38, 8),
+                high Decimal(38, 8),
+                low Decimal(38, 8),
+                close Decimal(38, 8),
+                volume Decimal(38, 8),
+                close_time Int64,
+                quote_volume Decimal(38, 8),
+                num_of_trades UInt64,
+                taker_buy_base_vol Decimal(38, 8),
+                taker_buy_quote_vol Decimal(38, 8)
+            ) ENGINE = ReplacingMergeTree ORDER BY (symbol, interval, open_time)",
+            Vec::new(),
+        )
+        .await?;
+        Ok(sink)
+    }
+
+    pub(crate) async fn write(&self, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Vec::new());
+        for row in rows {
+            wtr.write_record([
+                symbol,
+                interval,
+                &row.open_time.to_string(),
+                row.open_price(),
+                row.high(),
+                row.low(),
+                row.close(),
+                row.volume(),
+                &row.close_time.to_string(),
+                row.quote_volume(),
+                &row.num_of_trades.to_string(),
+                row.taker_buy_base_vol(),
+                row.taker_buy_quote_vol(),
+            ])?;
+        }
+        let body = wtr.into_inner().map_err(|e| e.into_error())?;
+        self.query("INSERT INTO klines FORMAT CSV", body).await
+    }
+
+    /// Runs a query, retried; a retried insert is deduplicated like one
+    /// loaded twice.
+    async fn query(&self, query: &str, body: Vec<u8>) -> Result<()> {
+        super::retrying("clickhouse query", || self.try_query(query, body.clone())).await
+    }
+
+    async fn try_query(&self, query: &str, body: Vec<u8>) -> Result<()> {
+        let mut url = self.url.clone();
+        url.query_pairs_mut().append_pair("query", query);
+        let resp = self
+            .client
+            .post(url)
+            .header("X-ClickHouse-User", &self.user)
+            .header("X-ClickHouse-Key", &self.password)
+            .body(body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            return Err(anyhow!(
+                "clickhouse: {}: {}",
+                status,
+                resp.text().await?.trim()
+            ));
+        }
+        Ok(())
+    }
+}
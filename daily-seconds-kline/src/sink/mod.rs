@@ -0,0 +1,82 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::exit::Failure;
+use crate::kline::Kline;
+use crate::manifest::Manifest;
+
+mod clickhouse;
+mod postgres;
+mod s3;
+mod sheets;
+mod sqlite;
+
+/// Attempts at each write to a sink.
+const ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled for each further one.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A database holding klines in one `klines` table keyed by symbol,
+/// interval and open time, or an object store holding daily files. Writes
+/// are upserts or overwrites, so loading the same rows again is harmless.
+/// A Google Sheet is the exception: it only appends.
+pub(crate) enum Sink {
+    Postgres(postgres::PostgresSink),
+    Sqlite(sqlite::SqliteSink),
+    ClickHouse(clickhouse::ClickHouseSink),
+    S3(s3::S3Sink),
+    Sheets(sheets::SheetsSink),
+}
+
+impl Sink {
+    /// `postgres://`, `clickhouse://`, `sqlite://path`, `s3://` or
+    /// `sheets://` URLs. Files in S3 are laid out and formatted like
+    /// `manifest`'s dataset.
+    pub(crate) async fn open(url: &str, manifest: &Manifest) -> Result<Self> {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            Ok(Sink::Postgres(postgres::PostgresSink::connect(url).await?))
+        } else if url.starts_with("clickhouse://") {
+            Ok(Sink::ClickHouse(
+                clickhouse::ClickHouseSink::connect(url).await?,
+            ))
+        } else if url.starts_with("s3://") {
+            Ok(Sink::S3(s3::S3Sink::open(url, manifest)?))
+        } else if url.starts_with("sheets://") {
+            Ok(Sink::Sheets(sheets::SheetsSink::open(url)?))
+        } else if let Some(path) = url.strip_prefix("sqlite://") {
+            Ok(Sink::Sqlite(sqlite::SqliteSink::open(path.as_ref())?))
+        } else {
+            Err(anyhow!("unsupported sink {:?}", url).context(Failure::Config))
+        }
+    }
+
+    pub(crate) async fn write(&self, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+        match self {
+            Sink::Postgres(s) => s.write(symbol, interval, rows).await,
+            Sink::Sqlite(s) => s.write(symbol, interval, rows).await,
+            Sink::ClickHouse(s) => s.write(symbol, interval, rows).await,
+            Sink::S3(s) => s.write(symbol, interval, rows).await,
+            Sink::Sheets(s) => s.write(symbol, interval, rows).await,
+        }
+    }
+}
+
+/// Runs `attempt` until it succeeds or `ATTEMPTS` have failed, waiting
+/// longer after each failure.
+async fn retrying<F: Future<Output = Result<()>>>(
+    what: &str,
+    mut attempt: impl FnMut() -> F,
+) -> Result<()> {
+    let mut delay = RETRY_DELAY;
+    for _ in 1..ATTEMPTS {
+        match attempt().await {
+            Ok(()) => return Ok(()),
+            Err(e) => tracing::warn!("retrying {} in {:?}: {:#}", what, delay, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+    attempt().await
+}
@@ -0,0 +1,135 @@
+use anyhow::Result;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, NoTls};
+
+use crate::kline::Kline;
+
+/// Rows per `INSERT`, well below the 65535 parameter limit.
+const CHUNK_ROWS: usize = 1000;
+const COLUMNS: usize = 13;
+
+pub(crate) struct PostgresSink {
+    client: Client,
+}
+
+impl PostgresSink {
+    pub(crate) async fn connect(url: &str) -> Result<Self> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::error!("sink connection closed: {}", e);
+            }
+        });
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS klines (
+                    symbol TEXT NOT NULL,
+                    interval TEXT NOT NULL,
+                    open_time BIGINT NOT NULL,
+                    open NUMERIC NOT NULL,
+                    high NUMERIC NOT NULL,
+                    low NUMERIC NOT NULL,
+                    close NUMERIC NOT NULL,
+                    volume NUMERIC NOT NULL,
+                    close_time BIGINT NOT NULL,
+                    quote_volume NUMERIC NOT NULL,
+                    num_of_trades BIGINT NOT NULL,
+                    taker_buy_base_vol NUMERIC NOT NULL,
+                    taker_buy_quote_vol NUMERIC NOT NULL,
+                    PRIMARY KEY (symbol, interval, open_time)
+                )",
+            )
+            .await?;
+        Ok(Self { client })
+    }
+
+    /// Upserts a chunk at a time, retrying each on its own.
+    pub(crate) async fn write(&self, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+        let what = format!("insert of {} {} rows", symbol, interval);
+        for chunk in rows.chunks(CHUNK_ROWS) {
+            super::retrying(&what, || self.insert(symbol, interval, chunk)).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert(&self, symbol: &str, interval: &str, chunk: &[Kline]) -> Result<()> {
+        let trades: Vec<i64> = chunk.iter().map(|r| r.num_of_trades as i64).collect();
+        let text: Vec<[&str; 8]> = chunk
+            .iter()
+            .map(|r| {
+                [
+                    r.open_price(),
+                    r.high(),
+                    r.low(),
+                    r.close(),
+                    r.volume(),
+                    r.quote_volume(),
+                    r.taker_buy_base_vol(),
+                    r.taker_buy_quote_vol(),
+                ]
+            })
+            .collect();
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(chunk.len() * COLUMNS);
+        for ((row, trades), t) in chunk.iter().zip(&trades).zip(&text) {
+            params.extend([
+                &symbol as &(dyn ToSql + Sync),
+                &interval,
+                &row.open_time,
+                &t[0],
+                &t[1],
+                &t[2],
+                &t[3],
+                &t[4],
+                &row.close_time,
+                &t[5],
+                trades,
+                &t[6],
+                &t[7],
+            ]);
+        }
+        self.client
+            .execute(&insert_sql(chunk.len()), &params)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A multi-row upsert; decimals are sent as text and cast so they stay exact.
+fn insert_sql(rows: usize) -> String {
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let p = |col: usize| row * COLUMNS + col + 1;
+            format!(
+                "(${}, ${}, ${}, ${}::text::numeric, ${}::text::numeric, ${}::text::numeric, \
+                 ${}::text::numeric, ${}::text::numeric, ${}, ${}::text::numeric, ${}, \
+                 ${}::text::numeric, ${}::text::numeric)",
+                p(0),
+                p(1),
+                p(2),
+                p(3),
+                p(4),
+                p(5),
+                p(6),
+                p(7),
+                p(8),
+                p(9),
+                p(10),
+                p(11),
+                p(12)
+            )
+        })
+        .collect();
+    format!(
+        "INSERT INTO klines (symbol, interval, open_time, open, high, low, close, volume,
+            close_time, quote_volume, num_of_trades, taker_buy_base_vol, taker_buy_quote_vol)
+         VALUES {}
+         ON CONFLICT (symbol, interval, open_time) DO UPDATE SET
+            open = excluded.open, high = excluded.high, low = excluded.low,
+            close = excluded.close, volume = excluded.volume,
+            close_time = excluded.close_time, quote_volume = excluded.quote_volume,
+            num_of_trades = excluded.num_of_trades,
+            taker_buy_base_vol = excluded.taker_buy_base_vol,
+            taker_buy_quote_vol = excluded.taker_buy_quote_vol",
+        values.join(", ")
+    )
+}
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+use crate::day::day_of;
+use crate::exit::Failure;
+use crate::format::{Format, Style};
+use crate::kline::Kline;
+use crate::layout::Layout;
+use crate::manifest::Manifest;
+use crate::market::market;
+use crate::queue::Job;
+use crate::writer;
+
+/// Uploads each day as the file `download` would have written, under the
+/// same path relative to `--out-dir` below the prefix. Requests are signed with AWS
+/// Signature Version 4 from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+/// and, for temporary credentials, `AWS_SESSION_TOKEN`; `AWS_REGION`
+/// defaults to `us-east-1`. Other S3-compatible stores are reached with
+/// path-style requests to `AWS_ENDPOINT_URL`. A retried upload overwrites
+/// the same object.
+pub(crate) struct S3Sink {
+    client: reqwest::Client,
+    /// Bucket URL an object's key is appended to
+    base: Url,
+    prefix: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    layout: Layout,
+    format: Format,
+    style: Style,
+}
+
+impl S3Sink {
+    /// `s3://bucket[/prefix]`, laid out and formatted like `manifest`'s
+    /// dataset.
+    pub(crate) fn open(url: &str, manifest: &Manifest) -> Result<Self> {
+        let path = url.strip_prefix("s3://").unwrap_or(url);
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("{}: no bucket", url).context(Failure::Config));
+        }
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let region = env("AWS_REGION").unwrap_or_else(|| "us-east-1".to_string());
+        let base = match env("AWS_ENDPOINT_URL") {
+            Some(endpoint) => format!("{}/{}/", endpoint.trim_end_matches('/'), bucket),
+            None => format!("https://{}.s3.{}.amazonaws.com/", bucket, region),
+        };
+        let credential = |name: &str| {
+            env(name)
+                .ok_or_else(|| anyhow!("{} isn't set", name))
+                .context(Failure::Config)
+        };
+        Ok(Self {
+            client: crate::client::build()?,
+            base: Url::parse(&base)
+                .with_context(|| format!("invalid endpoint {:?}", base))
+                .context(Failure::Config)?,
+            prefix: prefix.trim_matches('/').to_string(),
+            region,
+            access_key: credential("AWS_ACCESS_KEY_ID")?,
+            secret_key: credential("AWS_SECRET_ACCESS_KEY")?,
+            session_token: env("AWS_SESSION_TOKEN"),
+            layout: manifest.layout(),
+            format: manifest.format(),
+            style: manifest.style(),
+        })
+    }
+
+    /// Uploads a file per day the rows fall on.
+    pub(crate) async fn write(&self, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+        for day_rows in rows.chunk_by(|a, b| day_of(a.open_time) == day_of(b.open_time)) {
+            let job = Job {
+                symbol: symbol.to_string(),
+                interval: interval.to_string(),
+                day: day_of(day_rows[0].open_time),
+            };
+            let name = writer::file_name(&job, self.format);
+            let body = self.format.encode(day_rows, self.style)?;
+            let dir = self.layout.dir(symbol, interval, job.day);
+            let key: Vec<String> = [self.prefix.as_str(), market().dir().unwrap_or_default()]
+                .into_iter()
+                .filter(|p| !p.is_empty())
+                .map(str::to_string)
+                .chain(dir.iter().map(|c| c.to_string_lossy().into_owned()))
+                .chain([name])
+                .collect();
+            self.put(&key.join("/"), body).await?;
+        }
+        Ok(())
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        let mut url = self.base.clone();
+        let path: Vec<String> = key.split('/').map(uri_encode).collect();
+        url.set_path(&format!("{}{}", url.path(), path.join("/")));
+        super::retrying(&format!("upload of {}", key), || self.try_put(&url, &body)).await?;
+        tracing::info!("uploaded {} bytes to {}", body.len(), url);
+        Ok(())
+    }
+
+    async fn try_put(&self, url: &Url, body: &[u8]) -> Result<()> {
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let payload_hash = hex::encode(Sha256::digest(body));
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        let signed = signed.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "PUT\n{}\n\n{}\n{}\n{}",
+            url.path(),
+            canonical_headers,
+            signed,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date)?;
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, part)?;
+        }
+        let signature = hex::encode(hmac(&key, &string_to_sign)?);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed, signature
+        );
+
+        let mut request = self
+            .client
+            .put(url.clone())
+            .header("authorization", authorization)
+            .body(body.to_vec());
+        // reqwest sets the host header itself
+        for (name, value) in headers.into_iter().skip(1) {
+            request = request.header(name, value);
+        }
+        let resp = request.send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            return Err(anyhow!("s3: {}: {}", status, resp.text().await?.trim()));
+        }
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Result<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| anyhow!("invalid key: {}", e))?;
+    mac.update(data.as_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// A key segment percent-encoded as SigV4 expects, everything but
+/// unreserved characters.
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::exit::Failure;
+use crate::kline::{timestamp, Kline};
+use crate::resample::resample_days;
+
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+const SHEETS_URL: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+const DEFAULT_SHEET: &str = "Sheet1";
+/// Access tokens last an hour; fetch a new one a little before that.
+const TOKEN_TTL: Duration = Duration::from_secs(3300);
+
+/// The parts of a service-account key file the token exchange needs.
+#[derive(Deserialize)]
+struct ServiceAccount {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+}
+
+/// Appends rows to a Google Sheet through the Sheets API, signed in as a
+/// service account the sheet is shared with. A sheet has no keys, so
+/// loading the same files again appends them again.
+pub(crate) struct SheetsSink {
+    client: reqwest::Client,
+    spreadsheet: String,
+    sheet: String,
+    every_row: bool,
+    account: ServiceAccount,
+    key: EncodingKey,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl SheetsSink {
+    /// `sheets://SPREADSHEET_ID[/SHEET]?credentials=key.json[&rows=all]`;
+    /// one candle per symbol and day unless `rows=all`.
+    pub(crate) fn open(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix("sheets://").unwrap_or(url);
+        let (target, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (spreadsheet, sheet) = target.split_once('/').unwrap_or((target, DEFAULT_SHEET));
+        if spreadsheet.is_empty() {
+            return Err(anyhow!("{}: no spreadsheet id", url).context(Failure::Config));
+        }
+        let mut credentials = None;
+        let mut every_row = false;
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            match pair.split_once('=') {
+                Some(("credentials", path)) => credentials = Some(PathBuf::from(path)),
+                Some(("rows", "daily")) => every_row = false,
+                Some(("rows", "all")) => every_row = true,
+                _ => {
+                    return Err(
+                        anyhow!("{}: unknown parameter {:?}", url, pair).context(Failure::Config)
+                    )
+                }
+            }
+        }
+        let credentials = credentials
+            .ok_or_else(|| anyhow!("{}: no credentials=PATH", url).context(Failure::Config))?;
+        let data = std::fs::read(&credentials)
+            .with_context(|| format!("reading {:?}", credentials))
+            .context(Failure::Config)?;
+        let account: ServiceAccount = serde_json::from_slice(&data)
+            .with_context(|| format!("parsing {:?}", credentials))
+            .context(Failure::Config)?;
+        let key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())
+            .with_context(|| format!("private key in {:?}", credentials))
+            .context(Failure::Config)?;
+        Ok(Self {
+            client: crate::client::build()?,
+            spreadsheet: spreadsheet.to_string(),
+            sheet: sheet.to_string(),
+            every_row,
+            account,
+            key,
+            token: Mutex::new(None),
+        })
+    }
+
+    pub(crate) async fn write(&self, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+        let daily;
+        let (interval, rows) = if self.every_row {
+            (interval, rows)
+        } else {
+            daily = resample_days(rows)?;
+            ("1d", daily.as_slice())
+        };
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let values: Vec<Value> = rows
+            .iter()
+            .map(|row| {
+                json!([
+                    symbol,
+                    interval,
+                    timestamp(row.open_time),
+                    row.open_price(),
+                    row.high(),
+                    row.low(),
+                    row.close(),
+                    row.volume(),
+                    row.quote_volume(),
+                    row.num_of_trades,
+                    row.taker_buy_base_vol(),
+                    row.taker_buy_quote_vol(),
+                ])
+            })
+            .collect();
+        let url = format!(
+            "{}/{}/values/{}:append",
+            SHEETS_URL, self.spreadsheet, self.sheet
+        );
+        let resp = self
+            .client
+            .post(url)
+            .bearer_auth(self.access_token().await?)
+            .query(&[
+                ("valueInputOption", "USER_ENTERED"),
+                ("insertDataOption", "INSERT_ROWS"),
+            ])
+            .json(&json!({ "values": values }))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            return Err(anyhow!("sheets: {}: {}", status, resp.text().await?.trim()));
+        }
+        Ok(())
+    }
+
+    /// A cached access token, exchanged for a freshly signed JWT once it is
+    /// about to expire.
+    async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some((access, fetched)) = &*token {
+            if fetched.elapsed() < TOKEN_TTL {
+                return Ok(access.clone());
+            }
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let claims = Claims {
+            iss: &self.account.client_email,
+            scope: SCOPE,
+            aud: &self.account.token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.key)?;
+        let resp = self
+            .client
+            .post(&self.account.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            return Err(
+                anyhow!("sheets token: {}: {}", status, resp.text().await?.trim())
+                    .context(Failure::Config),
+            );
+        }
+        let access = resp.json::<Token>().await?.access_token;
+        *token = Some((access.clone(), Instant::now()));
+        Ok(access)
+    }
+}
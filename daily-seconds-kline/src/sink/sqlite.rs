@@ -0,0 +1,87 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+use crate::kline::Kline;
+
+pub(crate) struct SqliteSink {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteSink {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS klines (
+                symbol TEXT NOT NULL,
+                interval TEXT NOT NULL,
+                open_time INTEGER NOT NULL,
+                open TEXT NOT NULL,
+                high TEXT NOT NULL,
+                low TEXT NOT NULL,
+                close TEXT NOT NULL,
+                volume TEXT NOT NULL,
+                close_time INTEGER NOT NULL,
+                quote_volume TEXT NOT NULL,
+                num_of_trades INTEGER NOT NULL,
+                taker_buy_base_vol TEXT NOT NULL,
+                taker_buy_quote_vol TEXT NOT NULL,
+                PRIMARY KEY (symbol, interval, open_time)
+            )",
+        )?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Decimals are kept as text, SQLite has no exact decimal type. Runs on
+    /// the blocking pool, as a busy database can keep it waiting.
+    pub(crate) async fn write(&self, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+        let rows: Arc<[Kline]> = rows.into();
+        let what = format!("insert of {} {} rows", symbol, interval);
+        super::retrying(&what, || {
+            let conn = self.conn.clone();
+            let (symbol, interval, rows) = (symbol.to_string(), interval.to_string(), rows.clone());
+            async move {
+                tokio::task::spawn_blocking(move || insert(&conn, &symbol, &interval, &rows))
+                    .await?
+            }
+        })
+        .await
+    }
+}
+
+fn insert(conn: &Mutex<Connection>, symbol: &str, interval: &str, rows: &[Kline]) -> Result<()> {
+    let mut conn = conn.lock().unwrap();
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO klines (symbol, interval, open_time, open, high, low,
+                close, volume, close_time, quote_volume, num_of_trades,
+                taker_buy_base_vol, taker_buy_quote_vol)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        )?;
+        for row in rows {
+            stmt.execute(params![
+                symbol,
+                interval,
+                row.open_time,
+                row.open_price(),
+                row.high(),
+                row.low(),
+                row.close(),
+                row.volume(),
+                row.close_time,
+                row.quote_volume(),
+                row.num_of_trades as i64,
+                row.taker_buy_base_vol(),
+                row.taker_buy_quote_vol(),
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::day::{day_start_ms, next_day_ms};
+use crate::download::OUT_DIR;
+use crate::exit::Failure;
+use crate::format::Format;
+use crate::kline::Kline;
+use crate::manifest::{FileEntry, Manifest};
+use crate::writer::{sync_dir, Durability};
+
+const HOUR_MS: i64 = 3_600_000;
+
+#[derive(clap::Args)]
+pub(crate) struct SplitArgs {
+    /// Dataset to split, with its `manifest.json`
+    #[arg(long, default_value = OUT_DIR)]
+    dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = SplitBy::Hour)]
+    by: SplitBy,
+    /// Only split daily files larger than this many MiB
+    #[arg(long)]
+    larger_than_mb: Option<u64>,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum SplitBy {
+    Hour,
+}
+
+pub(crate) async fn run(args: SplitArgs) -> Result<()> {
+    tokio::task::spawn_blocking(move || split(&args)).await?
+}
+
+/// Replaces complete daily files with hourly files named
+/// `{symbol}-{interval}-{day}T{HH}.{ext}`, `HH` counting from the day's
+/// start, empty hours included. The
+/// manifest swaps the daily entry for the hourly ones in a single rewrite.
+fn split(args: &SplitArgs) -> Result<()> {
+    let SplitBy::Hour = args.by;
+    let mut manifest = Manifest::load(&args.dir)?;
+    let min_bytes = args.larger_than_mb.map_or(0, |mb| mb << 20);
+    let days: Vec<(String, FileEntry)> = manifest
+        .files()
+        .filter(|(_, e)| {
+            e.last_day.is_none() && e.hour.is_none() && e.archive.is_none() && e.bytes > min_bytes
+        })
+        .map(|(key, entry)| (key.to_string(), entry.clone()))
+        .collect();
+
+    let mut files = 0;
+    for (key, entry) in &days {
+        let source = args.dir.join(key);
+        let Some(format) = Format::of(&source) else {
+            continue;
+        };
+        if !entry.complete {
+            tracing::info!(
+                "{:?} doesn't cover the whole day yet, not splitting",
+                source
+            );
+            continue;
+        }
+        let rows = format
+            .read(&source, manifest.style())
+            .with_context(|| format!("reading {:?}", source))?;
+        if rows.len() != entry.rows {
+            return Err(anyhow!(
+                "{:?} has {} rows, the manifest recorded {}",
+                source,
+                rows.len(),
+                entry.rows
+            )
+            .context(Failure::Validation));
+        }
+
+        let start_ms = day_start_ms(entry.day);
+        let hours = (next_day_ms(start_ms) - start_ms) / HOUR_MS;
+        let mut written = 0;
+        for hour in 0..hours as u32 {
+            let from_ms = start_ms + i64::from(hour) * HOUR_MS;
+            let hour_rows: Vec<Kline> = rows
+                .iter()
+                .filter(|r| r.open_time >= from_ms && r.open_time < from_ms + HOUR_MS)
+                .cloned()
+                .collect();
+            let path = hour_path(&source, entry, hour, format);
+            format.write(&path, &hour_rows, manifest.style(), args.durability)?;
+            written += hour_rows.len();
+            manifest.insert(
+                &path,
+                FileEntry {
+                    hour: Some(hour),
+                    rows: hour_rows.len(),
+                    bytes: std::fs::metadata(&path)?.len(),
+                    ..entry.clone()
+                },
+            );
+        }
+        if written != rows.len() {
+            return Err(anyhow!(
+                "{:?}: {} of {} rows fall outside the day",
+                source,
+                rows.len() - written,
+                rows.len()
+            )
+            .context(Failure::Validation));
+        }
+        if args.durability == Durability::Fsync {
+            sync_dir(source.parent().unwrap_or(&args.dir))?;
+        }
+        manifest.remove(key);
+        manifest.save(args.durability)?;
+        std::fs::remove_file(&source)?;
+        files += 1;
+        tracing::info!(
+            "split {:?} into {} hourly files, {} rows",
+            source,
+            hours,
+            rows.len()
+        );
+    }
+    tracing::info!("split {} file(s)", files);
+    Ok(())
+}
+
+fn hour_path(source: &Path, entry: &FileEntry, hour: u32, format: Format) -> PathBuf {
+    source.with_file_name(format!(
+        "{}-{}-{}T{:02}.{}",
+        entry.symbol,
+        entry.interval,
+        entry.day.format("%Y-%m-%d"),
+        hour,
+        format.extension()
+    ))
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::exit::Failure;
+use crate::kline::{Kline, Provenance, Source};
+use crate::market::market;
+
+/// The exchange sends an update at least every two seconds and a ping every
+/// twenty; a stream quiet for this long is dead.
+const SILENCE: Duration = Duration::from_secs(60);
+
+/// A combined-stream frame, `{"stream": ..., "data": {...}}`.
+#[derive(Deserialize)]
+struct Frame<'a> {
+    #[serde(borrow)]
+    data: Event<'a>,
+}
+
+#[derive(Deserialize)]
+struct Event<'a> {
+    #[serde(rename = "s")]
+    symbol: &'a str,
+    #[serde(rename = "k", borrow)]
+    candle: Candle<'a>,
+}
+
+#[derive(Deserialize)]
+struct Candle<'a> {
+    #[serde(rename = "t")]
+    open_time: i64,
+    #[serde(rename = "T")]
+    close_time: i64,
+    #[serde(rename = "o")]
+    open: &'a str,
+    #[serde(rename = "h")]
+    high: &'a str,
+    #[serde(rename = "l")]
+    low: &'a str,
+    #[serde(rename = "c")]
+    close: &'a str,
+    #[serde(rename = "v")]
+    volume: &'a str,
+    #[serde(rename = "q")]
+    quote_volume: &'a str,
+    #[serde(rename = "n")]
+    trades: u64,
+    #[serde(rename = "V")]
+    taker_buy_base_vol: &'a str,
+    #[serde(rename = "Q")]
+    taker_buy_quote_vol: &'a str,
+    #[serde(rename = "B")]
+    unused: &'a str,
+    /// Whether the candle has closed
+    #[serde(rename = "x")]
+    closed: bool,
+}
+
+/// Closed candles of several symbols from the kline WebSocket streams. The
+/// exchange drops every connection after a day, so callers reconnect.
+pub(crate) struct KlineStream {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl KlineStream {
+    pub(crate) async fn connect(symbols: &[String], interval: &str) -> Result<Self> {
+        let streams: Vec<String> = symbols
+            .iter()
+            .map(|s| format!("{}@kline_{}", s.to_lowercase(), interval))
+            .collect();
+        let url = format!("{}?streams={}", market().stream_url(), streams.join("/"));
+        let (ws, _) = tokio_tungstenite::connect_async(&url)
+            .await
+            .with_context(|| format!("connecting to {}", url))?;
+        tracing::info!("connected to {}", url);
+        Ok(Self { ws })
+    }
+
+    /// The next candle to close and its symbol, or None once the exchange
+    /// closed the connection. Cancel-safe.
+    pub(crate) async fn next(&mut self) -> Result<Option<(String, Kline)>> {
+        loop {
+            let message = tokio::time::timeout(SILENCE, self.ws.next())
+                .await
+                .map_err(|_| anyhow!("nothing received for {:?}", SILENCE))?;
+            // pings are answered while reading
+            let text = match message.transpose()? {
+                Some(Message::Text(text)) => text,
+                Some(Message::Close(_)) | None => return Ok(None),
+                Some(_) => continue,
+            };
+            let frame: Frame = serde_json::from_str(&text).context(Failure::Validation)?;
+            let c = frame.data.candle;
+            if !c.closed {
+                continue;
+            }
+            let mut row = Kline::new(
+                c.open_time,
+                c.close_time,
+                c.trades,
+                [
+                    c.open,
+                    c.high,
+                    c.low,
+                    c.close,
+                    c.volume,
+                    c.quote_volume,
+                    c.taker_buy_base_vol,
+                    c.taker_buy_quote_vol,
+                    c.unused,
+                ],
+            );
+            row.provenance = Some(Provenance::now(Source::Ws));
+            return Ok(Some((frame.data.symbol.to_string(), row)));
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::download::SYMBOL;
+use crate::exit::Failure;
+use crate::market::market;
+
+/// Which symbols a download covers. Names may be patterns, resolved against
+/// the exchange's listed symbols: `*` and `?` wildcards, or a regex between
+/// slashes such as `/^(BTC|ETH)USD[CT]$/`.
+#[derive(clap::Args)]
+pub(crate) struct SymbolArgs {
+    /// Symbols to download, comma-separated
+    #[arg(long, alias = "symbol", value_delimiter = ',', default_value = SYMBOL)]
+    symbols: Vec<String>,
+    /// Read the symbols from this file instead, one per line; `#` starts a
+    /// comment
+    #[arg(long, conflicts_with_all = ["symbols", "universe", "quote"])]
+    symbols_file: Option<PathBuf>,
+    /// Download the universe of this name from `--universes` instead
+    #[arg(long, conflicts_with = "symbols")]
+    universe: Option<String>,
+    /// JSON file of named universes, each a list of symbols or the top
+    /// trading pairs in a quote asset by 24h volume, resolved at startup:
+    /// `{"majors": ["BTCUSDT", "ETHUSDT"], "top50": {"top": 50, "quote": "USDT"}}`
+    #[arg(long, default_value = "universes.json")]
+    universes: PathBuf,
+    /// Download every trading pair quoted in this asset instead, e.g. `USDC`
+    #[arg(long, conflicts_with_all = ["symbols", "universe"])]
+    quote: Option<String>,
+    /// With `--quote`, only pairs that traded at least this much of the
+    /// quote asset over the last 24h, e.g. `1e6`
+    #[arg(long, requires = "quote")]
+    min_daily_volume: Option<f64>,
+    /// Leave out symbols matching any of these, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Universe {
+    List(Vec<String>),
+    Top {
+        top: usize,
+        /// Only pairs quoted in this asset; volumes in different quote
+        /// assets don't compare
+        quote: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ticker {
+    symbol: String,
+    /// Left out on coin-margined futures, which trade in contracts
+    quote_volume: Option<String>,
+    /// Contracts traded on coin-margined futures
+    volume: String,
+}
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<ListedSymbol>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListedSymbol {
+    symbol: String,
+    /// `contractStatus` on coin-margined futures
+    #[serde(alias = "contractStatus")]
+    status: String,
+    quote_asset: String,
+    /// Quote asset one contract is worth, on coin-margined futures
+    contract_size: Option<f64>,
+}
+
+impl SymbolArgs {
+    /// The symbols to plan, in the order given; a pattern's matches are
+    /// sorted by name.
+    pub(crate) async fn resolve(&self, client: &reqwest::Client) -> Result<Vec<String>> {
+        let names = match (&self.symbols_file, &self.universe, &self.quote) {
+            (Some(path), _, _) => symbols_file(path)?,
+            (None, Some(name), _) => {
+                let names = self.universe(client, name).await?;
+                tracing::info!("universe {}: {}", name, names.join(","));
+                names
+            }
+            (None, None, Some(quote)) => {
+                let min = self.min_daily_volume.unwrap_or(0.0);
+                let names: Vec<String> = by_volume(client, quote)
+                    .await?
+                    .into_iter()
+                    .filter(|(_, volume)| *volume >= min)
+                    .map(|(symbol, _)| symbol)
+                    .collect();
+                tracing::info!(
+                    "{} pair(s) quoted in {} with 24h volume >= {}",
+                    names.len(),
+                    quote,
+                    min
+                );
+                names
+            }
+            (None, None, None) => self.symbols.clone(),
+        };
+        let patterns = names
+            .iter()
+            .map(|name| Pattern::parse(name))
+            .collect::<Result<Vec<_>>>()?;
+        let exclude = self
+            .exclude
+            .iter()
+            .map(|name| Pattern::parse(name))
+            .collect::<Result<Vec<_>>>()?;
+        if patterns.iter().all(|p| matches!(p, Pattern::Exact(_))) && exclude.is_empty() {
+            return Ok(names);
+        }
+
+        let listed = listed_symbols(client).await?;
+        let mut symbols: Vec<String> = Vec::new();
+        for pattern in &patterns {
+            let matched = match pattern {
+                Pattern::Exact(name) => vec![name.clone()],
+                Pattern::Regex(re) => listed.iter().filter(|s| re.is_match(s)).cloned().collect(),
+            };
+            if matched.is_empty() {
+                tracing::warn!("{} matches no listed symbol", pattern);
+            }
+            for symbol in matched {
+                if !symbols.contains(&symbol) && !exclude.iter().any(|p| p.matches(&symbol)) {
+                    symbols.push(symbol);
+                }
+            }
+        }
+        tracing::info!("{} symbol(s): {}", symbols.len(), symbols.join(","));
+        Ok(symbols)
+    }
+
+    /// The selection as given on the command line, kept in the manifest next
+    /// to what it resolved to.
+    pub(crate) fn query(&self) -> String {
+        let mut query = match (&self.symbols_file, &self.universe, &self.quote) {
+            (Some(path), _, _) => format!("--symbols-file {}", path.display()),
+            (None, Some(name), _) => format!("--universe {}", name),
+            (None, None, Some(quote)) => match self.min_daily_volume {
+                Some(min) => format!("--quote {} --min-daily-volume {}", quote, min),
+                None => format!("--quote {}", quote),
+            },
+            (None, None, None) => format!("--symbols {}", self.symbols.join(",")),
+        };
+        if !self.exclude.is_empty() {
+            query.push_str(&format!(" --exclude {}", self.exclude.join(",")));
+        }
+        query
+    }
+
+    async fn universe(&self, client: &reqwest::Client, name: &str) -> Result<Vec<String>> {
+        let data = std::fs::read(&self.universes)
+            .with_context(|| format!("reading {:?}", self.universes))
+            .context(Failure::Config)?;
+        let mut universes: BTreeMap<String, Universe> = serde_json::from_slice(&data)
+            .with_context(|| format!("parsing {:?}", self.universes))
+            .context(Failure::Config)?;
+        let universe = universes
+            .remove(name)
+            .ok_or_else(|| anyhow!("no universe {:?} in {:?}", name, self.universes))
+            .context(Failure::Config)?;
+        match universe {
+            Universe::List(symbols) => Ok(symbols),
+            Universe::Top { top, quote } => Ok(by_volume(client, &quote)
+                .await?
+                .into_iter()
+                .take(top)
+                .map(|(symbol, _)| symbol)
+                .collect()),
+        }
+    }
+}
+
+/// Symbols listed one per line.
+fn symbols_file(path: &Path) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {:?}", path))
+        .context(Failure::Config)?;
+    let symbols: Vec<String> = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    if symbols.is_empty() {
+        return Err(anyhow!("no symbols in {:?}", path).context(Failure::Config));
+    }
+    Ok(symbols)
+}
+
+enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn parse(s: &str) -> Result<Self> {
+        let re = if let Some(re) = s.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+            re.to_string()
+        } else if s.contains(['*', '?']) {
+            let glob = regex::escape(s).replace(r"\*", ".*").replace(r"\?", ".");
+            format!("^{}$", glob)
+        } else {
+            return Ok(Pattern::Exact(s.to_string()));
+        };
+        let re = Regex::new(&re)
+            .with_context(|| format!("invalid symbol pattern {:?}", s))
+            .context(Failure::Config)?;
+        Ok(Pattern::Regex(re))
+    }
+
+    fn matches(&self, symbol: &str) -> bool {
+        match self {
+            Pattern::Exact(name) => name == symbol,
+            Pattern::Regex(re) => re.is_match(symbol),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Exact(name) => f.write_str(name),
+            Pattern::Regex(re) => write!(f, "/{}/", re),
+        }
+    }
+}
+
+/// Every symbol `exchangeInfo` lists, sorted by name.
+async fn listed_symbols(client: &reqwest::Client) -> Result<Vec<String>> {
+    let mut symbols: Vec<String> = exchange_info(client)
+        .await?
+        .symbols
+        .into_iter()
+        .map(|s| s.symbol)
+        .collect();
+    symbols.sort_unstable();
+    Ok(symbols)
+}
+
+async fn exchange_info(client: &reqwest::Client) -> Result<ExchangeInfo> {
+    let body = get(client, "/exchangeInfo").await?;
+    serde_json::from_slice(&body).context(Failure::Validation)
+}
+
+/// Trading pairs quoted in `quote` with their 24h quote volume, highest
+/// first.
+async fn by_volume(client: &reqwest::Client, quote: &str) -> Result<Vec<(String, f64)>> {
+    let info = exchange_info(client).await?;
+    let body = get(client, "/ticker/24hr").await?;
+    let tickers: Vec<Ticker> = serde_json::from_slice(&body).context(Failure::Validation)?;
+    Ok(rank(info, tickers, quote))
+}
+
+/// See [`by_volume`]. Coin-margined futures report contracts, not quote
+/// volume, so theirs is the contracts times what one is worth.
+fn rank(info: ExchangeInfo, tickers: Vec<Ticker>, quote: &str) -> Vec<(String, f64)> {
+    let quoted: HashMap<String, Option<f64>> = info
+        .symbols
+        .into_iter()
+        .filter(|s| s.status == "TRADING" && s.quote_asset == quote)
+        .map(|s| (s.symbol, s.contract_size))
+        .collect();
+    let mut ranked: Vec<(String, f64)> = tickers
+        .into_iter()
+        .filter_map(|t| {
+            let contract_size = *quoted.get(&t.symbol)?;
+            let volume = match (&t.quote_volume, contract_size) {
+                (Some(volume), _) => volume.parse().unwrap_or(0.0),
+                (None, Some(size)) => t.volume.parse().unwrap_or(0.0) * size,
+                (None, None) => 0.0,
+            };
+            Some((t.symbol, volume))
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+async fn get(client: &reqwest::Client, path: &str) -> Result<bytes::Bytes> {
+    let url = format!("{}{}", market().api(), path);
+    Ok(client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked(info: &str, tickers: &str, quote: &str) -> Vec<(String, f64)> {
+        rank(
+            serde_json::from_str(info).unwrap(),
+            serde_json::from_str(tickers).unwrap(),
+            quote,
+        )
+    }
+
+    #[test]
+    fn spot_pairs_rank_by_quote_volume() {
+        let info = r#"{"symbols": [
+            {"symbol": "BTCUSDT", "status": "TRADING", "quoteAsset": "USDT"},
+            {"symbol": "ETHUSDT", "status": "TRADING", "quoteAsset": "USDT"},
+            {"symbol": "ETHBTC", "status": "TRADING", "quoteAsset": "BTC"}
+        ]}"#;
+        let tickers = r#"[
+            {"symbol": "BTCUSDT", "volume": "10", "quoteVolume": "600000"},
+            {"symbol": "ETHUSDT", "volume": "1000", "quoteVolume": "3500000"},
+            {"symbol": "ETHBTC", "volume": "9999", "quoteVolume": "500"}
+        ]"#;
+        assert_eq!(
+            ranked(info, tickers, "USDT"),
+            [
+                ("ETHUSDT".to_string(), 3_500_000.0),
+                ("BTCUSDT".to_string(), 600_000.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn coin_margined_contracts_rank_by_their_worth() {
+        // as `/dapi/v1/exchangeInfo` and `/dapi/v1/ticker/24hr` have them
+        let info = r#"{"symbols": [
+            {"symbol": "BTCUSD_PERP", "contractStatus": "TRADING", "quoteAsset": "USD", "contractSize": 100},
+            {"symbol": "ETHUSD_PERP", "contractStatus": "TRADING", "quoteAsset": "USD", "contractSize": 10},
+            {"symbol": "LTCUSD_PERP", "contractStatus": "SETTLING", "quoteAsset": "USD", "contractSize": 10}
+        ]}"#;
+        let tickers = r#"[
+            {"symbol": "BTCUSD_PERP", "volume": "20000", "baseVolume": "33.3"},
+            {"symbol": "ETHUSD_PERP", "volume": "500000", "baseVolume": "1428.5"},
+            {"symbol": "LTCUSD_PERP", "volume": "900000", "baseVolume": "90000"}
+        ]"#;
+        assert_eq!(
+            ranked(info, tickers, "USD"),
+            [
+                ("ETHUSD_PERP".to_string(), 5_000_000.0),
+                ("BTCUSD_PERP".to_string(), 2_000_000.0)
+            ]
+        );
+    }
+}
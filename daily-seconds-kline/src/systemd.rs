@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+
+use crate::health::Health;
+
+/// Sends `READY=1` for `Type=notify` units and, when `WatchdogSec=` is set,
+/// pings the watchdog at half its interval, but only while the download loop
+/// keeps making progress, so a hung loop gets the service restarted.
+/// Does nothing outside systemd.
+pub(crate) fn start(health: &Health) {
+    notify(&[NotifyState::Ready]);
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return;
+    }
+    let timeout = Duration::from_micros(usec);
+    tracing::info!("systemd watchdog every {:?}", timeout);
+    let health = health.clone();
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(timeout / 2);
+        loop {
+            ticks.tick().await;
+            if health.since_progress() < timeout {
+                notify(&[NotifyState::Watchdog]);
+            } else {
+                tracing::warn!(
+                    "no progress for {:?}, leaving the watchdog to expire",
+                    health.since_progress()
+                );
+            }
+        }
+    });
+}
+
+pub(crate) fn stopping() {
+    notify(&[NotifyState::Stopping]);
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        tracing::debug!("sd_notify failed: {}", e);
+    }
+}
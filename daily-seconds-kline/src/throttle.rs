@@ -0,0 +1,46 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A token bucket shared by every download, limiting their combined rate to
+/// `bytes_per_sec` with up to one second of burst.
+#[derive(Clone)]
+pub(crate) struct Throttle(Option<Arc<Mutex<Bucket>>>);
+
+struct Bucket {
+    rate: f64,
+    /// Negative while callers are waiting off a debt
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self(bytes_per_sec.map(|rate| {
+            Arc::new(Mutex::new(Bucket {
+                rate: rate as f64,
+                tokens: rate as f64,
+                refilled: Instant::now(),
+            }))
+        }))
+    }
+
+    /// Accounts for `bytes` already received and waits until they fit the
+    /// rate.
+    pub(crate) async fn consume(&self, bytes: usize) {
+        let Some(bucket) = &self.0 else {
+            return;
+        };
+        let wait = {
+            let mut bucket = bucket.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(bucket.rate);
+            bucket.refilled = now;
+            bucket.tokens -= bytes as f64;
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / bucket.rate))
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
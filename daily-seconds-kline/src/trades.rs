@@ -0,0 +1,331 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{NaiveDate, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::day::{day_start_ms, next_day_ms};
+use crate::download::Fetcher;
+use crate::exit::Failure;
+use crate::manifest::{FileEntry, Manifest};
+use crate::market::market;
+use crate::queue::Job;
+use crate::report;
+use crate::writer::{self, Durability};
+
+/// Most trades one request returns.
+const PAGE_ROWS: u32 = 1000;
+/// Longest span `aggTrades` accepts between `startTime` and `endTime`.
+const SEARCH_MS: i64 = 3_600_000;
+
+/// What a dataset holds. Trades have no interval; their name takes its
+/// place in file names, as in `ETHUSDC-aggTrades-2024-06-01.csv`.
+#[derive(
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum DataType {
+    /// Candles from `/api/v3/klines`
+    #[default]
+    Klines,
+    /// Trades aggregated by price and taker from `/api/v3/aggTrades`
+    #[value(name = "aggTrades")]
+    AggTrades,
+    /// Every trade from `/api/v3/historicalTrades`
+    Trades,
+}
+
+impl DataType {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            DataType::Klines => "klines",
+            DataType::AggTrades => "aggTrades",
+            DataType::Trades => "trades",
+        }
+    }
+}
+
+/// A trade as an endpoint returns it, paged by id with `fromId`.
+trait Tick: DeserializeOwned + Serialize + Send + 'static {
+    const PATH: &'static str;
+
+    fn id(&self) -> u64;
+
+    fn time(&self) -> i64;
+
+    /// Id of the first of these in the day that begins with `first`.
+    fn first_id(first: &AggTrade) -> u64;
+}
+
+/// An aggregate trade, written in the exchange's column order.
+#[derive(Deserialize, Serialize)]
+struct AggTrade {
+    #[serde(rename = "a")]
+    id: u64,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "q")]
+    quantity: String,
+    #[serde(rename = "f")]
+    first_trade_id: u64,
+    #[serde(rename = "l")]
+    last_trade_id: u64,
+    #[serde(rename = "T")]
+    time: i64,
+    #[serde(rename = "m")]
+    is_buyer_maker: bool,
+    #[serde(rename = "M")]
+    is_best_match: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trade {
+    id: u64,
+    price: String,
+    qty: String,
+    quote_qty: String,
+    time: i64,
+    is_buyer_maker: bool,
+    is_best_match: bool,
+}
+
+impl Tick for AggTrade {
+    const PATH: &'static str = "/aggTrades";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    fn first_id(first: &AggTrade) -> u64 {
+        first.id
+    }
+}
+
+impl Tick for Trade {
+    const PATH: &'static str = "/historicalTrades";
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn time(&self) -> i64 {
+        self.time
+    }
+
+    fn first_id(first: &AggTrade) -> u64 {
+        first.first_trade_id
+    }
+}
+
+/// Downloads the trades of every finished day from `from` to `to` into
+/// daily files, one day after another; days the manifest has complete are
+/// skipped.
+pub(crate) async fn download(
+    fetcher: &Fetcher,
+    mut manifest: Manifest,
+    symbols: &[String],
+    data: DataType,
+    from: NaiveDate,
+    to: NaiveDate,
+    durability: Durability,
+) -> Result<()> {
+    let now_ms = Utc::now().timestamp_millis();
+    let mut done = 0;
+    let mut failures = 0;
+    let mut failure = None;
+    for symbol in symbols {
+        for day in from.iter_days().take_while(|day| *day <= to) {
+            let job = Job {
+                symbol: symbol.clone(),
+                interval: data.name().to_string(),
+                day,
+            };
+            let start_ms = day_start_ms(day);
+            let end_ms = next_day_ms(start_ms) - 1;
+            if manifest.is_complete(&job) {
+                continue;
+            }
+            if end_ms >= now_ms {
+                tracing::info!("{} {} {} isn't over yet", job.symbol, job.interval, day);
+                continue;
+            }
+            let fetched = match data {
+                DataType::AggTrades => {
+                    download_day::<AggTrade>(fetcher, &mut manifest, &job, durability).await?
+                }
+                DataType::Trades => {
+                    download_day::<Trade>(fetcher, &mut manifest, &job, durability).await?
+                }
+                DataType::Klines => unreachable!("klines are downloaded in windows"),
+            };
+            match fetched {
+                Ok(()) => done += 1,
+                Err(e) => {
+                    tracing::warn!("{} {} {} failed: {:#}", job.symbol, job.interval, day, e);
+                    report::job_failed(&job, start_ms, end_ms, &e);
+                    failures += 1;
+                    if let Some(class) = Failure::of(&e) {
+                        failure = Some(failure.map_or(class, |f: Failure| f.min(class)));
+                    }
+                }
+            }
+        }
+    }
+    tracing::info!("{} day(s) of {} done", done, data.name());
+    if failures > 0 {
+        return Err(
+            anyhow!("{} day(s) failed, run again to retry them", failures)
+                .context(failure.unwrap_or(Failure::Partial)),
+        );
+    }
+    Ok(())
+}
+
+/// Fetches a day and writes it. A failed fetch is the inner error, leaving
+/// the day for a later run; a failed write is the outer one.
+async fn download_day<T: Tick>(
+    fetcher: &Fetcher,
+    manifest: &mut Manifest,
+    job: &Job,
+    durability: Durability,
+) -> Result<Result<()>> {
+    let rows = match fetch_day::<T>(fetcher, job).await {
+        Ok(rows) => rows,
+        Err(e) => return Ok(Err(e)),
+    };
+    let mut owned = std::mem::take(manifest);
+    let job = job.clone();
+    let progress = fetcher.progress().clone();
+    *manifest = tokio::task::spawn_blocking(move || {
+        let bytes = write_day(&mut owned, &job, &rows, durability)?;
+        progress.written(&job, rows.len(), bytes, true);
+        anyhow::Ok(owned)
+    })
+    .await??;
+    Ok(Ok(()))
+}
+
+/// Writes a day's trades like `download` writes klines and records them.
+/// Returns the size of the file.
+fn write_day<T: Tick>(
+    manifest: &mut Manifest,
+    job: &Job,
+    rows: &[T],
+    durability: Durability,
+) -> Result<u64> {
+    let format = manifest.format();
+    let parent = manifest
+        .dir()
+        .join(manifest.layout().dir(&job.symbol, &job.interval, job.day));
+    std::fs::create_dir_all(&parent)?;
+    let name = writer::file_name(job, format);
+    let path = parent.join(&name);
+    let tmp = parent.join(format!("{}.tmp", name));
+    format.write_records(&tmp, rows, manifest.style(), durability)?;
+    std::fs::rename(&tmp, &path)?;
+    if durability == Durability::Fsync {
+        writer::sync_dir(&parent)?;
+    }
+    tracing::info!("{} rows, file path: {:?}", rows.len(), path);
+    let bytes = std::fs::metadata(&path)?.len();
+    manifest.record(
+        &path,
+        FileEntry {
+            symbol: job.symbol.clone(),
+            interval: job.interval.clone(),
+            day: job.day,
+            last_day: None,
+            hour: None,
+            resampled_from: None,
+            aggregated_from: None,
+            archive: None,
+            rows: rows.len(),
+            bytes,
+            complete: true,
+        },
+        durability,
+    )?;
+    Ok(bytes)
+}
+
+/// The trades of a day in id order. Ids only grow, so paging from the
+/// day's first trade ends at the first one past the day.
+async fn fetch_day<T: Tick>(fetcher: &Fetcher, job: &Job) -> Result<Vec<T>> {
+    let start_ms = day_start_ms(job.day);
+    let end_ms = next_day_ms(start_ms) - 1;
+    let Some(first) = first_agg_trade(fetcher, &job.symbol, start_ms, end_ms).await? else {
+        return Ok(Vec::new());
+    };
+    let mut from_id = T::first_id(&first);
+    let mut rows = Vec::new();
+    loop {
+        let url = format!(
+            "{}{}?symbol={}&fromId={}&limit={}",
+            market().api(),
+            T::PATH,
+            job.symbol,
+            from_id,
+            PAGE_ROWS
+        );
+        let page: Vec<T> =
+            serde_json::from_slice(&fetcher.get(&url).await?).context(Failure::Validation)?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        from_id = last.id() + 1;
+        let past = last.time() > end_ms;
+        rows.extend(page.into_iter().filter(|t| t.time() <= end_ms));
+        if past {
+            break;
+        }
+    }
+    tracing::info!(
+        "{} {} {}: {} rows",
+        job.symbol,
+        job.interval,
+        job.day,
+        rows.len()
+    );
+    Ok(rows)
+}
+
+/// The first aggregate trade in `[start_ms, end_ms]`, looked for an hour at
+/// a time, the longest span `aggTrades` takes by time.
+async fn first_agg_trade(
+    fetcher: &Fetcher,
+    symbol: &str,
+    start_ms: i64,
+    end_ms: i64,
+) -> Result<Option<AggTrade>> {
+    let mut from_ms = start_ms;
+    while from_ms <= end_ms {
+        let to_ms = (from_ms + SEARCH_MS - 1).min(end_ms);
+        let url = format!(
+            "{}{}?symbol={}&startTime={}&endTime={}&limit=1",
+            market().api(),
+            AggTrade::PATH,
+            symbol,
+            from_ms,
+            to_ms
+        );
+        let page: Vec<AggTrade> =
+            serde_json::from_slice(&fetcher.get(&url).await?).context(Failure::Validation)?;
+        if let Some(first) = page.into_iter().next() {
+            return Ok(Some(first));
+        }
+        from_ms = to_ms + 1;
+    }
+    Ok(None)
+}
@@ -0,0 +1,80 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::exit::Failure;
+use crate::kline::{Kline, KlineRecord};
+use crate::market::Market;
+use crate::queue::Job;
+
+/// Open times at or past this are microseconds, as in spot files from 2025
+/// on; milliseconds stay below it until the year 5138.
+const MICROS_FROM: i64 = 100_000_000_000_000;
+
+/// The daily archive of a job's day; its checksum is at the same URL with
+/// `.CHECKSUM` appended.
+pub(crate) fn day_url(market: Market, job: &Job) -> String {
+    format!(
+        "{}/klines/{}/{}/{}-{}-{}.zip",
+        market.vision_url(),
+        job.symbol,
+        job.interval,
+        job.symbol,
+        job.interval,
+        job.day.format("%Y-%m-%d")
+    )
+}
+
+/// Checks `data` against a `.CHECKSUM` file, `{sha256}  {file name}`.
+pub(crate) fn check_sha256(data: &[u8], checksum: &str) -> Result<()> {
+    let expected = checksum.split_whitespace().next().unwrap_or_default();
+    let actual = hex::encode(Sha256::digest(data));
+    if !expected.eq_ignore_ascii_case(&actual) {
+        return Err(
+            anyhow!("sha256 {} doesn't match {}", actual, expected).context(Failure::Validation)
+        );
+    }
+    Ok(())
+}
+
+/// Rows of a daily kline archive from data.binance.vision, a zip holding a
+/// single CSV.
+pub(crate) fn read_zip(data: &[u8]) -> Result<Vec<Kline>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).context(Failure::Validation)?;
+    if archive.len() != 1 {
+        return Err(
+            anyhow!("expected one file in the zip, found {}", archive.len())
+                .context(Failure::Validation),
+        );
+    }
+    let mut csv = Vec::new();
+    archive
+        .by_index(0)
+        .context(Failure::Validation)?
+        .read_to_end(&mut csv)
+        .context(Failure::Validation)?;
+    read_csv(&csv)
+}
+
+/// Rows of a Vision kline CSV. Some files have a header row and newer ones
+/// count in microseconds; rows come back without either.
+pub(crate) fn read_csv(data: &[u8]) -> Result<Vec<Kline>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(data);
+    let mut rows = Vec::new();
+    for record in rdr.records() {
+        let record = record.context(Failure::Validation)?;
+        if rows.is_empty() && record.get(0).is_some_and(|f| f.parse::<i64>().is_err()) {
+            continue;
+        }
+        let mut row: KlineRecord = record.deserialize(None).context(Failure::Validation)?;
+        if row.open_time >= MICROS_FROM {
+            row.open_time /= 1000;
+            row.close_time /= 1000;
+        }
+        rows.push(Kline::from(&row));
+    }
+    Ok(rows)
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDate;
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::day::{day_start_ms, next_day_ms};
+use crate::download::{interval_ms, OUT_DIR};
+use crate::exit::Failure;
+use crate::format::{Format, StyleArgs};
+use crate::kline::{self, Source};
+use crate::layout::LayoutArgs;
+use crate::manifest::{FileEntry, Manifest};
+use crate::queue::Job;
+use crate::vision;
+use crate::writer::{self, DayBatch, Durability};
+
+#[derive(clap::Args)]
+pub(crate) struct WatchArgs {
+    /// Directory other systems drop daily files into, named
+    /// `{symbol}-{interval}-{yyyy-mm-dd}` with a `.zip` from
+    /// data.binance.vision or a `.csv`
+    dir: PathBuf,
+    /// Dataset the files are added to
+    #[arg(long, default_value = OUT_DIR)]
+    out: PathBuf,
+    /// Seconds a file's size must stay the same before it counts as fully
+    /// written
+    #[arg(long, default_value_t = 2)]
+    settle_secs: u64,
+    #[arg(long, value_enum, default_value_t)]
+    durability: Durability,
+    /// Format of the dataset's files; an existing dataset keeps its own
+    #[arg(long, value_enum)]
+    output_format: Option<Format>,
+    #[command(flatten)]
+    layout: LayoutArgs,
+    #[command(flatten)]
+    style: StyleArgs,
+}
+
+/// A dropped file, recognized by its name.
+struct Input {
+    symbol: String,
+    interval: String,
+    day: NaiveDate,
+    zip: bool,
+}
+
+impl Input {
+    fn parse(path: &Path) -> Option<Self> {
+        let zip = match path.extension()?.to_str()? {
+            "zip" => true,
+            "csv" => false,
+            _ => return None,
+        };
+        let stem = path.file_stem()?.to_str()?;
+        // the day is the last ten characters
+        let split = stem.len().checked_sub(10)?;
+        let (name, day) = (stem.get(..split)?, stem.get(split..)?);
+        let (symbol, interval) = name.strip_suffix('-')?.rsplit_once('-')?;
+        Some(Self {
+            symbol: symbol.to_string(),
+            interval: interval.to_string(),
+            day: NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()?,
+            zip,
+        })
+    }
+}
+
+/// Adds files dropped into `dir` to the dataset as they arrive, and those
+/// already there at startup. Each is checked, rewritten in the dataset's
+/// format and recorded in its manifest, then renamed with a `.done`
+/// suffix, or `.rejected` if it fails validation. Runs until stopped.
+pub(crate) async fn run(args: WatchArgs) -> Result<()> {
+    std::fs::create_dir_all(&args.out)?;
+    let mut manifest = Manifest::load(&args.out)?;
+    manifest.set_layout(args.layout.layout())?;
+    manifest.set_format(args.output_format)?;
+    manifest.set_style(args.style.apply(manifest.style())?)?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let _ = tx.send(event);
+    })
+    .context(Failure::Config)?;
+    watcher
+        .watch(&args.dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("watching {:?}", args.dir))
+        .context(Failure::Config)?;
+    tracing::info!("watching {:?} for files to add to {:?}", args.dir, args.out);
+
+    // path -> size when last seen and since when, once looked at
+    let mut pending: BTreeMap<PathBuf, Option<(u64, Instant)>> = BTreeMap::new();
+    for entry in std::fs::read_dir(&args.dir)? {
+        let path = entry?.path();
+        if Input::parse(&path).is_some() {
+            pending.insert(path, None);
+        }
+    }
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let event = event.ok_or_else(|| anyhow!("file watcher stopped"))??;
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    for path in event.paths {
+                        if Input::parse(&path).is_some() {
+                            pending.insert(path, None);
+                        }
+                    }
+                }
+            }
+            _ = tick.tick() => {
+                for path in settled(&mut pending, Duration::from_secs(args.settle_secs)) {
+                    let durability = args.durability;
+                    manifest = tokio::task::spawn_blocking(move || {
+                        process(&mut manifest, &path, durability)?;
+                        anyhow::Ok(manifest)
+                    })
+                    .await??;
+                }
+            }
+        }
+    }
+}
+
+/// Takes the pending files whose size hasn't changed for `settle`.
+fn settled(
+    pending: &mut BTreeMap<PathBuf, Option<(u64, Instant)>>,
+    settle: Duration,
+) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    pending.retain(|path, seen| {
+        // gone: renamed away or deleted by whoever dropped it
+        let Ok(meta) = std::fs::metadata(path) else {
+            return false;
+        };
+        match *seen {
+            Some((len, since)) if len == meta.len() => {
+                if since.elapsed() < settle {
+                    return true;
+                }
+                ready.push(path.clone());
+                false
+            }
+            _ => {
+                *seen = Some((meta.len(), Instant::now()));
+                true
+            }
+        }
+    });
+    ready
+}
+
+/// Adds one file, then renames it out of the way. Only validation failures
+/// are the file's fault; anything else stops the watch.
+fn process(manifest: &mut Manifest, path: &Path, durability: Durability) -> Result<()> {
+    let suffix = match ingest(manifest, path, durability) {
+        Ok(()) => "done",
+        Err(e) if Failure::of(&e) == Some(Failure::Validation) => {
+            tracing::warn!("rejected {:?}: {:#}", path, e);
+            "rejected"
+        }
+        Err(e) => return Err(e.context(format!("adding {:?}", path))),
+    };
+    let mut renamed = path.as_os_str().to_owned();
+    renamed.push(".");
+    renamed.push(suffix);
+    std::fs::rename(path, &renamed).with_context(|| format!("renaming {:?}", path))?;
+    Ok(())
+}
+
+fn ingest(manifest: &mut Manifest, path: &Path, durability: Durability) -> Result<()> {
+    let input = Input::parse(path).expect("only recognized files are pending");
+    let step_ms = interval_ms(&input.interval)
+        .ok_or_else(|| anyhow!("unknown interval {:?}", input.interval))
+        .context(Failure::Validation)?;
+    let data = std::fs::read(path)?;
+    let mut rows = if input.zip {
+        verify_checksum(path, &data)?;
+        vision::read_zip(&data)?
+    } else {
+        vision::read_csv(&data)?
+    };
+    if input.zip {
+        kline::stamp(&mut rows, Source::Vision);
+    }
+
+    let (start_ms, end_ms) = (
+        day_start_ms(input.day),
+        next_day_ms(day_start_ms(input.day)),
+    );
+    let mut last_ms = None;
+    for row in &rows {
+        if !(start_ms..end_ms).contains(&row.open_time) {
+            return Err(anyhow!("row at {} is outside {}", row.open_time, input.day)
+                .context(Failure::Validation));
+        }
+        if row.open_time % step_ms != 0 || last_ms.is_some_and(|last| row.open_time <= last) {
+            return Err(anyhow!(
+                "row at {} is out of order or off the {} grid",
+                row.open_time,
+                input.interval
+            )
+            .context(Failure::Validation));
+        }
+        last_ms = Some(row.open_time);
+    }
+    if rows.is_empty() {
+        return Err(anyhow!("no rows").context(Failure::Validation));
+    }
+
+    let job = Job {
+        symbol: input.symbol,
+        interval: input.interval,
+        day: input.day,
+    };
+    if manifest.is_complete(&job) {
+        tracing::info!(
+            "{:?}: {} {} {} is already in the dataset",
+            path,
+            job.symbol,
+            job.interval,
+            job.day
+        );
+        return Ok(());
+    }
+    let batch = DayBatch {
+        job,
+        rows,
+        complete: true,
+    };
+    let written = writer::write_file(
+        manifest.dir(),
+        manifest.layout(),
+        manifest.style(),
+        manifest.format(),
+        &batch,
+        durability,
+    )?;
+    manifest.record(
+        &written,
+        FileEntry {
+            symbol: batch.job.symbol.clone(),
+            interval: batch.job.interval.clone(),
+            day: batch.job.day,
+            last_day: None,
+            hour: None,
+            resampled_from: None,
+            aggregated_from: None,
+            archive: None,
+            rows: batch.rows.len(),
+            bytes: std::fs::metadata(&written)?.len(),
+            complete: true,
+        },
+        durability,
+    )?;
+    tracing::info!(
+        "added {:?} as {:?}, {} rows",
+        path,
+        written,
+        batch.rows.len()
+    );
+    Ok(())
+}
+
+/// Checks a zip against the `.CHECKSUM` file Vision publishes next to it,
+/// if one was dropped too.
+fn verify_checksum(path: &Path, data: &[u8]) -> Result<()> {
+    let mut checksum = path.as_os_str().to_owned();
+    checksum.push(".CHECKSUM");
+    match std::fs::read_to_string(&checksum) {
+        Ok(text) => {
+            vision::check_sha256(data, &text).with_context(|| format!("checking {:?}", checksum))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::disk::{self, SpaceGuard};
+use crate::format::{Format, Style};
+use crate::health::Health;
+use crate::kline::Kline;
+use crate::layout::Layout;
+use crate::manifest::{FileEntry, Manifest};
+use crate::progress::Progress;
+use crate::prune::Retention;
+use crate::queue::{Job, Queue};
+use crate::sink::Sink;
+
+const PENDING_DAYS: usize = 2;
+
+/// How hard to make sure a file has reached the disk before it is recorded
+/// as done in the manifest and the queue.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Durability {
+    /// Leave writing back to the OS, write errors may go unnoticed
+    None,
+    /// Flush buffers and check for write errors; survives a process crash
+    #[default]
+    Flush,
+    /// Also fsync the file and its directory; survives power loss
+    Fsync,
+}
+
+/// One day of klines, as `--day-start` cuts days, ready to be written.
+pub(crate) struct DayBatch {
+    pub(crate) job: Job,
+    pub(crate) rows: Vec<Kline>,
+    /// Whether the whole day was fetched; only then is the job marked done
+    /// once the file is on disk.
+    pub(crate) complete: bool,
+}
+
+/// Where a [`DailyWriter`] puts days.
+pub(crate) enum Target {
+    /// Files in the manifest's directory, written while there is space and
+    /// pruned as `retention` asks
+    Files {
+        manifest: Manifest,
+        space: SpaceGuard,
+        retention: Retention,
+    },
+    /// A database or bucket; which days are done is only kept in the queue
+    Sink(Sink),
+}
+
+/// Writes day batches on a background task so that slow disks don't stall
+/// request scheduling. File I/O itself runs on tokio's blocking pool.
+pub(crate) struct DailyWriter {
+    tx: mpsc::Sender<DayBatch>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl DailyWriter {
+    pub(crate) fn spawn(
+        queue: Queue,
+        target: Target,
+        durability: Durability,
+        health: Health,
+        progress: Progress,
+    ) -> Self {
+        let (tx, mut rx) = mpsc::channel::<DayBatch>(PENDING_DAYS);
+        let handle = tokio::spawn(async move {
+            let result =
+                write_batches(&mut rx, &queue, target, durability, &health, &progress).await;
+            if let Err(e) = &result {
+                health.sink_failed(e);
+            }
+            result
+        });
+        Self {
+            tx,
+            handle: Some(handle),
+        }
+    }
+
+    /// Queues a batch for writing. Waits only if the writer is already
+    /// `PENDING_DAYS` batches behind.
+    pub(crate) async fn write(&mut self, batch: DayBatch) -> Result<()> {
+        if self.tx.send(batch).await.is_err() {
+            // the writer task stopped, its result carries the real error
+            if let Some(handle) = self.handle.take() {
+                handle.await??;
+            }
+            return Err(anyhow!("daily writer stopped unexpectedly"));
+        }
+        Ok(())
+    }
+
+    /// Waits for all queued batches to be written.
+    pub(crate) async fn finish(self) -> Result<()> {
+        drop(self.tx);
+        match self.handle {
+            Some(handle) => handle.await?,
+            None => Ok(()),
+        }
+    }
+}
+
+async fn write_batches(
+    rx: &mut mpsc::Receiver<DayBatch>,
+    queue: &Queue,
+    mut target: Target,
+    durability: Durability,
+    health: &Health,
+    progress: &Progress,
+) -> Result<()> {
+    if let Target::Files { manifest, .. } = &target {
+        tokio::fs::create_dir_all(manifest.dir()).await?;
+    }
+    while let Some(batch) = rx.recv().await {
+        let (batch, bytes) = match &mut target {
+            Target::Files {
+                manifest,
+                space,
+                retention,
+            } => {
+                space
+                    .reserve(disk::estimate_bytes(batch.rows.len() as u64, manifest))
+                    .await?;
+                let mut owned = std::mem::take(manifest);
+                let retention = retention.clone();
+                let (batch, bytes, returned) = tokio::task::spawn_blocking(move || {
+                    let bytes = write_day(&mut owned, &batch, durability)?;
+                    if !retention.is_empty() {
+                        let dir = owned.dir().to_path_buf();
+                        retention.apply(&dir, &mut owned, durability)?;
+                    }
+                    anyhow::Ok((batch, bytes, owned))
+                })
+                .await??;
+                *manifest = returned;
+                (batch, bytes)
+            }
+            Target::Sink(sink) => {
+                let job = &batch.job;
+                sink.write(&job.symbol, &job.interval, &batch.rows)
+                    .await
+                    .with_context(|| {
+                        format!("writing {} {} {}", job.symbol, job.interval, job.day)
+                    })?;
+                (batch, 0)
+            }
+        };
+        progress.written(&batch.job, batch.rows.len(), bytes, batch.complete);
+        if batch.complete {
+            queue.mark_done(&batch.job).await?;
+        } else {
+            queue.release(&batch.job).await?;
+        }
+        health.written(&batch.job.symbol);
+    }
+    Ok(())
+}
+
+/// Writes a batch into the manifest's dataset, laid out and formatted as
+/// the dataset is, and records it. Returns the size of the file.
+pub(crate) fn write_day(
+    manifest: &mut Manifest,
+    batch: &DayBatch,
+    durability: Durability,
+) -> Result<u64> {
+    let path = write_file(
+        manifest.dir(),
+        manifest.layout(),
+        manifest.style(),
+        manifest.format(),
+        batch,
+        durability,
+    )?;
+    let bytes = std::fs::metadata(&path)?.len();
+    manifest.record(
+        &path,
+        FileEntry {
+            symbol: batch.job.symbol.clone(),
+            interval: batch.job.interval.clone(),
+            day: batch.job.day,
+            last_day: None,
+            hour: None,
+            resampled_from: None,
+            aggregated_from: None,
+            archive: None,
+            rows: batch.rows.len(),
+            bytes,
+            complete: batch.complete,
+        },
+        durability,
+    )?;
+    Ok(bytes)
+}
+
+pub(crate) fn write_file(
+    dir: &Path,
+    layout: Layout,
+    style: Style,
+    format: Format,
+    batch: &DayBatch,
+    durability: Durability,
+) -> Result<PathBuf> {
+    let job = &batch.job;
+    let parent = dir.join(layout.dir(&job.symbol, &job.interval, job.day));
+    std::fs::create_dir_all(&parent)?;
+    let path = parent.join(file_name(job, format));
+    tracing::info!("data lenth: {}, file path: {:?}", batch.rows.len(), path);
+    // a crash mid-write leaves only the temporary file, never a short one
+    let tmp = parent.join(format!("{}.tmp", file_name(job, format)));
+    format.write(&tmp, &batch.rows, style, durability)?;
+    std::fs::rename(&tmp, &path)?;
+    if durability == Durability::Fsync {
+        sync_dir(&parent)?;
+    }
+    Ok(path)
+}
+
+/// `{symbol}-{interval}-{day}.{ext}`, the name of a daily file.
+pub(crate) fn file_name(job: &Job, format: Format) -> String {
+    format!(
+        "{}-{}-{}.{}",
+        job.symbol,
+        job.interval,
+        job.day.format("%Y-%m-%d"),
+        format.extension()
+    )
+}
+
+pub(crate) fn sync_dir(dir: &Path) -> Result<()> {
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}